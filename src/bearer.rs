@@ -1,25 +1,66 @@
+use crate::deserializers::u64_from_str_or_u64;
 use chrono::{DateTime, Duration, Utc};
 use serde::{de::Visitor, ser::Serializer, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 use std::fmt;
 
 /// The bearer token type.
 ///
+/// Besides the field names of the spec, the camelCase names (`accessToken`, ...) and `expires`
+/// for `expires_in` are accepted, as sent by some providers.
+///
+/// Non-exhaustive since 0.11, which added `token_type`, so further fields can be added without
+/// breaking changes. Construct it with [`Bearer::new`] or by deserializing a token response.
+///
 /// See [RFC 6750](http://tools.ietf.org/html/rfc6750).
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct Bearer {
+    #[serde(alias = "accessToken")]
     pub access_token: String,
+    /// `Bearer`, or `DPoP` for DPoP-bound tokens, if present. The case sent by the provider is
+    /// normalized.
+    #[serde(
+        default,
+        alias = "tokenType",
+        deserialize_with = "token_type_supported",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub token_type: Option<String>,
     pub scope: Option<String>,
+    #[serde(alias = "refreshToken")]
     pub refresh_token: Option<String>,
     #[serde(
         default,
         rename = "expires_in",
+        alias = "expiresIn",
+        alias = "expires",
         deserialize_with = "expire_in_to_instant",
         serialize_with = "serialize_expire_in"
     )]
     pub expires: Option<DateTime<Utc>>,
+    #[serde(alias = "idToken")]
     pub id_token: Option<String>,
 }
 
+fn token_type_supported<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let token_type: Option<String> = Deserialize::deserialize(deserializer)?;
+    match token_type {
+        Some(token_type) if token_type.eq_ignore_ascii_case("bearer") => {
+            Ok(Some("Bearer".to_string()))
+        }
+        Some(token_type) if token_type.eq_ignore_ascii_case("dpop") => Ok(Some("DPoP".to_string())),
+        Some(token_type) => Err(serde::de::Error::custom(format!(
+            "Unsupported token_type: {}",
+            token_type
+        ))),
+        None => Ok(None),
+    }
+}
+
 fn expire_in_to_instant<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
 where
     D: Deserializer<'de>,
@@ -30,7 +71,7 @@ where
         type Value = Option<DateTime<Utc>>;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("an integer containing seconds")
+            formatter.write_str("an integer or a string containing seconds")
         }
 
         fn visit_none<E>(self) -> Result<Self::Value, E>
@@ -44,7 +85,7 @@ where
         where
             D: Deserializer<'de>,
         {
-            let expire_in: u64 = serde::de::Deserialize::deserialize(d)?;
+            let expire_in = u64_from_str_or_u64(d)?;
             Ok(Some(Utc::now() + Duration::seconds(expire_in as i64)))
         }
     }
@@ -63,18 +104,70 @@ fn serialize_expire_in<S: Serializer>(
 }
 
 impl Bearer {
+    /// A bearer with just the access token, e.g. one received from a gateway.
+    pub fn new(access_token: impl Into<String>) -> Self {
+        Self {
+            access_token: access_token.into(),
+            token_type: None,
+            scope: None,
+            refresh_token: None,
+            expires: None,
+            id_token: None,
+        }
+    }
+
+    /// Parses a token response, computing the expiry relative to `received_at` instead of the
+    /// current time.
+    pub fn from_response(
+        json: Value,
+        received_at: DateTime<Utc>,
+    ) -> Result<Self, serde_json::Error> {
+        let expires_in = ["expires_in", "expiresIn", "expires"]
+            .iter()
+            .find_map(|name| json.get(name))
+            .filter(|expires_in| !expires_in.is_null())
+            .map(u64_from_str_or_u64)
+            .transpose()?;
+        let mut bearer: Bearer = serde_json::from_value(json)?;
+        bearer.expires = expires_in.map(|seconds| received_at + Duration::seconds(seconds as i64));
+        Ok(bearer)
+    }
+
     pub fn expired(&self) -> bool {
+        self.expired_at(Utc::now())
+    }
+
+    /// Checks if the token is expired at the given point in time.
+    pub fn expired_at(&self, now: DateTime<Utc>) -> bool {
         if let Some(expires) = self.expires {
-            expires < Utc::now()
+            expires < now
         } else {
             false
         }
     }
+
+    /// The granted scopes. Besides the space separated scope of the spec, comma separated scopes
+    /// as sent by some providers are accepted.
+    pub fn scopes(&self) -> Vec<&str> {
+        self.scope
+            .as_deref()
+            .unwrap_or_default()
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|scope| !scope.is_empty())
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn new_equals_deserialized() {
+        let bearer: Bearer = serde_json::from_str(r#"{"access_token":"aaaaaaaa"}"#).unwrap();
+        assert_eq!(Bearer::new("aaaaaaaa"), bearer);
+    }
 
     #[test]
     fn from_response_refresh() {
@@ -95,6 +188,88 @@ mod tests {
         assert!(expires <= (Utc::now() + Duration::seconds(3600)));
     }
 
+    #[test]
+    fn from_response_expires_in_string() {
+        let json = r#"
+            {
+                "token_type":"Bearer",
+                "access_token":"aaaaaaaa",
+                "expires_in":"3600"
+            }
+        "#;
+        let bearer: Bearer = serde_json::from_str(json).unwrap();
+        let expires = bearer.expires.unwrap();
+        assert!(expires > (Utc::now() + Duration::seconds(3599)));
+        assert!(expires <= (Utc::now() + Duration::seconds(3600)));
+    }
+
+    #[test]
+    fn from_response_expires_in_invalid_string() {
+        let json = r#"
+            {
+                "token_type":"Bearer",
+                "access_token":"aaaaaaaa",
+                "expires_in":"soon"
+            }
+        "#;
+        assert!(serde_json::from_str::<Bearer>(json).is_err());
+    }
+
+    #[test]
+    fn camel_case_fields() {
+        let json = json!({
+            "tokenType": "bearer",
+            "accessToken": "aaaaaaaa",
+            "refreshToken": "bbbbbbbb",
+            "idToken": "cccccccc",
+            "expiresIn": 3600
+        });
+        let received_at = Utc::now();
+        let bearer = Bearer::from_response(json, received_at).unwrap();
+        assert_eq!("aaaaaaaa", bearer.access_token);
+        assert_eq!(Some("Bearer"), bearer.token_type.as_deref());
+        assert_eq!(Some("bbbbbbbb"), bearer.refresh_token.as_deref());
+        assert_eq!(Some("cccccccc"), bearer.id_token.as_deref());
+        assert_eq!(Some(received_at + Duration::seconds(3600)), bearer.expires);
+    }
+
+    #[test]
+    fn expires_field() {
+        let json = json!({"access_token": "aaaaaaaa", "expires": "3600"});
+        let received_at = Utc::now();
+        let bearer = Bearer::from_response(json.clone(), received_at).unwrap();
+        assert_eq!(Some(received_at + Duration::seconds(3600)), bearer.expires);
+
+        let bearer: Bearer = serde_json::from_value(json).unwrap();
+        assert!(bearer.expires.unwrap() > Utc::now() + Duration::seconds(3599));
+    }
+
+    #[test]
+    fn from_response_received_at() {
+        let json = serde_json::json!({
+            "token_type": "Bearer",
+            "access_token": "aaaaaaaa",
+            "expires_in": 3600
+        });
+        let received_at = Utc::now();
+        let bearer = Bearer::from_response(json, received_at).unwrap();
+        assert_eq!(Some(received_at + Duration::seconds(3600)), bearer.expires);
+        assert!(!bearer.expired());
+        assert!(!bearer.expired_at(received_at + Duration::seconds(3599)));
+        assert!(bearer.expired_at(received_at + Duration::seconds(3601)));
+    }
+
+    #[test]
+    fn from_response_received_at_without_expires_in() {
+        let json = serde_json::json!({
+            "token_type": "Bearer",
+            "access_token": "aaaaaaaa"
+        });
+        let bearer = Bearer::from_response(json, Utc::now()).unwrap();
+        assert_eq!(None, bearer.expires);
+        assert!(!bearer.expired_at(Utc::now() + Duration::days(365)));
+    }
+
     #[test]
     fn from_response_static() {
         let json = r#"
@@ -109,4 +284,51 @@ mod tests {
         assert_eq!(None, bearer.refresh_token);
         assert_eq!(None, bearer.expires);
     }
+
+    #[test]
+    fn token_type_case_insensitive() {
+        for token_type in ["bearer", "Bearer", "BEARER"] {
+            let json = format!(
+                r#"{{"token_type":"{}","access_token":"aaaaaaaa"}}"#,
+                token_type
+            );
+            let bearer: Bearer = serde_json::from_str(&json).unwrap();
+            assert_eq!(Some("Bearer".into()), bearer.token_type);
+        }
+    }
+
+    #[test]
+    fn token_type_dpop() {
+        let json = r#"{"token_type":"DPoP","access_token":"aaaaaaaa"}"#;
+        let bearer: Bearer = serde_json::from_str(json).unwrap();
+        assert_eq!(Some("DPoP".into()), bearer.token_type);
+    }
+
+    #[test]
+    fn token_type_absent() {
+        let bearer: Bearer = serde_json::from_str(r#"{"access_token":"aaaaaaaa"}"#).unwrap();
+        assert_eq!(None, bearer.token_type);
+    }
+
+    #[test]
+    fn token_type_unsupported() {
+        let json = r#"{"token_type":"mac","access_token":"aaaaaaaa"}"#;
+        let error = serde_json::from_str::<Bearer>(json).unwrap_err();
+        assert!(error.to_string().contains("Unsupported token_type: mac"));
+    }
+
+    #[test]
+    fn scopes_space_or_comma_separated() {
+        for scope in ["a b c", "a,b,c", "a, b ,c", " a  b,,c "] {
+            let json = json!({"access_token": "aaaaaaaa", "scope": scope});
+            let bearer: Bearer = serde_json::from_value(json).unwrap();
+            assert_eq!(vec!["a", "b", "c"], bearer.scopes(), "{}", scope);
+        }
+    }
+
+    #[test]
+    fn scopes_absent() {
+        let bearer: Bearer = serde_json::from_str(r#"{"access_token":"aaaaaaaa"}"#).unwrap();
+        assert!(bearer.scopes().is_empty());
+    }
 }
@@ -0,0 +1,397 @@
+use crate::{
+    client::{check_jwks, token_body, token_response, validate_userinfo_content_type, USER_AGENT},
+    discovered,
+    dpop::{DPOP_NONCE, USE_DPOP_NONCE},
+    error::{ClientError, Error, Userinfo as ErrorUserinfo},
+    trace, Bearer, Claims, ClientAuth, Configurable, Discovered, Operation, Provider,
+    StandardClaims, Token, Userinfo,
+};
+use biscuit::CompactJson;
+use chrono::Duration;
+use reqwest::{
+    blocking::{RequestBuilder, Response},
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+    StatusCode,
+};
+use serde_json::Value;
+use std::ops::Deref;
+use url::Url;
+
+/// Synchronous OpenID Connect client on top of `reqwest::blocking`, for applications without an
+/// async runtime.
+///
+/// The requests to the provider are blocking, everything else (e.g. [`crate::Client::auth_url`],
+/// [`crate::Client::decode_token`] and [`crate::Client::validate_token`]) is available through
+/// the wrapped async client.
+#[derive(Debug)]
+pub struct Client<P = Discovered, C: CompactJson + Claims = StandardClaims> {
+    client: crate::Client<P, C>,
+    http_client: reqwest::blocking::Client,
+}
+
+impl<P, C: CompactJson + Claims> Deref for Client<P, C> {
+    type Target = crate::Client<P, C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+/// HTTP client used for discovery, identifying itself with the user agent.
+fn default_http_client() -> Result<reqwest::blocking::Client, Error> {
+    let builder = reqwest::blocking::Client::builder().user_agent(USER_AGENT);
+    #[cfg(feature = "rustls")]
+    let builder = builder.use_rustls_tls();
+    Ok(builder.build()?)
+}
+
+impl<C: CompactJson + Claims> Client<Discovered, C> {
+    /// Constructs a client from an issuer url and client parameters via discovery.
+    pub fn discover(
+        id: String,
+        secret: String,
+        redirect: Option<String>,
+        issuer: Url,
+    ) -> Result<Self, Error> {
+        Self::discover_with_client(default_http_client()?, id, secret, redirect, issuer)
+    }
+
+    /// Constructs a client from an issuer url and client parameters via discovery, using the
+    /// given HTTP client.
+    pub fn discover_with_client(
+        http_client: reqwest::blocking::Client,
+        id: String,
+        secret: String,
+        redirect: Option<String>,
+        issuer: Url,
+    ) -> Result<Self, Error> {
+        let mut config = Err(Error::CannotBeABase);
+        for url in discovered::discovery_urls(&issuer)? {
+            config = trace::request_blocking(Operation::Discovery, &url, None, || {
+                let resp = http_client.get(url.clone()).send()?.error_for_status()?;
+                Ok::<crate::Config, Error>(resp.json()?)
+            });
+            if config.is_ok() {
+                break;
+            }
+        }
+        let config = config?;
+        let jwks = match config.jwks_uri {
+            Some(ref jwks_uri) => Some(trace::request_blocking(
+                Operation::Jwks,
+                jwks_uri,
+                None,
+                || discovered::jwks_from_value(http_client.get(jwks_uri.clone()).send()?.json()?),
+            )?),
+            None => None,
+        };
+        check_jwks(jwks.as_ref())?;
+
+        let client = crate::Client::new(
+            config.into(),
+            id,
+            secret,
+            redirect,
+            reqwest::Client::new(),
+            jwks,
+        );
+        Ok(Self::new(client, http_client))
+    }
+}
+
+impl<C: CompactJson + Claims, P: Provider + Configurable> Client<P, C> {
+    /// Wraps a client, sending its requests with the blocking HTTP client instead of its own.
+    pub fn new(client: crate::Client<P, C>, http_client: reqwest::blocking::Client) -> Self {
+        Self {
+            client,
+            http_client,
+        }
+    }
+
+    /// The wrapped client.
+    pub fn into_inner(self) -> crate::Client<P, C> {
+        self.client
+    }
+
+    /// Given an auth_code and auth options, request the token, decode, and validate it. Errors
+    /// with Missing::IdToken if the provider returned no id_token for an OpenID Connect flow.
+    pub fn authenticate(
+        &self,
+        auth_code: &str,
+        nonce: Option<&str>,
+        max_age: Option<&Duration>,
+    ) -> Result<Token<C>, Error> {
+        let body = self.client.authorization_code_body(
+            auth_code,
+            None,
+            nonce,
+            self.client.redirect_uri.as_deref(),
+        )?;
+        let bearer: Bearer = serde_json::from_value(self.post_token(body)?)?;
+        self.client.require_id_token(&bearer)?;
+        let mut token: Token<C> = bearer.into();
+        if let Some(id_token) = token.id_token.as_mut() {
+            self.client.decode_token(id_token)?;
+            self.client.validate_token(id_token, nonce, max_age)?;
+        }
+        Ok(token)
+    }
+
+    /// Requests an access token using an authorization code.
+    ///
+    /// See [RFC 6749, section 4.1.3](http://tools.ietf.org/html/rfc6749#section-4.1.3).
+    pub fn request_token(&self, code: &str) -> Result<Bearer, ClientError> {
+        let body = self.client.authorization_code_body(
+            code,
+            None,
+            None,
+            self.client.redirect_uri.as_deref(),
+        )?;
+        Ok(serde_json::from_value(self.post_token(body)?)?)
+    }
+
+    /// Refreshes an access token.
+    ///
+    /// See [RFC 6749, section 6](http://tools.ietf.org/html/rfc6749#section-6).
+    ///
+    /// # Panics
+    ///
+    /// If the token has no refresh token.
+    pub fn refresh_token(&self, token: Bearer, scope: Option<&str>) -> Result<Bearer, ClientError> {
+        let body = self.client.refresh_token_body(&token, scope);
+        let mut new_token: Bearer = serde_json::from_value(self.post_token(body)?)?;
+        if new_token.refresh_token.is_none() {
+            new_token.refresh_token = token.refresh_token;
+        }
+        Ok(new_token)
+    }
+
+    /// Get a userinfo json document for a given token at the provider's userinfo endpoint.
+    /// Returns [Standard Claims](https://openid.net/specs/openid-connect-core-1_0.html#StandardClaims) as [Userinfo] struct.
+    ///
+    /// # Errors
+    ///
+    /// - [ErrorUserinfo::NoUrl] if this provider doesn't have a userinfo endpoint
+    /// - [ErrorUserinfo::MismatchSubject] if the returned userinfo document and tokens subject mismatch
+    /// - [ErrorUserinfo::MismatchEmail] if `enforce_userinfo_email` is set and the returned
+    ///   userinfo document and tokens email mismatch
+    pub fn request_userinfo(&self, token: &Token<C>) -> Result<Userinfo, Error> {
+        let claims = token.id_token.as_ref().map(|x| x.payload()).transpose()?;
+        let info = self.request_userinfo_with_access_token(&token.bearer.access_token)?;
+        self.check_userinfo(claims, &info)?;
+        Ok(info)
+    }
+
+    /// Get a userinfo json document for the given access token, e.g. one obtained outside of this
+    /// client. The subject is not checked against an id_token.
+    pub fn request_userinfo_with_access_token(
+        &self,
+        access_token: &str,
+    ) -> Result<Userinfo, Error> {
+        let url = match self.client.config().userinfo_endpoint {
+            Some(ref url) => url,
+            None => return Err(ErrorUserinfo::NoUrl.into()),
+        };
+
+        let observer = self.client.observer.as_deref();
+        trace::request_blocking(Operation::Userinfo, url, observer, || {
+            let mut resp = self.userinfo_request(url, access_token, None)?.send()?;
+            // The server asks to retry with its nonce in the DPoP proof
+            if self.client.dpop.is_some() && resp.status() == StatusCode::UNAUTHORIZED {
+                if let Some(dpop_nonce) = response_dpop_nonce(&resp) {
+                    resp = self
+                        .userinfo_request(url, access_token, Some(&dpop_nonce))?
+                        .send()?;
+                }
+            }
+            let resp = resp.error_for_status()?;
+            let content_type = resp
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|content_type| content_type.to_str().ok());
+            validate_userinfo_content_type(content_type)?;
+            Ok(resp.json()?)
+        })
+    }
+
+    fn userinfo_request(
+        &self,
+        url: &Url,
+        access_token: &str,
+        dpop_nonce: Option<&str>,
+    ) -> Result<RequestBuilder, Error> {
+        let request = self.http_client.get(url.clone());
+        Ok(match self.client.dpop {
+            Some(ref dpop) => request
+                .header(AUTHORIZATION, format!("DPoP {}", access_token))
+                .header(
+                    "DPoP",
+                    dpop.proof("GET", url, Some(access_token), dpop_nonce)?,
+                ),
+            None => request.bearer_auth(access_token),
+        })
+    }
+
+    fn post_token(&self, body: String) -> Result<Value, ClientError> {
+        let (mut status, mut json, dpop_nonce) = self.send_token_request(body.clone(), None)?;
+
+        // The server asks to retry with its nonce in the DPoP proof
+        if let Some(dpop_nonce) = dpop_nonce {
+            if json.get("error").and_then(Value::as_str) == Some(USE_DPOP_NONCE) {
+                (status, json, _) = self.send_token_request(body, Some(&dpop_nonce))?;
+            }
+        }
+
+        token_response(status, json)
+    }
+
+    fn send_token_request(
+        &self,
+        body: String,
+        dpop_nonce: Option<&str>,
+    ) -> Result<(StatusCode, Value, Option<String>), ClientError> {
+        let client = &self.client;
+        let token_uri = client.provider.token_uri();
+        let mut request = self
+            .http_client
+            .post(token_uri.clone())
+            .header(ACCEPT, "application/json")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if client.client_auth == ClientAuth::ClientSecret {
+            request = request.basic_auth(&client.client_id, Some(client.client_secret.as_str()));
+        }
+        if let Some(ref dpop) = client.dpop {
+            request = request.header("DPoP", dpop.proof("POST", token_uri, None, dpop_nonce)?);
+        }
+
+        let observer = client.observer.as_deref();
+        trace::request_blocking(Operation::Token, token_uri, observer, || {
+            let response = request.body(body).send()?;
+            let dpop_nonce = response_dpop_nonce(&response);
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|content_type| content_type.to_str().ok())
+                .map(String::from);
+            let status = response.status();
+            let json = token_body(content_type.as_deref(), &response.bytes()?)?;
+            Ok((status, json, dpop_nonce))
+        })
+    }
+}
+
+fn response_dpop_nonce(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get(DPOP_NONCE)
+        .and_then(|nonce| nonce.to_str().ok())
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, config_json, MockServer, Response};
+    use serde_json::json;
+
+    fn provider() -> MockServer {
+        MockServer::start(|request| {
+            let base = Url::parse(&format!("http://{}/", request.header("host").unwrap())).unwrap();
+            match request.path.as_str() {
+                "/.well-known/openid-configuration" => Response::json(config_json(&base)),
+                "/jwks" => Response::json(serde_json::to_value(testing::jwks("key")).unwrap()),
+                "/token" => {
+                    let claims =
+                        testing::claims_json(base.as_str().trim_end_matches('/'), "client");
+                    Response::json(json!({
+                        "access_token": "access",
+                        "refresh_token": "refresh",
+                        "id_token": testing::sign(claims, Some("key")),
+                    }))
+                }
+                "/userinfo" => Response::json(json!({"sub": "subject"})),
+                _ => Response::with_status(404, json!({})),
+            }
+        })
+    }
+
+    fn client(server: &MockServer) -> Client {
+        Client::discover(
+            String::from("client"),
+            String::from("secret"),
+            Some(String::from("https://app.example.com/callback")),
+            server.url("/"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn request_token() {
+        let server = provider();
+        let client = client(&server);
+
+        let bearer = client.request_token("code").unwrap();
+        assert_eq!("access", bearer.access_token);
+
+        let request = &server.requests_to("/token")[0];
+        assert_eq!("POST", request.method);
+        assert!(request.body.contains("grant_type=authorization_code"));
+        assert!(request.body.contains("code=code"));
+        assert!(request
+            .body
+            .contains("redirect_uri=https%3A%2F%2Fapp.example.com%2Fcallback"));
+        assert!(request
+            .header("authorization")
+            .unwrap()
+            .starts_with("Basic "));
+    }
+
+    #[test]
+    fn authenticate_and_request_userinfo() {
+        let server = provider();
+        let client = client(&server);
+
+        let token = client.authenticate("code", None, None).unwrap();
+        assert_eq!(
+            "subject",
+            token.id_token.as_ref().unwrap().payload().unwrap().sub
+        );
+
+        let userinfo = client.request_userinfo(&token).unwrap();
+        assert_eq!(Some("subject"), userinfo.sub.as_deref());
+        let request = &server.requests_to("/userinfo")[0];
+        assert_eq!(Some("Bearer access"), request.header("authorization"));
+    }
+
+    #[test]
+    fn refresh_token() {
+        let server = provider();
+        let client = client(&server);
+
+        let bearer = client.request_token("code").unwrap();
+        let bearer = client.refresh_token(bearer, Some("openid")).unwrap();
+        assert_eq!(Some("refresh"), bearer.refresh_token.as_deref());
+
+        let request = &server.requests_to("/token")[1];
+        assert!(request.body.contains("grant_type=refresh_token"));
+        assert!(request.body.contains("refresh_token=refresh"));
+    }
+
+    #[test]
+    fn request_token_error() {
+        let server = MockServer::start(|request| {
+            let base = Url::parse(&format!("http://{}/", request.header("host").unwrap())).unwrap();
+            match request.path.as_str() {
+                "/.well-known/openid-configuration" => Response::json(config_json(&base)),
+                "/jwks" => Response::json(serde_json::to_value(testing::jwks("key")).unwrap()),
+                _ => Response::with_status(400, json!({"error": "invalid_grant"})),
+            }
+        });
+        let client = client(&server);
+
+        assert!(matches!(
+            client.request_token("code"),
+            Err(ClientError::OAuth(_))
+        ));
+    }
+}
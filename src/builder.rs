@@ -0,0 +1,291 @@
+use crate::{
+    client::{check_jwks, default_http_client, USER_AGENT},
+    error::Error,
+    Claims, Client, ClientObserver, Discovered, Provider, StandardClaims,
+};
+use biscuit::{jwa::SignatureAlgorithm, jwk::JWKSet, CompactJson, Empty};
+use chrono::Duration;
+use std::{marker::PhantomData, sync::Arc};
+use url::Url;
+
+/// Builder for a [`Client`], as an alternative to the positional arguments of [`Client::new`].
+///
+/// # Examples
+///
+/// ```
+/// use openid::{Client, ClientBuilder};
+/// use openid::provider::google::Installed;
+///
+/// let client: Client<_> = ClientBuilder::new()
+///     .provider(Installed)
+///     .client_id("CLIENT_ID")
+///     .client_secret("CLIENT_SECRET")
+///     .redirect_uri("urn:ietf:wg:oauth:2.0:oob")
+///     .build()
+///     .unwrap();
+/// ```
+pub struct ClientBuilder<P = Discovered, C: CompactJson + Claims = StandardClaims> {
+    provider: Option<P>,
+    client_id: Option<String>,
+    client_secret: String,
+    redirect_uri: Option<String>,
+    redirect_uris: Vec<String>,
+    http_client: Option<reqwest::Client>,
+    user_agent: Option<String>,
+    jwks: Option<JWKSet<Empty>>,
+    jwks_uri: Option<Url>,
+    leeway: Duration,
+    allowed_algorithms: Option<Vec<SignatureAlgorithm>>,
+    require_asymmetric_signing: bool,
+    additional_issuers: Vec<Url>,
+    observer: Option<Arc<dyn ClientObserver>>,
+    marker: PhantomData<C>,
+}
+
+impl<P, C: CompactJson + Claims> Default for ClientBuilder<P, C> {
+    fn default() -> Self {
+        Self {
+            provider: None,
+            client_id: None,
+            client_secret: String::new(),
+            redirect_uri: None,
+            redirect_uris: Vec::new(),
+            http_client: None,
+            user_agent: None,
+            jwks: None,
+            jwks_uri: None,
+            leeway: Duration::zero(),
+            allowed_algorithms: None,
+            require_asymmetric_signing: false,
+            additional_issuers: Vec::new(),
+            observer: None,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<P: Provider, C: CompactJson + Claims> ClientBuilder<P, C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The OAuth provider, required.
+    pub fn provider(mut self, provider: P) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// The client ID, required.
+    pub fn client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.client_id = Some(client_id.into());
+        self
+    }
+
+    /// The client secret, empty if not set.
+    pub fn client_secret(mut self, client_secret: impl Into<String>) -> Self {
+        self.client_secret = client_secret.into();
+        self
+    }
+
+    pub fn redirect_uri(mut self, redirect_uri: impl Into<String>) -> Self {
+        self.redirect_uri = Some(redirect_uri.into());
+        self
+    }
+
+    /// Further redirect URIs which can be selected per request.
+    pub fn redirect_uris(mut self, redirect_uris: Vec<String>) -> Self {
+        self.redirect_uris = redirect_uris;
+        self
+    }
+
+    /// The HTTP client, a default one if not set.
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// User-Agent of the default HTTP client, `openid-rs/<version>` if not set. Not applied to
+    /// a client given with [`ClientBuilder::http_client`].
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn jwks(mut self, jwks: JWKSet<Empty>) -> Self {
+        self.jwks = Some(jwks);
+        self
+    }
+
+    /// Key set url to refresh the keys from instead of the `jwks_uri` of the provider.
+    pub fn jwks_uri(mut self, jwks_uri: Url) -> Self {
+        self.jwks_uri = Some(jwks_uri);
+        self
+    }
+
+    /// Tolerance for clock skew when checking the expiry of tokens.
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Signature algorithms accepted for id_tokens.
+    pub fn allowed_algorithms(mut self, algorithms: Vec<SignatureAlgorithm>) -> Self {
+        self.allowed_algorithms = Some(algorithms);
+        self
+    }
+
+    /// Reject id_tokens signed with a symmetric (HS*) algorithm.
+    pub fn require_asymmetric_signing(mut self, require: bool) -> Self {
+        self.require_asymmetric_signing = require;
+        self
+    }
+
+    /// Issuers whose tokens are accepted besides the issuer of the provider.
+    pub fn additional_issuers(mut self, issuers: Vec<Url>) -> Self {
+        self.additional_issuers = issuers;
+        self
+    }
+
+    /// Notified of each request of the client, e.g. to record metrics.
+    pub fn observer(mut self, observer: Arc<dyn ClientObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Builds the client. Errors with MissingBuilderField if the provider or client ID are not
+    /// set, or with Decode::EmptySet if the key set has no keys.
+    pub fn build(self) -> Result<Client<P, C>, Error> {
+        let provider = self
+            .provider
+            .ok_or(Error::MissingBuilderField("provider"))?;
+        let client_id = self
+            .client_id
+            .ok_or(Error::MissingBuilderField("client_id"))?;
+        check_jwks(self.jwks.as_ref())?;
+
+        let http_client = match self.http_client {
+            Some(http_client) => http_client,
+            None => default_http_client(self.user_agent.as_deref().unwrap_or(USER_AGENT))?,
+        };
+
+        let mut client = Client::new(
+            provider,
+            client_id,
+            self.client_secret,
+            self.redirect_uri,
+            http_client,
+            self.jwks,
+        );
+        client.redirect_uris = self.redirect_uris;
+        client.jwks_uri = self.jwks_uri;
+        client.leeway = self.leeway;
+        client.allowed_algorithms = self.allowed_algorithms;
+        client.require_asymmetric_signing = self.require_asymmetric_signing;
+        client.additional_issuers = self.additional_issuers;
+        client.observer = self.observer;
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::Decode,
+        provider::google::Installed,
+        testing::{self, config_json, MockServer, Response},
+        Config,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn build_equals_new() {
+        let built: Client<_> = ClientBuilder::new()
+            .provider(Installed)
+            .client_id("client")
+            .client_secret("secret")
+            .redirect_uri("urn:ietf:wg:oauth:2.0:oob")
+            .http_client(reqwest::Client::new())
+            .jwks(testing::jwks("key"))
+            .build()
+            .unwrap();
+        let new: Client<_> = Client::new(
+            Installed,
+            String::from("client"),
+            String::from("secret"),
+            Some(String::from("urn:ietf:wg:oauth:2.0:oob")),
+            reqwest::Client::new(),
+            Some(testing::jwks("key")),
+        );
+
+        assert_eq!(format!("{:?}", new), format!("{:?}", built));
+    }
+
+    #[test]
+    fn build_with_options() {
+        let client: Client<_> = ClientBuilder::new()
+            .provider(Installed)
+            .client_id("client")
+            .leeway(Duration::seconds(30))
+            .allowed_algorithms(vec![SignatureAlgorithm::RS256])
+            .require_asymmetric_signing(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(Duration::seconds(30), client.leeway);
+        assert_eq!(
+            Some(vec![SignatureAlgorithm::RS256]),
+            client.allowed_algorithms
+        );
+        assert!(client.require_asymmetric_signing);
+    }
+
+    #[test]
+    fn build_without_client_id() {
+        let client = ClientBuilder::<_, StandardClaims>::new()
+            .provider(Installed)
+            .build();
+        assert!(matches!(
+            client,
+            Err(Error::MissingBuilderField("client_id"))
+        ));
+    }
+
+    #[test]
+    fn build_with_empty_jwks() {
+        let client = ClientBuilder::<_, StandardClaims>::new()
+            .provider(Installed)
+            .client_id("client")
+            .jwks(JWKSet { keys: vec![] })
+            .build();
+        assert!(matches!(client, Err(Error::Decode(Decode::EmptySet))));
+    }
+
+    async fn token_request_user_agent(builder: ClientBuilder) -> Option<String> {
+        let server = MockServer::start(|_| Response::json(json!({"access_token": "fresh"})));
+        let config: Config = serde_json::from_value(config_json(&server.url("/"))).unwrap();
+        let client = builder
+            .provider(config.into())
+            .client_id("client")
+            .build()
+            .unwrap();
+
+        client.request_token("code").await.unwrap();
+
+        let request = &server.requests_to("/token")[0];
+        request.header("user-agent").map(String::from)
+    }
+
+    #[tokio::test]
+    async fn default_user_agent() {
+        let user_agent = token_request_user_agent(ClientBuilder::new()).await;
+        assert_eq!(Some(USER_AGENT), user_agent.as_deref());
+        assert!(USER_AGENT.starts_with("openid-rs/"));
+    }
+
+    #[tokio::test]
+    async fn custom_user_agent() {
+        let user_agent = token_request_user_agent(ClientBuilder::new().user_agent("app/1.0")).await;
+        assert_eq!(Some("app/1.0"), user_agent.as_deref());
+    }
+}
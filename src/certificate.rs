@@ -0,0 +1,208 @@
+use crate::error::Decode;
+use ring::digest;
+use serde_json::Value;
+
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const EXPLICIT_VERSION: u8 = 0xa0;
+
+/// 1.2.840.113549.1.1.1
+const RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+/// Reads a DER element, returning its tag, contents and the remaining input.
+fn element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&length, mut input) = input.split_first()?;
+    let length = if length & 0x80 == 0 {
+        length as usize
+    } else {
+        let octets = (length & 0x7f) as usize;
+        if octets == 0 || octets > 4 || input.len() < octets {
+            return None;
+        }
+        let (length, rest) = input.split_at(octets);
+        input = rest;
+        length
+            .iter()
+            .fold(0, |length, octet| length << 8 | *octet as usize)
+    };
+    if input.len() < length {
+        return None;
+    }
+    let (contents, rest) = input.split_at(length);
+    Some((tag, contents, rest))
+}
+
+/// Reads a DER element with the expected tag, returning its contents and the remaining input.
+fn expect(input: &[u8], expected: u8) -> Option<(&[u8], &[u8])> {
+    match element(input)? {
+        (tag, contents, rest) if tag == expected => Some((contents, rest)),
+        _ => None,
+    }
+}
+
+/// Big-endian unsigned value of a positive DER integer.
+fn unsigned(integer: &[u8]) -> &[u8] {
+    match integer {
+        [0, rest @ ..] if !rest.is_empty() => rest,
+        _ => integer,
+    }
+}
+
+/// Extracts modulus and exponent of the RSA public key of a DER encoded X.509 certificate.
+///
+/// See [RFC 5280, section 4.1](https://www.rfc-editor.org/rfc/rfc5280#section-4.1) and
+/// [RFC 8017, appendix A.1.1](https://www.rfc-editor.org/rfc/rfc8017#appendix-A.1.1).
+pub(crate) fn rsa_public_key(certificate: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (certificate, _) = expect(certificate, SEQUENCE)?;
+    let (mut tbs, _) = expect(certificate, SEQUENCE)?;
+    if tbs.first() == Some(&EXPLICIT_VERSION) {
+        tbs = element(tbs)?.2;
+    }
+    // Skip serialNumber, signature, issuer, validity and subject
+    for _ in 0..5 {
+        tbs = element(tbs)?.2;
+    }
+
+    let (public_key_info, _) = expect(tbs, SEQUENCE)?;
+    let (algorithm, public_key_info) = expect(public_key_info, SEQUENCE)?;
+    let (oid, _) = expect(algorithm, OBJECT_IDENTIFIER)?;
+    if oid != RSA_ENCRYPTION {
+        return None;
+    }
+    let (public_key, _) = expect(public_key_info, BIT_STRING)?;
+    // The leading octet counts the unused bits
+    let public_key = public_key.strip_prefix(&[0])?;
+    let (public_key, _) = expect(public_key, SEQUENCE)?;
+    let (n, public_key) = expect(public_key, INTEGER)?;
+    let (e, _) = expect(public_key, INTEGER)?;
+    Some((unsigned(n), unsigned(e)))
+}
+
+/// Decodes the leading certificate of a JWK `x5c` chain.
+pub(crate) fn leading_certificate(key: &Value) -> Option<Result<Vec<u8>, Decode>> {
+    let certificate = key.get("x5c")?.as_array()?.first()?;
+    let certificate = certificate
+        .as_str()
+        .and_then(|certificate| base64::decode(certificate).ok())
+        .ok_or(Decode::InvalidCertificate);
+    Some(certificate)
+}
+
+/// Verifies the `x5t` (SHA-1) and `x5t#S256` thumbprints of a JWK match its leading `x5c`
+/// certificate, if both are present.
+pub(crate) fn verify_thumbprints(key: &Value) -> Result<(), Decode> {
+    let thumbprints = [
+        ("x5t", &digest::SHA1_FOR_LEGACY_USE_ONLY),
+        ("x5t#S256", &digest::SHA256),
+    ];
+    for (name, algorithm) in thumbprints {
+        if let (Some(expected), Some(certificate)) = (key.get(name), leading_certificate(key)) {
+            let certificate = certificate?;
+            let actual = digest::digest(algorithm, &certificate);
+            let actual = base64::encode_config(actual.as_ref(), base64::URL_SAFE_NO_PAD);
+            if expected.as_str() != Some(actual.as_str()) {
+                return Err(Decode::ThumbprintMismatch(name.to_string()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fills in `n` and `e` of RSA keys that only supply an `x5c` certificate chain, taken from the
+/// public key of the leading certificate. Rejects keys with thumbprints not matching their
+/// certificate.
+pub(crate) fn complete_rsa_keys(jwks: &mut Value) -> Result<(), Decode> {
+    let keys = match jwks.get_mut("keys").and_then(Value::as_array_mut) {
+        Some(keys) => keys,
+        None => return Ok(()),
+    };
+
+    for key in keys {
+        verify_thumbprints(key)?;
+        if key.get("kty").and_then(Value::as_str) != Some("RSA") || key.get("n").is_some() {
+            continue;
+        }
+        let certificate = match leading_certificate(key) {
+            Some(certificate) => certificate?,
+            None => continue,
+        };
+        let (n, e) = rsa_public_key(&certificate).ok_or(Decode::InvalidCertificate)?;
+        let n = base64::encode_config(n, base64::URL_SAFE_NO_PAD);
+        let e = base64::encode_config(e, base64::URL_SAFE_NO_PAD);
+        if let Some(key) = key.as_object_mut() {
+            key.insert("n".to_string(), n.into());
+            key.insert("e".to_string(), e.into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RSA_CERTIFICATE;
+    use serde_json::json;
+
+    #[test]
+    fn rsa_public_key_from_certificate() {
+        let (n, e) = rsa_public_key(RSA_CERTIFICATE).unwrap();
+        assert_eq!(256, n.len());
+        assert_eq!([0xca, 0x28, 0x32, 0x7f], n[..4]);
+        assert_eq!([0x01, 0x00, 0x01], e);
+    }
+
+    #[test]
+    fn rsa_public_key_from_garbage() {
+        assert!(rsa_public_key(&[SEQUENCE, 0x82, 0xff]).is_none());
+    }
+
+    #[test]
+    fn complete_rsa_keys_keeps_modulus() {
+        let mut jwks =
+            json!({"keys": [{"kty": "RSA", "n": "AQAB", "e": "AQAB", "x5c": ["invalid"]}]});
+        let expected = jwks.clone();
+        complete_rsa_keys(&mut jwks).unwrap();
+        assert_eq!(expected, jwks);
+    }
+
+    #[test]
+    fn complete_rsa_keys_rejects_invalid_certificate() {
+        let mut jwks = json!({"keys": [{"kty": "RSA", "x5c": ["aW52YWxpZA=="]}]});
+        assert!(matches!(
+            complete_rsa_keys(&mut jwks),
+            Err(Decode::InvalidCertificate)
+        ));
+    }
+
+    fn key(x5t_s256: &str) -> Value {
+        json!({
+            "kty": "RSA",
+            "x5c": [base64::encode(RSA_CERTIFICATE)],
+            "x5t#S256": x5t_s256,
+        })
+    }
+
+    #[test]
+    fn thumbprint_matches_certificate() {
+        let thumbprint = digest::digest(&digest::SHA256, RSA_CERTIFICATE);
+        let thumbprint = base64::encode_config(thumbprint.as_ref(), base64::URL_SAFE_NO_PAD);
+        let mut jwks = json!({"keys": [key(&thumbprint)]});
+        complete_rsa_keys(&mut jwks).unwrap();
+        assert!(jwks["keys"][0].get("n").is_some());
+    }
+
+    #[test]
+    fn thumbprint_mismatch_is_rejected() {
+        let thumbprint = base64::encode_config([0; 32], base64::URL_SAFE_NO_PAD);
+        let mut jwks = json!({"keys": [key(&thumbprint)]});
+        assert!(matches!(
+            complete_rsa_keys(&mut jwks),
+            Err(Decode::ThumbprintMismatch(name)) if name == "x5t#S256"
+        ));
+    }
+}
@@ -11,7 +11,8 @@ pub trait Claims {
     /// Audience(s) that this ID Token is intended for. It MUST contain the OAuth 2.0 client_id of the Relying Party as an audience value. It MAY also contain identifiers for other audiences. In the general case, the aud value is an array of case sensitive strings. In the common special case when there is one audience, the aud value MAY be a single case sensitive string.
     fn aud(&self) -> &SingleOrMultiple<String>;
     /// Expiration time on or after which the ID Token MUST NOT be accepted for processing. The processing of this parameter requires that the current date/time MUST be before the expiration date/time listed in the value. Implementers MAY provide for some small leeway, usually no more than a few minutes, to account for clock skew. Its value is a JSON number representing the number of seconds from 1970-01-01T0:0:0Z as measured in UTC until the date/time. See RFC 3339 [RFC3339] for details regarding date/times in general and UTC in particular.
-    fn exp(&self) -> i64;
+    /// None if the token has no exp, which fails validation with Validation::Missing::Expiry.
+    fn exp(&self) -> Option<i64>;
     /// Time at which the JWT was issued. Its value is a JSON number representing the number of seconds from 1970-01-01T0:0:0Z as measured in UTC until the date/time.
     fn iat(&self) -> i64;
     /// Time when the End-User authentication occurred. Its value is a JSON number representing the number of seconds from 1970-01-01T0:0:0Z as measured in UTC until the date/time. When a max_age request is made or when auth_time is requested as an Essential Claim, then this Claim is REQUIRED; otherwise, its inclusion is OPTIONAL. (The auth_time Claim semantically corresponds to the OpenID 2.0 PAPE [OpenID.PAPE] auth_time response parameter.)
@@ -48,4 +49,44 @@ pub trait Claims {
     fn c_hash_to_vec(&self) -> Option<Vec<u8>> {
         base64::decode_config(self.c_hash()?, base64::URL_SAFE).ok()
     }
+    /// Whether the amr claim contains the authentication method, e.g. `mfa` or `otp` to require
+    /// multi-factor authentication. False if there is no amr claim.
+    ///
+    /// See [RFC 8176](https://www.rfc-editor.org/rfc/rfc8176) for registered values.
+    fn authenticated_with(&self, method: &str) -> bool {
+        self.amr()
+            .is_some_and(|amr| amr.iter().any(|amr| amr == method))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::standard_claims, StandardClaims};
+    use serde_json::json;
+
+    fn claims(amr: Option<&[&str]>) -> StandardClaims {
+        standard_claims(json!({ "amr": amr }))
+    }
+
+    #[test]
+    fn authenticated_with() {
+        let claims = claims(Some(&["pwd", "otp", "mfa"]));
+        assert_eq!(
+            Some(&vec!["pwd".to_string(), "otp".into(), "mfa".into()]),
+            claims.amr()
+        );
+        assert!(claims.authenticated_with("mfa"));
+        assert!(claims.authenticated_with("otp"));
+        assert!(!claims.authenticated_with("hwk"));
+        // Values are case sensitive
+        assert!(!claims.authenticated_with("MFA"));
+    }
+
+    #[test]
+    fn authenticated_without_amr() {
+        let claims = claims(None);
+        assert_eq!(None, claims.amr());
+        assert!(!claims.authenticated_with("pwd"));
+    }
 }
@@ -1,26 +1,48 @@
 use crate::{
-    discovered,
-    error::{ClientError, Decode, Error, Jose, Userinfo as ErrorUserinfo},
+    certificate, discovered,
+    dpop::{DPoPKey, DPOP_NONCE, USE_DPOP_NONCE},
+    error::{
+        ClientError, Decode, Error, Jose, Mismatch, Missing, StepUp, Userinfo as ErrorUserinfo,
+        Validation,
+    },
+    session::SessionStore,
+    trace,
     validation::{
-        validate_token_aud, validate_token_exp, validate_token_issuer, validate_token_nonce,
+        lint_token, validate_refreshed_token, validate_state, validate_token_at_hash,
+        validate_token_aud, validate_token_certificate_binding, validate_token_exp_with_leeway,
+        validate_token_issuer_in, validate_token_nonce, validate_token_subject, TokenWarning,
     },
-    Bearer, Claims, Config, Configurable, Discovered, IdToken, OAuth2Error, Options, Provider,
-    StandardClaims, Token, Userinfo,
+    AuthSession, Bearer, Claims, ClientAuth, ClientObserver, ClientSecret, Config, Configurable,
+    Discovered, FragmentResponse, IdToken, OAuth2Error, Operation, Options, Prompt, Provider,
+    StandardClaims, Token, Userinfo, UserinfoCache,
 };
 use biscuit::{
+    errors::ValidationError,
     jwa::{self, SignatureAlgorithm},
-    jwk::{AlgorithmParameters, JWKSet},
-    jws::{Compact, Secret},
+    jwk::{AlgorithmParameters, JWKSet, JWK},
+    jws::{Compact, Header, Secret},
     CompactJson, Empty,
 };
-use chrono::Duration;
-use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use chrono::{Duration, Utc};
+use reqwest::{
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    StatusCode,
+};
 use serde_json::Value;
-use std::marker::PhantomData;
-use url::{form_urlencoded::Serializer, Url};
+use std::{
+    collections::HashMap,
+    fmt, future,
+    marker::PhantomData,
+    sync::{Arc, Mutex, RwLock},
+    task::{Poll, Waker},
+    time::{self, Instant},
+};
+use url::{
+    form_urlencoded::{self, Serializer},
+    Url,
+};
 
 /// OAuth 2.0 client.
-#[derive(Debug)]
 pub struct Client<P = Discovered, C: CompactJson + Claims = StandardClaims> {
     /// OAuth provider.
     pub provider: P,
@@ -29,17 +51,300 @@ pub struct Client<P = Discovered, C: CompactJson + Claims = StandardClaims> {
     pub client_id: String,
 
     /// Client secret.
-    pub client_secret: String,
+    pub client_secret: ClientSecret,
 
     /// Redirect URI.
     pub redirect_uri: Option<String>,
 
+    /// Further registered redirect URIs, which can be selected per request instead of
+    /// `redirect_uri`, e.g. a deep link of a mobile app.
+    pub redirect_uris: Vec<String>,
+
     pub http_client: reqwest::Client,
 
+    /// Key set the id_tokens are decoded with, until [`Client::decode_token_refreshing`] fetched
+    /// a new one.
     pub jwks: Option<JWKSet<Empty>>,
+
+    /// Reject tokens signed with an algorithm not listed in the provider's
+    /// `id_token_signing_alg_values_supported`. Ignored if the provider advertises none.
+    pub enforce_advertised_algorithms: bool,
+
+    /// Key to request DPoP-bound tokens and prove their possession at the userinfo endpoint.
+    ///
+    /// See [RFC 9449](https://www.rfc-editor.org/rfc/rfc9449).
+    pub dpop: Option<DPoPKey>,
+
+    /// Authentication method at the token endpoint. With [`ClientAuth::MutualTls`] the
+    /// `http_client` must be built with the client certificate as identity.
+    pub client_auth: ClientAuth,
+
+    /// Tolerance for clock skew when checking the expiry of tokens.
+    pub leeway: Duration,
+
+    /// Signature algorithms accepted for id_tokens. All algorithms supported by the key are
+    /// accepted if not set.
+    pub allowed_algorithms: Option<Vec<SignatureAlgorithm>>,
+
+    /// Reject id_tokens signed with a symmetric (HS*) algorithm, whose key is the shared client
+    /// secret, or unsigned ones, so only asymmetric algorithms are accepted.
+    pub require_asymmetric_signing: bool,
+
+    /// Also send the nonce in the token request of [`Client::authenticate`], for providers
+    /// expecting it there. Spec-compliant providers only need it in the auth request.
+    pub nonce_in_token_request: bool,
+
+    /// Reject id_tokens with a `typ` header other than `JWT`, e.g. access tokens (`at+jwt`)
+    /// passed off as id_tokens. Tokens without `typ` are accepted.
+    pub enforce_id_token_typ: bool,
+
+    /// Minimum time between key set fetches of [`Client::decode_token_refreshing`].
+    pub jwks_refresh_interval: Duration,
+
+    /// Key set fetched by [`Client::decode_token_refreshing`] instead of the `jwks_uri` of the
+    /// provider, e.g. an internal mirror.
+    pub jwks_uri: Option<Url>,
+
+    /// Add the `openid` scope to the scope of [`Client::auth_url`] if missing. Disable it for
+    /// pure OAuth 2.0 providers, the scope is then sent verbatim.
+    pub inject_openid_scope: bool,
+
+    /// Reject a userinfo whose `email` differs from the `email` of the id_token, if both have
+    /// one, as it may have been substituted.
+    pub enforce_userinfo_email: bool,
+
+    /// Issuers whose tokens are accepted besides the issuer of the provider, e.g. the old issuer
+    /// during the cutover of a provider migration.
+    pub additional_issuers: Vec<Url>,
+
+    /// Notified of each token, userinfo and key set refresh request, e.g. to record metrics.
+    pub observer: Option<Arc<dyn ClientObserver>>,
+    jwks_refresh: Arc<JwksRefresh>,
+    key_secrets: Arc<KeySecrets>,
     marker: PhantomData<C>,
 }
 
+/// Wait after a failed key set fetch of [`Client::decode_token_refreshing`], unless the
+/// `jwks_refresh_interval` is shorter.
+const JWKS_FAILURE_BACKOFF: time::Duration = time::Duration::from_secs(10);
+
+/// Key set fetched by [`Client::decode_token_refreshing`], and when it was last fetched
+/// successfully.
+#[derive(Default)]
+struct JwksRefresh {
+    fetch: Mutex<JwksFetch>,
+    jwks: RwLock<Option<JWKSet<Empty>>>,
+}
+
+#[derive(Default)]
+struct JwksFetch {
+    last: Option<Instant>,
+    /// When the last fetch failed, to back off before the next one.
+    failed: Option<Instant>,
+    /// The fetch in flight, awaited by the decodes arriving meanwhile.
+    flight: Option<Arc<Flight>>,
+}
+
+/// A fetch in flight, whose waiters are woken once it is done. Wakers are None when done.
+struct Flight(Mutex<Option<Vec<Waker>>>);
+
+impl Flight {
+    fn new() -> Self {
+        Self(Mutex::new(Some(Vec::new())))
+    }
+
+    fn finish(&self) {
+        for waker in self.0.lock().unwrap().take().into_iter().flatten() {
+            waker.wake();
+        }
+    }
+
+    async fn wait(&self) {
+        // Index of the waker of this waiter, registered on the first poll
+        let mut slot: Option<usize> = None;
+        future::poll_fn(|cx| match *self.0.lock().unwrap() {
+            Some(ref mut wakers) => {
+                match slot {
+                    Some(i) if wakers[i].will_wake(cx.waker()) => {}
+                    Some(i) => wakers[i] = cx.waker().clone(),
+                    None => {
+                        slot = Some(wakers.len());
+                        wakers.push(cx.waker().clone());
+                    }
+                }
+                Poll::Pending
+            }
+            None => Poll::Ready(()),
+        })
+        .await
+    }
+
+    #[cfg(test)]
+    fn waiters(&self) -> usize {
+        self.0.lock().unwrap().as_ref().map_or(0, Vec::len)
+    }
+}
+
+/// Finishes the flight of the fetching decode, also if it fails or its future is dropped.
+struct FlightGuard<'a>(&'a JwksRefresh, Arc<Flight>);
+
+impl Drop for FlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch.lock().unwrap().flight = None;
+        self.1.finish();
+    }
+}
+
+/// Number of keys [`KeySecrets`] holds the secrets of, so keys of former key sets are dropped.
+const MAX_KEY_SECRETS: usize = 16;
+
+/// Secrets of the keys tokens were decoded with, so a secret is built and the certificate
+/// thumbprints of its key are verified once per key instead of on every decode. Looked up by key
+/// id, the cached key must equal the given one, so a key id reused for another key is rebuilt.
+#[derive(Default)]
+struct KeySecrets(RwLock<HashMap<Option<String>, KeySecret>>);
+
+/// A key and its secret.
+type KeySecret = (JWK<Empty>, Secret);
+
+impl KeySecrets {
+    /// Calls `f` with the secret of the key, building it on first use.
+    fn with_secret<R>(&self, key: &JWK<Empty>, f: impl FnOnce(&Secret) -> R) -> Result<R, Error> {
+        let kid = &key.common.key_id;
+        if let Some((_, secret)) = self.0.read().unwrap().get(kid).filter(|(k, _)| k == key) {
+            return Ok(f(secret));
+        }
+
+        certificate::verify_thumbprints(&serde_json::to_value(key)?)?;
+        let secret = key_secret(key)?;
+        let result = f(&secret);
+        let mut secrets = self.0.write().unwrap();
+        if secrets.len() >= MAX_KEY_SECRETS && !secrets.contains_key(kid) {
+            secrets.clear();
+        }
+        secrets.insert(kid.clone(), (key.clone(), secret));
+        Ok(result)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.0.read().unwrap().len()
+    }
+}
+
+/// The token endpoint response, unless it is an OAuth 2.0 error. Error responses have a non-2xx
+/// status, but some providers answer errors with 200, so a success response counts as error if it
+/// has an `error` and no `access_token`. A token response with an unrelated `error` field is kept.
+pub(crate) fn token_response(status: StatusCode, json: Value) -> Result<Value, ClientError> {
+    let access_token = json.get("access_token").or_else(|| json.get("accessToken"));
+    if status.is_success() && access_token.is_some() {
+        return Ok(json);
+    }
+    match serde_json::from_value::<OAuth2Error>(json.clone()) {
+        Ok(error) => Err(ClientError::from(error)),
+        Err(_) => Ok(json),
+    }
+}
+
+/// The error the provider redirected with, if any.
+fn redirect_error(redirect: &Url) -> Option<OAuth2Error> {
+    let query: serde_json::Map<String, Value> = redirect
+        .query_pairs()
+        .map(|(key, value)| (key.into_owned(), value.into_owned().into()))
+        .collect();
+    serde_json::from_value(query.into()).ok()
+}
+
+/// Errors with RequestAndRequestUri if the options pass the request object both by value and by
+/// reference.
+fn check_request_object(options: &Options) -> Result<(), Error> {
+    if options.request.is_some() && options.request_uri.is_some() {
+        return Err(Error::RequestAndRequestUri);
+    }
+    Ok(())
+}
+
+/// Parses the body of a token endpoint response. Legacy providers answer with a form-encoded
+/// body instead of JSON, its parameters become string values.
+pub(crate) fn token_body(content_type: Option<&str>, body: &[u8]) -> Result<Value, ClientError> {
+    let mime = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .unwrap_or_default()
+        .trim();
+    if mime.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+        let params = form_urlencoded::parse(body)
+            .map(|(key, value)| (key.into_owned(), Value::String(value.into_owned())))
+            .collect();
+        Ok(Value::Object(params))
+    } else {
+        Ok(serde_json::from_slice(body)?)
+    }
+}
+
+/// Checks the userinfo response is JSON or a JWT, if it has a Content-Type.
+pub(crate) fn validate_userinfo_content_type(content_type: Option<&str>) -> Result<(), Error> {
+    if let Some(content_type) = content_type {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        let expected = ["application/json", "application/jwt"];
+        if !expected.iter().any(|e| mime.eq_ignore_ascii_case(e)) {
+            return Err(ErrorUserinfo::UnexpectedContentType {
+                expected: expected.join(" or "),
+                actual: content_type.to_string(),
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Checks the userinfo subject matches the id_token subject, if both are given.
+pub(crate) fn validate_userinfo_subject<C: Claims>(
+    claims: Option<&C>,
+    info: &Userinfo,
+) -> Result<(), Error> {
+    if let (Some(claims), Some(info_sub)) = (claims, &info.sub) {
+        if claims.sub() != info_sub {
+            let expected = info_sub.clone();
+            let actual = claims.sub().to_string();
+            return Err(ErrorUserinfo::MismatchSubject { expected, actual }.into());
+        }
+    }
+    Ok(())
+}
+
+/// Checks the userinfo email matches the id_token email, if both are given.
+pub(crate) fn validate_userinfo_email<C: Claims>(
+    claims: Option<&C>,
+    info: &Userinfo,
+) -> Result<(), Error> {
+    let token_email = claims.and_then(|claims| claims.userinfo().email.as_ref());
+    if let (Some(token_email), Some(info_email)) = (token_email, &info.email) {
+        if token_email != info_email {
+            let expected = token_email.clone();
+            let actual = info_email.clone();
+            return Err(ErrorUserinfo::MismatchEmail { expected, actual }.into());
+        }
+    }
+    Ok(())
+}
+
+/// User-Agent of the HTTP clients created by this crate.
+pub(crate) const USER_AGENT: &str = concat!("openid-rs/", env!("CARGO_PKG_VERSION"));
+
+/// HTTP client used if none is given, identifying itself with the user agent.
+pub(crate) fn default_http_client(user_agent: &str) -> Result<reqwest::Client, Error> {
+    Ok(http_client_builder(user_agent).build()?)
+}
+
+/// Builder of the HTTP clients created by this crate, using rustls if its feature is enabled,
+/// even if native-tls is enabled as well.
+pub(crate) fn http_client_builder(user_agent: &str) -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder().user_agent(user_agent);
+    #[cfg(feature = "rustls")]
+    let builder = builder.use_rustls_tls();
+    builder
+}
+
 // Common pattern in the Client::decode function when dealing with mismatched keys
 macro_rules! wrong_key {
     ($expected:expr, $actual:expr) => {
@@ -51,6 +356,118 @@ macro_rules! wrong_key {
     };
 }
 
+/// Errors with Decode::EmptySet if a key set is given, but has no keys.
+pub(crate) fn check_jwks(jwks: Option<&JWKSet<Empty>>) -> Result<(), Error> {
+    match jwks {
+        Some(jwks) if jwks.keys.is_empty() => Err(Decode::EmptySet.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The key of the key set the token header refers to.
+fn select_key<'a>(
+    header: &Header<Empty>,
+    jwks: &'a JWKSet<Empty>,
+) -> Result<&'a JWK<Empty>, Error> {
+    // If there is more than one key, the token MUST have a key id
+    if jwks.keys.len() > 1 {
+        let token_kid = header.registered.key_id.clone().ok_or(Decode::MissingKid)?;
+        return jwks
+            .find(&token_kid)
+            .ok_or_else(|| Decode::MissingKey(token_kid).into());
+    }
+    // Only checked on construction by discovery, from_config and the builder, as new() can't
+    // return an error and the key set may be replaced
+    let key = jwks.keys.first().ok_or(Decode::EmptySet)?;
+    // A key id on both sides must still match
+    if let (Some(token_kid), Some(key_kid)) = (&header.registered.key_id, &key.common.key_id) {
+        if token_kid != key_kid {
+            return Err(Decode::MissingKey(token_kid.clone()).into());
+        }
+    }
+    Ok(key)
+}
+
+/// Checks that the key can verify tokens signed with the algorithm, by its `alg` and type.
+fn check_key_algorithm(key: &JWK<Empty>, alg: SignatureAlgorithm) -> Result<(), Error> {
+    if let Some(key_alg) = key.common.algorithm.as_ref() {
+        if let jwa::Algorithm::Signature(sig) = *key_alg {
+            if alg != sig {
+                return wrong_key!(sig, alg);
+            }
+        } else {
+            return wrong_key!(SignatureAlgorithm::default(), key_alg);
+        }
+    }
+
+    match key.algorithm {
+        AlgorithmParameters::OctetKey(_) => match alg {
+            SignatureAlgorithm::HS256 | SignatureAlgorithm::HS384 | SignatureAlgorithm::HS512 => {
+                Ok(())
+            }
+            _ => wrong_key!("HS256 | HS384 | HS512", alg),
+        },
+        AlgorithmParameters::RSA(_) => match alg {
+            SignatureAlgorithm::RS256 | SignatureAlgorithm::RS384 | SignatureAlgorithm::RS512 => {
+                Ok(())
+            }
+            _ => wrong_key!("RS256 | RS384 | RS512", alg),
+        },
+        // Rejected by key_secret
+        _ => Ok(()),
+    }
+}
+
+/// The secret verifying signatures of the key. Only HMAC and RSA keys are supported.
+fn key_secret(key: &JWK<Empty>) -> Result<Secret, Error> {
+    match key.algorithm {
+        AlgorithmParameters::OctetKey(ref parameters) => {
+            Ok(Secret::Bytes(parameters.value.clone()))
+        }
+        AlgorithmParameters::RSA(ref params) => Ok(Secret::RSAModulusExponent {
+            n: params.n.clone(),
+            e: params.e.clone(),
+        }),
+        AlgorithmParameters::EllipticCurve(_) => Err(Decode::UnsupportedEllipticCurve.into()),
+        AlgorithmParameters::OctetKeyPair(_) => Err(Decode::UnsupportedOctetKeyPair.into()),
+    }
+}
+
+/// Prints the client secret as `***`, so clients can be logged safely.
+impl<C: CompactJson + Claims, P: fmt::Debug> fmt::Debug for Client<P, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("provider", &self.provider)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"***")
+            .field("redirect_uri", &self.redirect_uri)
+            .field("redirect_uris", &self.redirect_uris)
+            .field("http_client", &self.http_client)
+            .field("jwks", &self.jwks)
+            .field(
+                "enforce_advertised_algorithms",
+                &self.enforce_advertised_algorithms,
+            )
+            .field("dpop", &self.dpop)
+            .field("client_auth", &self.client_auth)
+            .field("leeway", &self.leeway)
+            .field("allowed_algorithms", &self.allowed_algorithms)
+            .field(
+                "require_asymmetric_signing",
+                &self.require_asymmetric_signing,
+            )
+            .field("nonce_in_token_request", &self.nonce_in_token_request)
+            .field("enforce_id_token_typ", &self.enforce_id_token_typ)
+            .field("jwks_refresh_interval", &self.jwks_refresh_interval)
+            .field("jwks_uri", &self.jwks_uri)
+            .field("inject_openid_scope", &self.inject_openid_scope)
+            .field("enforce_userinfo_email", &self.enforce_userinfo_email)
+            .field("additional_issuers", &self.additional_issuers)
+            .field("observer", &self.observer.is_some())
+            .finish()
+    }
+}
+
 /// Implement clone if the provider can be cloned.
 impl<C: CompactJson + Claims, P: Clone> Clone for Client<P, C> {
     fn clone(&self) -> Self {
@@ -63,8 +480,25 @@ impl<C: CompactJson + Claims, P: Clone> Clone for Client<P, C> {
             client_id: self.client_id.clone(),
             client_secret: self.client_secret.clone(),
             redirect_uri: self.redirect_uri.as_ref().cloned(),
+            redirect_uris: self.redirect_uris.clone(),
             http_client: self.http_client.clone(),
             jwks,
+            enforce_advertised_algorithms: self.enforce_advertised_algorithms,
+            dpop: self.dpop.clone(),
+            client_auth: self.client_auth,
+            leeway: self.leeway,
+            allowed_algorithms: self.allowed_algorithms.clone(),
+            require_asymmetric_signing: self.require_asymmetric_signing,
+            nonce_in_token_request: self.nonce_in_token_request,
+            enforce_id_token_typ: self.enforce_id_token_typ,
+            jwks_refresh_interval: self.jwks_refresh_interval,
+            jwks_uri: self.jwks_uri.clone(),
+            inject_openid_scope: self.inject_openid_scope,
+            enforce_userinfo_email: self.enforce_userinfo_email,
+            additional_issuers: self.additional_issuers.clone(),
+            observer: self.observer.clone(),
+            jwks_refresh: self.jwks_refresh.clone(),
+            key_secrets: self.key_secrets.clone(),
             marker: PhantomData,
         }
     }
@@ -78,10 +512,14 @@ impl<C: CompactJson + Claims> Client<Discovered, C> {
         redirect: Option<String>,
         issuer: Url,
     ) -> Result<Self, Error> {
-        Self::discover_with_client(reqwest::Client::new(), id, secret, redirect, issuer).await
+        let http_client = default_http_client(USER_AGENT)?;
+        Self::discover_with_client(http_client, id, secret, redirect, issuer).await
     }
 
-    /// Constructs a client from an issuer url and client parameters via discovery
+    /// Constructs a client from an issuer url and client parameters via discovery. If the
+    /// provider has no `jwks_uri`, as some pure OAuth 2.0 servers, the client has no key set and
+    /// [`Client::decode_token`] does not verify tokens. Errors with Decode::EmptySet if the
+    /// provider's key set has no keys.
     pub async fn discover_with_client(
         http_client: reqwest::Client,
         id: String,
@@ -90,7 +528,67 @@ impl<C: CompactJson + Claims> Client<Discovered, C> {
         issuer: Url,
     ) -> Result<Self, Error> {
         let config = discovered::discover(&http_client, issuer).await?;
-        let jwks = discovered::jwks(&http_client, config.jwks_uri.clone()).await?;
+        let jwks = match config.jwks_uri {
+            Some(ref jwks_uri) => Some(discovered::jwks(&http_client, jwks_uri.clone()).await?),
+            None => None,
+        };
+
+        check_jwks(jwks.as_ref())?;
+
+        let provider = config.into();
+
+        Ok(Self::new(provider, id, secret, redirect, http_client, jwks))
+    }
+
+    /// Constructs a client from an issuer url and client parameters via discovery, sending all
+    /// requests through the given proxy.
+    pub async fn discover_with_proxy(
+        id: String,
+        secret: String,
+        redirect: Option<String>,
+        issuer: Url,
+        proxy_url: Url,
+    ) -> Result<Self, Error> {
+        let http_client = http_client_builder(USER_AGENT)
+            .proxy(reqwest::Proxy::all(proxy_url)?)
+            .build()?;
+        Self::discover_with_client(http_client, id, secret, redirect, issuer).await
+    }
+
+    /// Constructs a client from an already known config and key set, without any network calls.
+    /// Errors with Decode::EmptySet if the key set has no keys.
+    pub fn from_config(
+        config: Config,
+        jwks: Option<JWKSet<Empty>>,
+        id: String,
+        secret: String,
+        redirect: Option<String>,
+        http_client: reqwest::Client,
+    ) -> Result<Self, Error> {
+        check_jwks(jwks.as_ref())?;
+        Ok(Self::new(
+            config.into(),
+            id,
+            secret,
+            redirect,
+            http_client,
+            jwks,
+        ))
+    }
+
+    /// Constructs a client from an issuer url and client parameters via discovery, using the
+    /// given key set instead of fetching it from the provider. Errors with Decode::EmptySet if
+    /// the key set has no keys.
+    pub async fn discover_with_jwks(
+        id: String,
+        secret: String,
+        redirect: Option<String>,
+        issuer: Url,
+        jwks: JWKSet<Empty>,
+    ) -> Result<Self, Error> {
+        check_jwks(Some(&jwks))?;
+        let http_client = default_http_client(USER_AGENT)?;
+        let config = discovered::discover(&http_client, issuer).await?;
 
         let provider = config.into();
 
@@ -118,23 +616,71 @@ impl<C: CompactJson + Claims, P: Provider + Configurable> Client<P, C> {
         self.provider.config()
     }
 
+    /// Claims the provider advertises in `claims_supported`, e.g. to offer available profile
+    /// fields. None if the provider does not advertise them.
+    pub fn supported_userinfo_claims(&self) -> Option<&[String]> {
+        self.config().claims_supported.as_deref()
+    }
+
     /// Constructs the auth_url to redirect a client to the provider. Options are... optional. Use
     /// them as needed. Keep the Options struct around for authentication, or at least the nonce
     /// and max_age parameter - we need to verify they stay the same and validate if you used them.
+    ///
+    /// If the options set `request_uri` or `request`, the url only contains the client ID,
+    /// response type and scope besides it, the other parameters must be in the request object.
+    /// `request_uri` wins if both are set, see [`Client::try_auth_url`] to reject that instead.
     pub fn auth_url(&self, options: &Options) -> Url {
+        self.auth_url_with_redirect_uri(options, self.redirect_uri.as_deref())
+    }
+
+    /// Like [`Client::auth_url`], but errors with RequestAndRequestUri if the options set both
+    /// `request` and `request_uri`.
+    pub fn try_auth_url(&self, options: &Options) -> Result<Url, Error> {
+        check_request_object(options)?;
+        Ok(self.auth_url(options))
+    }
+
+    /// Like [`Client::auth_url`], redirecting to the given URI instead of `redirect_uri`. Errors
+    /// with UnregisteredRedirectUri if it is neither `redirect_uri` nor one of `redirect_uris`.
+    /// Request the token with the same URI, see [`Client::request_token_with_redirect`].
+    pub fn auth_url_with_redirect(
+        &self,
+        options: &Options,
+        redirect_uri: &str,
+    ) -> Result<Url, Error> {
+        let redirect_uri = self.registered_redirect_uri(redirect_uri)?;
+        check_request_object(options)?;
+        Ok(self.auth_url_with_redirect_uri(options, Some(redirect_uri)))
+    }
+
+    fn auth_url_with_redirect_uri(&self, options: &Options, redirect_uri: Option<&str>) -> Url {
         let scope = match options.scope {
             Some(ref scope) => {
-                if !scope.contains("openid") {
-                    String::from("openid ") + scope
+                if self.inject_openid_scope && !scope.contains("openid") {
+                    Some(String::from("openid ") + scope)
                 } else {
-                    scope.clone()
+                    Some(scope.clone())
                 }
             }
             // Default scope value
-            None => String::from("openid"),
+            None if self.inject_openid_scope => Some(String::from("openid")),
+            None => None,
+        };
+
+        // The parameters are passed in the request object
+        let request_object = match (&options.request_uri, &options.request) {
+            (Some(request_uri), _) => Some(("request_uri", request_uri)),
+            (None, Some(request)) => Some(("request", request)),
+            (None, None) => None,
         };
+        if let Some((name, value)) = request_object {
+            let mut url = self.auth_uri_with_redirect(scope.as_deref(), None, None);
+            url.query_pairs_mut().append_pair(name, value);
+            return url;
+        }
 
-        let mut url = self.auth_uri(Some(&scope), options.state.as_deref());
+        let mut url =
+            self.auth_uri_with_redirect(scope.as_deref(), options.state.as_deref(), redirect_uri);
         {
             let mut query = url.query_pairs_mut();
             if let Some(ref nonce) = options.nonce {
@@ -144,20 +690,25 @@ impl<C: CompactJson + Claims, P: Provider + Configurable> Client<P, C> {
                 query.append_pair("display", display.as_str());
             }
             if let Some(ref prompt) = options.prompt {
-                let s = prompt
-                    .iter()
-                    .map(|s| s.as_str())
-                    .collect::<Vec<_>>()
-                    .join(" ");
+                // Per spec, none must not be combined with other values, the others are dropped
+                let s = if prompt.contains(&Prompt::None) {
+                    Prompt::None.as_str().to_string()
+                } else {
+                    prompt
+                        .iter()
+                        .map(|s| s.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                };
                 query.append_pair("prompt", s.as_str());
             }
             if let Some(max_age) = options.max_age {
                 query.append_pair("max_age", max_age.num_seconds().to_string().as_str());
             }
-            if let Some(ref ui_locales) = options.ui_locales {
+            if let Some(ui_locales) = options.joined_ui_locales() {
                 query.append_pair("ui_locales", ui_locales.as_str());
             }
-            if let Some(ref claims_locales) = options.claims_locales {
+            if let Some(claims_locales) = options.joined_claims_locales() {
                 query.append_pair("claims_locales", claims_locales.as_str());
             }
             if let Some(ref id_token_hint) = options.id_token_hint {
@@ -166,21 +717,114 @@ impl<C: CompactJson + Claims, P: Provider + Configurable> Client<P, C> {
             if let Some(ref login_hint) = options.login_hint {
                 query.append_pair("login_hint", login_hint.as_str());
             }
-            if let Some(ref acr_values) = options.acr_values {
+            if let Some(acr_values) = options.joined_acr_values() {
                 query.append_pair("acr_values", acr_values.as_str());
             }
+            if let Some(ref claims) = options.claims {
+                query.append_pair("claims", claims.to_string().as_str());
+            }
         }
         url
     }
 
-    /// Given an auth_code and auth options, request the token, decode, and validate it.
+    /// Checks the claims requested by the `claims` parameter of the options are listed in the
+    /// `claims_supported` of the provider, as the provider may silently drop the others. Errors
+    /// with UnsupportedClaims listing the unsupported ones.
+    pub fn validate_claims_request(&self, options: &Options) -> Result<(), Error> {
+        let unsupported: Vec<String> = options
+            .requested_claims()
+            .into_iter()
+            .filter(|claim| !self.config().supports_claim(claim))
+            .map(String::from)
+            .collect();
+        if unsupported.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedClaims(unsupported))
+        }
+    }
+
+    /// The requested acr values of the options the provider does not list in its
+    /// `acr_values_supported`, e.g. to warn before sending an auth url it would not satisfy.
+    pub fn unsupported_acr_values(&self, options: &Options) -> Vec<String> {
+        options
+            .joined_acr_values()
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter(|acr_value| !self.config().supports_acr_value(acr_value))
+            .map(String::from)
+            .collect()
+    }
+
+    /// Like [`Client::auth_url`], but generates the state, nonce and a PKCE code challenge. The
+    /// state and nonce of the options are replaced by the generated ones. Keep the returned
+    /// session around to validate the response of the provider.
+    ///
+    /// # Panics
+    ///
+    /// If the system random number generator fails.
+    pub fn auth_url_with_session(&self, base_options: &Options) -> (Url, AuthSession) {
+        self.auth_url_with_session_redirect_uri(base_options, self.redirect_uri.as_deref())
+    }
+
+    /// Like [`Client::auth_url_with_session`], redirecting to the given URI instead of
+    /// `redirect_uri`. Errors with UnregisteredRedirectUri if it is neither `redirect_uri` nor one
+    /// of `redirect_uris`. The session keeps the URI for the token request.
+    ///
+    /// # Panics
+    ///
+    /// If the system random number generator fails.
+    pub fn auth_url_with_session_and_redirect(
+        &self,
+        base_options: &Options,
+        redirect_uri: &str,
+    ) -> Result<(Url, AuthSession), Error> {
+        let redirect_uri = self.registered_redirect_uri(redirect_uri)?;
+        Ok(self.auth_url_with_session_redirect_uri(base_options, Some(redirect_uri)))
+    }
+
+    fn auth_url_with_session_redirect_uri(
+        &self,
+        base_options: &Options,
+        redirect_uri: Option<&str>,
+    ) -> (Url, AuthSession) {
+        let session = AuthSession {
+            redirect_uri: redirect_uri.map(String::from),
+            ..AuthSession::generate(base_options.max_age)
+        };
+        let options = Options {
+            state: Some(session.state.clone()),
+            nonce: Some(session.nonce.clone()),
+            ..base_options.clone()
+        };
+
+        let mut url = self.auth_url_with_redirect_uri(&options, redirect_uri);
+        if let Some(code_challenge) = session.code_challenge() {
+            url.query_pairs_mut()
+                .append_pair("code_challenge", &code_challenge)
+                .append_pair("code_challenge_method", "S256");
+        }
+        (url, session)
+    }
+
+    /// Given an auth_code and auth options, request the token, decode, and validate it. Errors
+    /// with Missing::IdToken if the provider returned no id_token for an OpenID Connect flow.
     pub async fn authenticate(
         &self,
         auth_code: &str,
         nonce: Option<&str>,
         max_age: Option<&Duration>,
     ) -> Result<Token<C>, Error> {
-        let bearer = self.request_token(auth_code).await.map_err(Error::from)?;
+        let bearer = self
+            .request_authorization_code_token(
+                auth_code,
+                None,
+                nonce,
+                self.redirect_uri.as_deref(),
+                None,
+            )
+            .await?;
+        self.require_id_token(&bearer)?;
         let mut token: Token<C> = bearer.into();
         if let Some(id_token) = token.id_token.as_mut() {
             self.decode_token(id_token)?;
@@ -189,145 +833,821 @@ impl<C: CompactJson + Claims, P: Provider + Configurable> Client<P, C> {
         Ok(token)
     }
 
-    /// Mutates a Compact::encoded Token to Compact::decoded. Errors are:
-    ///
-    /// - Decode::MissingKid if the keyset has multiple keys but the key id on the token is missing
-    /// - Decode::MissingKey if the given key id is not in the key set
-    /// - Decode::EmptySet if the keyset is empty
-    /// - Jose::WrongKeyType if the alg of the key and the alg in the token header mismatch
-    /// - Jose::WrongKeyType if the specified key alg isn't a signature algorithm
-    /// - Jose error if decoding fails
-    pub fn decode_token(&self, token: &mut IdToken<C>) -> Result<(), Error> {
-        // This is an early return if the token is already decoded
-        if let Compact::Decoded { .. } = *token {
-            return Ok(());
+    /// Like [`Client::authenticate`], validating against the nonce and max_age of the session and
+    /// sending its PKCE code verifier and redirect URI with the token request.
+    pub async fn authenticate_session(
+        &self,
+        auth_code: &str,
+        session: &AuthSession,
+    ) -> Result<Token<C>, Error> {
+        let bearer = self
+            .request_authorization_code_token(
+                auth_code,
+                session.code_verifier.as_deref(),
+                Some(&session.nonce),
+                session
+                    .redirect_uri
+                    .as_deref()
+                    .or(self.redirect_uri.as_deref()),
+                None,
+            )
+            .await?;
+        self.require_id_token(&bearer)?;
+        let mut token: Token<C> = bearer.into();
+        if let Some(id_token) = token.id_token.as_mut() {
+            self.decode_token(id_token)?;
+            self.validate_token(id_token, Some(&session.nonce), session.max_age.as_ref())?;
         }
+        Ok(token)
+    }
 
-        if self.jwks.is_none() {
-            return Ok(());
+    /// The token response of an OpenID Connect flow must carry an id_token. The `openid` scope is
+    /// requested if [`Client::inject_openid_scope`] is set or the provider granted it, so a
+    /// missing id_token errors with Missing::IdToken instead of returning an unvalidated token.
+    /// Pure OAuth 2.0 clients use [`Client::request_token`] instead.
+    pub(crate) fn require_id_token(&self, bearer: &Bearer) -> Result<(), Error> {
+        let granted_openid = bearer
+            .scope
+            .as_deref()
+            .is_some_and(|scope| scope.split_whitespace().any(|scope| scope == "openid"));
+        if bearer.id_token.is_none() && (self.inject_openid_scope || granted_openid) {
+            return Err(Validation::Missing(Missing::IdToken).into());
         }
+        Ok(())
+    }
 
-        let jwks = self.jwks.as_ref().unwrap();
+    /// Like [`Client::authenticate_session`], for auth urls requesting offline access, e.g. with
+    /// the `offline_access` scope. Errors with MissingRefreshToken if the provider returned no
+    /// refresh token, as when the scope or `prompt=consent` was not sent.
+    pub async fn authorize_offline(
+        &self,
+        auth_code: &str,
+        session: &AuthSession,
+    ) -> Result<Token<C>, Error> {
+        let token = self.authenticate_session(auth_code, session).await?;
+        if token.bearer.refresh_token.is_none() {
+            return Err(Error::MissingRefreshToken);
+        }
+        Ok(token)
+    }
 
-        let header = token.unverified_header()?;
-        // If there is more than one key, the token MUST have a key id
-        let key = if jwks.keys.len() > 1 {
-            let token_kid = header.registered.key_id.ok_or(Decode::MissingKid)?;
-            jwks.find(&token_kid).ok_or(Decode::MissingKey(token_kid))?
-        } else {
-            // TODO We would want to verify the keyset is >1 in the constructor
-            // rather than every decode call, but we can't return an error in new().
-            jwks.keys.first().as_ref().ok_or(Decode::EmptySet)?
-        };
+    /// Like [`Client::auth_url_with_session`], keeping the session in the store under its state
+    /// instead of returning it. Complete the flow with [`Client::authenticate_with_store`].
+    ///
+    /// # Panics
+    ///
+    /// If the system random number generator fails.
+    pub fn auth_url_with_store(&self, options: &Options, store: &dyn SessionStore) -> Url {
+        let (url, session) = self.auth_url_with_session(options);
+        store.store(session.state.clone(), session);
+        url
+    }
 
-        if let Some(alg) = key.common.algorithm.as_ref() {
-            if let jwa::Algorithm::Signature(sig) = *alg {
-                if header.registered.algorithm != sig {
-                    return wrong_key!(sig, header.registered.algorithm);
-                }
-            } else {
-                return wrong_key!(SignatureAlgorithm::default(), alg);
+    /// Handles the redirect of the provider to an auth url of [`Client::auth_url_with_store`]:
+    /// the session is taken from the store by the state of the redirect, which is parsed with
+    /// [`Client::parse_authorization_response`] and the code exchanged with
+    /// [`Client::authenticate_session`]. Errors with UnknownSession if no session is stored for
+    /// the state, e.g. as it was already used.
+    pub async fn authenticate_with_store(
+        &self,
+        redirect: &Url,
+        store: &dyn SessionStore,
+    ) -> Result<Token<C>, Error> {
+        let state = redirect
+            .query_pairs()
+            .find(|(key, _)| key == "state")
+            .map(|(_, state)| state.into_owned());
+        let session = match state {
+            Some(state) => store.take(&state).ok_or(Error::UnknownSession(state))?,
+            // The provider may redirect with an error and without the state
+            None => {
+                return Err(match redirect_error(redirect) {
+                    Some(error) => ClientError::from(error).into(),
+                    None => Validation::Missing(Missing::State).into(),
+                })
             }
-        }
+        };
+        let code = self.parse_authorization_response(redirect, &session)?;
+        self.authenticate_session(&code, &session).await
+    }
 
-        let alg = header.registered.algorithm;
-        match key.algorithm {
-            // HMAC
-            AlgorithmParameters::OctetKey(ref parameters) => match alg {
-                SignatureAlgorithm::HS256
-                | SignatureAlgorithm::HS384
-                | SignatureAlgorithm::HS512 => {
-                    *token = token.decode(&Secret::Bytes(parameters.value.clone()), alg)?;
-                    Ok(())
-                }
-                _ => wrong_key!("HS256 | HS384 | HS512", alg),
-            },
-            AlgorithmParameters::RSA(ref params) => match alg {
-                SignatureAlgorithm::RS256
-                | SignatureAlgorithm::RS384
-                | SignatureAlgorithm::RS512 => {
-                    let pkcs = Secret::RSAModulusExponent {
-                        n: params.n.clone(),
-                        e: params.e.clone(),
-                    };
-                    *token = token.decode(&pkcs, alg)?;
-                    Ok(())
-                }
-                _ => wrong_key!("RS256 | RS384 | RS512", alg),
-            },
-            AlgorithmParameters::EllipticCurve(_) => Err(Decode::UnsupportedEllipticCurve.into()),
-            AlgorithmParameters::OctetKeyPair(_) => Err(Decode::UnsupportedOctetKeyPair.into()),
-        }
+    /// Checks the redirect of the provider to the `post_logout_redirect_uri` after an RP-initiated
+    /// logout: its `state` must be the one sent with the logout request. Errors with
+    /// Validation::Mismatch::State or Validation::Missing::State otherwise.
+    ///
+    /// See [RP-Initiated Logout, section 3](https://openid.net/specs/openid-connect-rpinitiated-1_0.html#RedirectionAfterLogout)
+    pub fn parse_logout_response(&self, redirect: &Url, expected_state: &str) -> Result<(), Error> {
+        let state = redirect
+            .query_pairs()
+            .find(|(key, _)| key == "state")
+            .map(|(_, state)| state);
+        validate_state(expected_state, state.as_deref())
     }
 
-    /// Validate a decoded token. If you don't get an error, its valid! Nonce and max_age come from
-    /// your auth_uri options. Errors are:
+    /// Parses the redirect of the provider in the authorization code flow, returning the code to
+    /// exchange for a token. Returns the error the provider redirected with, if any.
     ///
-    /// - Jose Error if the Token isn't decoded
-    /// - Validation::Mismatch::Issuer if the provider issuer and token issuer mismatch
-    /// - Validation::Mismatch::Nonce if a given nonce and the token nonce mismatch
-    /// - Validation::Missing::Nonce if either the token or args has a nonce and the other does not
-    /// - Validation::Missing::Audience if the token aud doesn't contain the client id
-    /// - Validation::Missing::AuthorizedParty if there are multiple audiences and azp is missing
-    /// - Validation::Mismatch::AuthorizedParty if the azp is not the client_id
-    /// - Validation::Expired::Expires if the current time is past the expiration time
-    /// - Validation::Expired::MaxAge is the token is older than the provided max_age
-    /// - Validation::Missing::Authtime if a max_age was given and the token has no auth time
-    pub fn validate_token(
+    /// The state must match the session. The `iss` parameter must equal the issuer as string if
+    /// present, and is required if the provider advertises
+    /// `authorization_response_iss_parameter_supported`, to defend against mix-up attacks.
+    ///
+    /// See [RFC 9207](https://www.rfc-editor.org/rfc/rfc9207)
+    pub fn parse_authorization_response(
         &self,
-        token: &IdToken<C>,
-        nonce: Option<&str>,
-        max_age: Option<&Duration>,
-    ) -> Result<(), Error> {
-        let claims = token.payload()?;
-        let config = self.config();
+        redirect: &Url,
+        session: &AuthSession,
+    ) -> Result<String, Error> {
+        if let Some(error) = redirect_error(redirect) {
+            return Err(ClientError::from(error).into());
+        }
+        let mut query: serde_json::Map<String, Value> = redirect
+            .query_pairs()
+            .map(|(key, value)| (key.into_owned(), value.into_owned().into()))
+            .collect();
 
-        validate_token_issuer(claims, config)?;
+        validate_state(&session.state, query.get("state").and_then(Value::as_str))?;
 
-        validate_token_nonce(claims, nonce)?;
+        let config = self.config();
+        match query.get("iss").and_then(Value::as_str) {
+            // Compared as strings, see RFC 9207 section 2.4. The issuer is parsed as url, which
+            // adds a trailing slash to a bare origin, so also accept it without.
+            Some(actual)
+                if actual != config.issuer.as_str()
+                    && (config.issuer.path() != "/"
+                        || Some(actual) != config.issuer.as_str().strip_suffix('/')) =>
+            {
+                let expected = config.issuer.to_string();
+                let actual = actual.to_string();
+                return Err(
+                    Validation::Mismatch(Mismatch::ResponseIssuer { expected, actual }).into(),
+                );
+            }
+            None if config.authorization_response_iss_parameter_supported => {
+                return Err(Validation::Missing(Missing::ResponseIssuer).into());
+            }
+            _ => {}
+        }
 
-        validate_token_aud(claims, &self.client_id)?;
+        match query.remove("code") {
+            Some(Value::String(code)) => Ok(code),
+            _ => Err(Validation::Missing(Missing::AuthorizationCode).into()),
+        }
+    }
+
+    /// Parses the fragment of a redirect in the implicit or hybrid flow, e.g. for
+    /// `response_type=id_token token`. The state must match the session, the id_token is decoded
+    /// and validated against the session.
+    ///
+    /// Returns [`FragmentResponse::Token`] if the fragment has an access_token, which then must
+    /// match the at_hash of the id_token, otherwise [`FragmentResponse::IdToken`], e.g. for
+    /// `response_type=id_token`.
+    ///
+    /// See [spec 3.2.2.5](https://openid.net/specs/openid-connect-core-1_0.html#ImplicitAuthResponse)
+    pub fn parse_fragment_response(
+        &self,
+        fragment: &str,
+        session: &AuthSession,
+    ) -> Result<FragmentResponse<C>, Error> {
+        let fragment = fragment.strip_prefix('#').unwrap_or(fragment);
+        let json: serde_json::Map<String, Value> = form_urlencoded::parse(fragment.as_bytes())
+            .map(|(key, value)| (key.into_owned(), value.into_owned().into()))
+            .collect();
+        if let Ok(error) = serde_json::from_value::<OAuth2Error>(json.clone().into()) {
+            return Err(ClientError::from(error).into());
+        }
+
+        validate_state(&session.state, json.get("state").and_then(Value::as_str))?;
+        let mut id_token: IdToken<C> = match json.get("id_token").and_then(Value::as_str) {
+            Some(id_token) => Compact::new_encoded(id_token),
+            None => return Err(Validation::Missing(Missing::IdToken).into()),
+        };
+        self.decode_token(&mut id_token)?;
+        self.validate_token(&id_token, Some(&session.nonce), session.max_age.as_ref())?;
+        if !json.contains_key("access_token") {
+            return Ok(FragmentResponse::IdToken(id_token));
+        }
+
+        let bearer = Bearer::from_response(json.into(), Utc::now())?;
+        let alg = id_token.header()?.registered.algorithm;
+        validate_token_at_hash(id_token.payload()?, alg, &bearer.access_token)?;
+        Ok(FragmentResponse::Token(Token {
+            bearer,
+            id_token: Some(id_token),
+        }))
+    }
+
+    /// Mutates a Compact::encoded Token to Compact::decoded. Errors are:
+    ///
+    /// - Decode::MissingKid if the keyset has multiple keys but the key id on the token is missing
+    /// - Decode::MissingKey if the given key id is not in the key set, also if the key set has a
+    ///   single key with a different key id
+    /// - Decode::EmptySet if the keyset is empty
+    /// - Decode::UnexpectedType if `enforce_id_token_typ` is set and the token typ is not `JWT`
+    /// - Decode::SymmetricAlgorithm if `require_asymmetric_signing` is set and the token alg is
+    ///   HS* or none
+    /// - Decode::DisallowedAlgorithm if the token alg is not in `allowed_algorithms`
+    /// - Decode::UnadvertisedAlgorithm if `enforce_advertised_algorithms` is set and the provider
+    ///   does not advertise the token alg
+    /// - Decode::ThumbprintMismatch if the x5t of the key does not match its x5c certificate
+    /// - Jose::WrongKeyType if the alg of the key and the alg in the token header mismatch
+    /// - Jose::WrongKeyType if the specified key alg isn't a signature algorithm
+    /// - Jose error if decoding fails
+    ///
+    /// Once [`Client::decode_token_refreshing`] fetched the key set, tokens are decoded with the
+    /// fetched keys instead of `jwks`.
+    pub fn decode_token(&self, token: &mut IdToken<C>) -> Result<(), Error> {
+        self.with_jwks(|jwks| match jwks {
+            Some(jwks) => self.decode_token_with(token, jwks),
+            None => Ok(()),
+        })
+    }
+
+    /// Calls `f` with the current key set: the one of the last refresh of
+    /// [`Client::decode_token_refreshing`], or `jwks` if there was none yet.
+    fn with_jwks<R>(&self, f: impl FnOnce(Option<&JWKSet<Empty>>) -> R) -> R {
+        match *self.jwks_refresh.jwks.read().unwrap() {
+            Some(ref jwks) => f(Some(jwks)),
+            None => f(self.jwks.as_ref()),
+        }
+    }
+
+    /// Like [`Client::decode_token`], but if the token refers to a key id not in the key set, as
+    /// during a key rollover of the provider, the key set is fetched again and decoding retried.
+    ///
+    /// The key set is fetched at most once per `jwks_refresh_interval`, shared by all clones of
+    /// the client, and used by all decodes of the client from then on. After a failed fetch the
+    /// next one waits a shorter backoff of at most 10 seconds instead. Tokens with an unknown key
+    /// id arriving while the key set is fetched wait for that fetch and are decoded with its
+    /// keys. Those arriving later in the interval fail with Decode::MissingKey, so a burst of them
+    /// can't flood the provider with requests, also while its key set endpoint is down.
+    pub async fn decode_token_refreshing(&self, token: &mut IdToken<C>) -> Result<(), Error> {
+        let result = self.decode_token(token);
+        if !matches!(result, Err(Error::Decode(Decode::MissingKey(_)))) {
+            return result;
+        }
+
+        let guard = {
+            let mut fetch = self.jwks_refresh.fetch.lock().unwrap();
+            match fetch.flight.clone() {
+                Some(flight) => Err(flight),
+                None => {
+                    let interval = self.jwks_refresh_interval.to_std().unwrap_or_default();
+                    let backoff = interval.min(JWKS_FAILURE_BACKOFF);
+                    if matches!(fetch.last, Some(last) if last.elapsed() < interval)
+                        || matches!(fetch.failed, Some(failed) if failed.elapsed() < backoff)
+                    {
+                        return result;
+                    }
+                    let flight = Arc::new(Flight::new());
+                    fetch.flight = Some(flight.clone());
+                    Ok(FlightGuard(&self.jwks_refresh, flight))
+                }
+            }
+        };
+        let _guard = match guard {
+            Ok(guard) => guard,
+            // Another decode is fetching the key set, so decode with its keys once it is done
+            Err(flight) => {
+                flight.wait().await;
+                return self.decode_token(token);
+            }
+        };
+
+        let jwks_uri = match self.jwks_uri.as_ref().or(self.config().jwks_uri.as_ref()) {
+            Some(jwks_uri) => jwks_uri.clone(),
+            None => return result,
+        };
+        let jwks =
+            discovered::jwks_observed(&self.http_client, jwks_uri, self.observer.as_deref()).await;
+        let jwks = {
+            let mut fetch = self.jwks_refresh.fetch.lock().unwrap();
+            match jwks {
+                Ok(jwks) => {
+                    fetch.last = Some(Instant::now());
+                    jwks
+                }
+                Err(error) => {
+                    fetch.failed = Some(Instant::now());
+                    return Err(error);
+                }
+            }
+        };
+        let result = self.decode_token_with(token, &jwks);
+        *self.jwks_refresh.jwks.write().unwrap() = Some(jwks);
+        result
+    }
+
+    /// Builds the secrets of the keys of the current key set ahead of the first token, skipping
+    /// keys that can't verify tokens.
+    pub(crate) fn prepare_key_secrets(&self) {
+        self.with_jwks(|jwks| {
+            for key in jwks.iter().flat_map(|jwks| &jwks.keys) {
+                let _ = self.key_secrets.with_secret(key, |_| ());
+            }
+        });
+    }
+
+    /// Checks the `typ` and `alg` of the token header against the settings of the client.
+    fn check_token_header(&self, header: &Header<Empty>) -> Result<(), Error> {
+        if self.enforce_id_token_typ {
+            if let Some(typ) = header.registered.media_type.as_ref() {
+                if !typ.eq_ignore_ascii_case("jwt") {
+                    return Err(Decode::UnexpectedType(typ.clone()).into());
+                }
+            }
+        }
+
+        if self.require_asymmetric_signing {
+            let alg = header.registered.algorithm;
+            if matches!(
+                alg,
+                SignatureAlgorithm::None
+                    | SignatureAlgorithm::HS256
+                    | SignatureAlgorithm::HS384
+                    | SignatureAlgorithm::HS512
+            ) {
+                let alg = serde_json::to_value(alg)?;
+                let alg = alg.as_str().unwrap_or_default().to_string();
+                return Err(Decode::SymmetricAlgorithm(alg).into());
+            }
+        }
+
+        if let Some(ref allowed) = self.allowed_algorithms {
+            if !allowed.contains(&header.registered.algorithm) {
+                let alg = serde_json::to_value(header.registered.algorithm)?;
+                let alg = alg.as_str().unwrap_or_default().to_string();
+                return Err(Decode::DisallowedAlgorithm(alg).into());
+            }
+        }
+
+        let advertised = &self.config().id_token_signing_alg_values_supported;
+        if self.enforce_advertised_algorithms && !advertised.is_empty() {
+            let alg = serde_json::to_value(header.registered.algorithm)?;
+            let alg = alg.as_str().unwrap_or_default();
+            if !advertised.iter().any(|advertised| advertised == alg) {
+                return Err(Decode::UnadvertisedAlgorithm(alg.to_string()).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Like [`Client::decode_token`], verifying the signature with the given key instead of a key
+    /// of the key set, e.g. a public key loaded from PEM. The token alg must be the given one,
+    /// otherwise Jose::WrongKeyType is returned. The other errors are those of `decode_token`,
+    /// except for the ones about the key set.
+    pub fn decode_token_with_key(
+        &self,
+        token: &mut IdToken<C>,
+        secret: &Secret,
+        alg: SignatureAlgorithm,
+    ) -> Result<(), Error> {
+        // This is an early return if the token is already decoded
+        if let Compact::Decoded { .. } = *token {
+            return Ok(());
+        }
+
+        let header = token.unverified_header()?;
+        self.check_token_header(&header)?;
+        if header.registered.algorithm != alg {
+            return wrong_key!(alg, header.registered.algorithm);
+        }
+
+        *token = token.decode(secret, alg)?;
+        Ok(())
+    }
+
+    fn decode_token_with<T: CompactJson>(
+        &self,
+        token: &mut Compact<T, Empty>,
+        jwks: &JWKSet<Empty>,
+    ) -> Result<(), Error> {
+        // This is an early return if the token is already decoded
+        if let Compact::Decoded { .. } = *token {
+            return Ok(());
+        }
 
-        validate_token_exp(claims, max_age)?;
+        let header = token.unverified_header()?;
+        self.check_token_header(&header)?;
+        let key = select_key(&header, jwks)?;
+        check_key_algorithm(key, header.registered.algorithm)?;
 
+        let alg = header.registered.algorithm;
+        *token = self
+            .key_secrets
+            .with_secret(key, |secret| token.decode(secret, alg))??;
         Ok(())
     }
 
+    /// Verifies the signature of a JWT against the key set without validating any claims, e.g. for
+    /// debugging. Returns false if the signature does not match. Errors if the token can't be
+    /// checked at all, e.g. if it is malformed or its key is not in the key set.
+    pub fn verify_signature(&self, jwt: &str) -> Result<bool, Error> {
+        // Any claims are accepted, they are not validated
+        let mut token = Compact::<Empty, Empty>::new_encoded(jwt);
+        let result = self.with_jwks(|jwks| {
+            let jwks = jwks.ok_or(Decode::EmptySet)?;
+            self.decode_token_with(&mut token, jwks)
+        });
+        match result {
+            Ok(()) => Ok(true),
+            Err(Error::Jose(
+                Jose::ValidationError(ValidationError::InvalidSignature)
+                | Jose::UnspecifiedCryptographicError,
+            )) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Validate a decoded token. If you don't get an error, its valid! Nonce and max_age come from
+    /// your auth_uri options. Errors are:
+    ///
+    /// - Jose Error if the Token isn't decoded
+    /// - Validation::Mismatch::Issuer if the token issuer is neither the provider issuer nor one
+    ///   of the `additional_issuers`
+    /// - Validation::Missing::Subject if the token has an empty sub
+    /// - Validation::Mismatch::Nonce if a given nonce and the token nonce mismatch
+    /// - Validation::Missing::Nonce if either the token or args has a nonce and the other does not
+    /// - Validation::Missing::Audience if the token aud doesn't contain the client id
+    /// - Validation::Missing::AuthorizedParty if there are multiple audiences and azp is missing
+    /// - Validation::Mismatch::AuthorizedParty if the azp is not the client_id
+    /// - Validation::Expired::Expires if the current time is past the expiration time plus leeway
+    /// - Validation::Expired::MaxAge is the token is older than the provided max_age plus leeway
+    /// - Validation::Missing::Authtime if a max_age was given and the token has no auth time
+    pub fn validate_token(
+        &self,
+        token: &IdToken<C>,
+        nonce: Option<&str>,
+        max_age: Option<&Duration>,
+    ) -> Result<(), Error> {
+        trace::validation(|| {
+            let claims = token.payload()?;
+            let config = self.config();
+
+            validate_token_issuer_in(claims, config, &self.additional_issuers)?;
+
+            validate_token_subject(claims)?;
+
+            validate_token_nonce(claims, nonce)?;
+
+            validate_token_aud(claims, &self.client_id)?;
+
+            validate_token_exp_with_leeway(claims, max_age, &self.leeway)?;
+
+            Ok(())
+        })
+    }
+
+    /// Checks a validated token for a step-up before a sensitive operation: its acr must be the
+    /// required one and the user must have authenticated within max_age, plus leeway. Errors
+    /// with StepUpRequired carrying the unmet requirements, whose [`StepUp::options`] request a
+    /// new authentication, or
+    /// with Jose if the token is not decoded.
+    pub fn require_step_up(
+        &self,
+        token: &IdToken<C>,
+        required_acr: &str,
+        max_age: &Duration,
+    ) -> Result<(), Error> {
+        let claims = token.payload()?;
+        let acr_met = claims.acr().is_some_and(|acr| acr == required_acr);
+        let fresh = claims.auth_time().is_some_and(|auth_time| {
+            let age = Duration::seconds(Utc::now().timestamp() - auth_time);
+            age < *max_age + self.leeway
+        });
+        if acr_met && fresh {
+            return Ok(());
+        }
+        Err(Error::StepUpRequired(StepUp {
+            acr: (!acr_met).then(|| required_acr.to_string()),
+            max_age: (!fresh).then_some(*max_age),
+        }))
+    }
+
+    /// Non-fatal counterpart of [`Client::validate_token`], returning warnings about a token that
+    /// may still be valid, e.g. a missing `auth_time`, to diagnose provider quirks. Errors with
+    /// Jose if the token is not decoded.
+    pub fn lint_token(
+        &self,
+        token: &IdToken<C>,
+        max_age: Option<&Duration>,
+    ) -> Result<Vec<TokenWarning>, Error> {
+        let claims = token.payload()?;
+        Ok(lint_token(claims, self.config(), max_age))
+    }
+
+    /// Like [`Client::validate_token`], but runs all checks instead of stopping at the first
+    /// failure. All failures are returned in a Validation::Multiple, so you can see everything
+    /// wrong with a token at once.
+    pub fn validate_token_all(
+        &self,
+        token: &IdToken<C>,
+        nonce: Option<&str>,
+        max_age: Option<&Duration>,
+    ) -> Result<(), Error> {
+        trace::validation(|| {
+            let claims = token.payload()?;
+            let config = self.config();
+
+            let results = [
+                validate_token_issuer_in(claims, config, &self.additional_issuers),
+                validate_token_subject(claims),
+                validate_token_nonce(claims, nonce),
+                validate_token_aud(claims, &self.client_id),
+                validate_token_exp_with_leeway(claims, max_age, &self.leeway),
+            ];
+            let mut failures = Vec::new();
+            for result in results {
+                match result {
+                    Ok(()) => {}
+                    Err(Error::Validation(failure)) => failures.push(failure),
+                    Err(error) => return Err(error),
+                }
+            }
+
+            if failures.is_empty() {
+                Ok(())
+            } else {
+                Err(Validation::Multiple(failures).into())
+            }
+        })
+    }
+
+    /// Validate that a certificate-bound access token was issued for the presented client
+    /// certificate, by comparing its `cnf.x5t#S256` claim to the base64url encoded SHA-256
+    /// thumbprint of the certificate.
+    ///
+    /// See [RFC 8705, section 3](https://www.rfc-editor.org/rfc/rfc8705#section-3).
+    ///
+    /// # Errors
+    ///
+    /// - Validation::Missing::CertificateThumbprint if the claims contain no thumbprint
+    /// - Validation::Mismatch::CertificateThumbprint if the thumbprints mismatch
+    pub fn validate_certificate_binding(
+        &self,
+        token_claims: &Value,
+        cert_thumbprint: &str,
+    ) -> Result<(), Error> {
+        validate_token_certificate_binding(token_claims, cert_thumbprint)
+    }
+
+    /// Ensures the access token of an OpenID Connect token is valid by refreshing it if necessary.
+    /// Errors with MissingRefreshToken if it expired and there is no refresh_token.
+    ///
+    /// See [`Client::refresh_token_full`] for how a new id_token is handled.
+    pub async fn ensure_and_revalidate(&self, token: Token<C>) -> Result<Token<C>, Error> {
+        if !token.bearer.expired() {
+            return Ok(token);
+        }
+        self.refresh_token_full(token, None).await
+    }
+
+    /// Like [`Client::refresh_token`], keeping the id_token.
+    ///
+    /// If the refresh response contains a new id_token, it is decoded and validated, and checked
+    /// against the previous id_token with [`validate_refreshed_token`]: a different `iss`, `sub`,
+    /// `aud`, `azp` or `auth_time` errors with Validation::Mismatch::RefreshedToken. Otherwise the
+    /// previous id_token is kept.
+    ///
+    /// Errors with MissingRefreshToken if the token has no refresh_token.
+    pub async fn refresh_token_full(
+        &self,
+        token: Token<C>,
+        scope: Option<&str>,
+    ) -> Result<Token<C>, Error> {
+        if token.bearer.refresh_token.is_none() {
+            return Err(Error::MissingRefreshToken);
+        }
+        let Token {
+            bearer,
+            id_token: mut previous,
+        } = token;
+        let previous_id_token = bearer.id_token.clone();
+
+        let mut bearer = self.refresh_token(bearer, scope).await?;
+        if bearer.id_token.is_none() {
+            bearer.id_token = previous_id_token;
+            return Ok(Token {
+                bearer,
+                id_token: previous,
+            });
+        }
+
+        let mut token: Token<C> = bearer.into();
+        if let Some(id_token) = token.id_token.as_mut() {
+            self.decode_token(id_token)?;
+            let refreshed = id_token.payload()?;
+            // The nonce is compared with the previous id_token instead
+            self.validate_token(id_token, refreshed.nonce().map(String::as_str), None)?;
+            if let Some(previous) = previous.as_mut() {
+                self.decode_token(previous)?;
+                validate_refreshed_token(previous.payload()?, refreshed)?;
+            }
+        }
+        Ok(token)
+    }
+
     /// Get a userinfo json document for a given token at the provider's userinfo endpoint.
     /// Errors are:
     ///
     /// - Userinfo::NoUrl if this provider doesn't have a userinfo endpoint
     /// - Error::Insecure if the userinfo url is not https
     /// - Error::Jose if the token is not decoded
-    /// - Error::Http if something goes wrong getting the document
+    /// - Error::Http if something goes wrong getting the document, including error statuses
     /// - Error::Json if the response is not a valid Userinfo document
     /// - Userinfo::MismatchSubject if the returned userinfo document and tokens subject mismatch
+    /// - Userinfo::MismatchEmail if `enforce_userinfo_email` is set and the returned userinfo
+    ///   document and tokens email mismatch
     pub async fn request_userinfo(&self, token: &Token<C>) -> Result<Userinfo, Error> {
-        match self.config().userinfo_endpoint {
-            Some(ref url) => {
-                let claims = token.id_token.as_ref().map(|x| x.payload()).transpose()?;
-                let auth_code = token.bearer.access_token.to_string();
-                let resp = self
-                    .http_client
-                    .get(url.clone())
-                    .bearer_auth(auth_code)
-                    .send()
-                    .await?;
-                let info: Userinfo = resp.json().await?;
-                if let Some(claims) = claims {
-                    if let Some(info_sub) = &info.sub {
-                        if claims.sub() != info_sub {
-                            let expected = info_sub.clone();
-                            let actual = claims.sub().to_string();
-                            return Err(ErrorUserinfo::MismatchSubject { expected, actual }.into());
-                        }
-                    }
-                }
+        self.request_userinfo_with_timeout(token, None).await
+    }
+
+    /// Like [`Client::request_userinfo`], but if the provider has no userinfo endpoint, the
+    /// userinfo is taken from the claims of the id_token instead, which often carries the profile
+    /// claims. The id_token must be decoded and validated. Errors with Userinfo::NoUrl if there
+    /// is neither an endpoint nor an id_token.
+    pub async fn userinfo_or_idtoken(&self, token: &Token<C>) -> Result<Userinfo, Error> {
+        if self.config().userinfo_endpoint.is_some() {
+            return self.request_userinfo(token).await;
+        }
+        match token.id_token {
+            Some(ref id_token) => {
+                let claims = id_token.payload()?;
+                let mut info = claims.userinfo().clone();
+                // The subject is a claim of the token, not part of its userinfo
+                info.sub = Some(claims.sub().to_string());
                 Ok(info)
             }
             None => Err(ErrorUserinfo::NoUrl.into()),
         }
     }
+
+    /// Like [`Client::request_userinfo`], aborting after the given timeout instead of the timeout
+    /// of the http client.
+    pub async fn request_userinfo_with_timeout(
+        &self,
+        token: &Token<C>,
+        timeout: Option<time::Duration>,
+    ) -> Result<Userinfo, Error> {
+        let claims = token.id_token.as_ref().map(|x| x.payload()).transpose()?;
+        let info = self.userinfo(&token.bearer.access_token, timeout).await?;
+        self.check_userinfo(claims, &info)?;
+        Ok(info)
+    }
+
+    /// Get the userinfo using just an access token, e.g. one received from a gateway. As there is
+    /// no id_token, the subject of the userinfo is not checked.
+    pub async fn request_userinfo_with_access_token(
+        &self,
+        access_token: &str,
+    ) -> Result<Userinfo, Error> {
+        self.userinfo(access_token, None).await
+    }
+
+    /// Like [`Client::request_userinfo`], sending the ETag of the cached userinfo as
+    /// `If-None-Match`. If the provider answers 304 Not Modified, the cached userinfo is returned,
+    /// otherwise the cache is updated with the response and its ETag.
+    pub async fn request_userinfo_cached(
+        &self,
+        token: &Token<C>,
+        cache: &mut UserinfoCache,
+    ) -> Result<Userinfo, Error> {
+        let claims = token.id_token.as_ref().map(|x| x.payload()).transpose()?;
+        let info = self
+            .userinfo_cached(&token.bearer.access_token, None, Some(cache))
+            .await?;
+        self.check_userinfo(claims, &info)?;
+        Ok(info)
+    }
+
+    /// Checks the subject and, if `enforce_userinfo_email` is set, the email of the userinfo
+    /// against the claims of the id_token.
+    pub(crate) fn check_userinfo(&self, claims: Option<&C>, info: &Userinfo) -> Result<(), Error> {
+        validate_userinfo_subject(claims, info)?;
+        if self.enforce_userinfo_email {
+            validate_userinfo_email(claims, info)?;
+        }
+        Ok(())
+    }
+
+    async fn userinfo(
+        &self,
+        access_token: &str,
+        timeout: Option<time::Duration>,
+    ) -> Result<Userinfo, Error> {
+        self.userinfo_cached(access_token, timeout, None).await
+    }
+
+    async fn userinfo_cached(
+        &self,
+        access_token: &str,
+        timeout: Option<time::Duration>,
+        cache: Option<&mut UserinfoCache>,
+    ) -> Result<Userinfo, Error> {
+        let url = self
+            .config()
+            .userinfo_endpoint
+            .as_ref()
+            .ok_or(ErrorUserinfo::NoUrl)?;
+        let etag = cache.as_deref().and_then(|cache| {
+            cache.userinfo.as_ref()?;
+            cache.etag.clone()
+        });
+        let request = |dpop_nonce: Option<&str>| {
+            let request = self.userinfo_request(url, access_token, dpop_nonce, timeout)?;
+            Ok::<_, Error>(match etag {
+                Some(ref etag) => request.header(IF_NONE_MATCH, etag),
+                None => request,
+            })
+        };
+
+        trace::request(Operation::Userinfo, url, self.observer.as_deref(), async {
+            let mut resp = request(None)?.send().await?;
+            // The server asks to retry with its nonce in the DPoP proof
+            if self.dpop.is_some() && resp.status() == StatusCode::UNAUTHORIZED {
+                let dpop_nonce = resp
+                    .headers()
+                    .get(DPOP_NONCE)
+                    .and_then(|nonce| nonce.to_str().ok())
+                    .map(String::from);
+                if let Some(dpop_nonce) = dpop_nonce {
+                    resp = request(Some(&dpop_nonce))?.send().await?;
+                }
+            }
+            // Only sent with a cached userinfo
+            if etag.is_some() && resp.status() == StatusCode::NOT_MODIFIED {
+                if let Some(info) = cache.as_deref().and_then(|cache| cache.userinfo.clone()) {
+                    return Ok(info);
+                }
+            }
+            let resp = resp.error_for_status()?;
+            let content_type = resp
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|content_type| content_type.to_str().ok());
+            validate_userinfo_content_type(content_type)?;
+            let new_etag = resp
+                .headers()
+                .get(ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .map(String::from);
+            let info: Userinfo = resp.json().await?;
+            if let Some(cache) = cache {
+                cache.etag = new_etag;
+                cache.userinfo = Some(info.clone());
+            }
+            Ok(info)
+        })
+        .await
+    }
+
+    fn userinfo_request(
+        &self,
+        url: &Url,
+        access_token: &str,
+        dpop_nonce: Option<&str>,
+        timeout: Option<time::Duration>,
+    ) -> Result<reqwest::RequestBuilder, Error> {
+        let mut request = self.http_client.get(url.clone());
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        Ok(match self.dpop {
+            Some(ref dpop) => request
+                .header(AUTHORIZATION, format!("DPoP {}", access_token))
+                .header(
+                    "DPoP",
+                    dpop.proof("GET", url, Some(access_token), dpop_nonce)?,
+                ),
+            None => request.bearer_auth(access_token),
+        })
+    }
+
+    /// Like [`Client::request_userinfo`], but if the access token is rejected with 401
+    /// Unauthorized, the bearer is refreshed using its refresh token and the request is retried
+    /// once. If there is no refresh token or refreshing fails, the original error is returned.
+    pub async fn request_userinfo_auto_refresh(
+        &self,
+        token: &mut Token<C>,
+    ) -> Result<Userinfo, Error> {
+        let error = match self.request_userinfo(token).await {
+            Err(Error::Http(error)) if error.status() == Some(StatusCode::UNAUTHORIZED) => error,
+            result => return result,
+        };
+
+        if token.bearer.refresh_token.is_none() {
+            return Err(error.into());
+        }
+        match self.refresh_token(token.bearer.clone(), None).await {
+            Ok(mut bearer) => {
+                // Keep the raw id_token matching the decoded one
+                bearer.id_token = token.bearer.id_token.take();
+                token.bearer = bearer;
+            }
+            Err(_) => return Err(error.into()),
+        }
+
+        self.request_userinfo(token).await
+    }
 }
 
 impl<P, C> Client<P, C>
@@ -362,10 +1682,27 @@ where
         Client {
             provider,
             client_id,
-            client_secret,
+            client_secret: client_secret.into(),
             redirect_uri,
+            redirect_uris: Vec::new(),
             http_client,
             jwks,
+            enforce_advertised_algorithms: false,
+            dpop: None,
+            client_auth: ClientAuth::default(),
+            leeway: Duration::zero(),
+            allowed_algorithms: None,
+            require_asymmetric_signing: false,
+            nonce_in_token_request: false,
+            enforce_id_token_typ: false,
+            jwks_refresh_interval: Duration::minutes(1),
+            jwks_uri: None,
+            inject_openid_scope: true,
+            enforce_userinfo_email: false,
+            additional_issuers: Vec::new(),
+            observer: None,
+            jwks_refresh: Arc::default(),
+            key_secrets: Arc::default(),
             marker: PhantomData,
         }
     }
@@ -394,6 +1731,15 @@ where
     /// );
     /// ```
     pub fn auth_uri(&self, scope: Option<&str>, state: Option<&str>) -> Url {
+        self.auth_uri_with_redirect(scope, state, self.redirect_uri.as_deref())
+    }
+
+    fn auth_uri_with_redirect(
+        &self,
+        scope: Option<&str>,
+        state: Option<&str>,
+        redirect_uri: Option<&str>,
+    ) -> Url {
         let mut uri = self.provider.auth_uri().clone();
 
         {
@@ -402,7 +1748,7 @@ where
             query.append_pair("response_type", "code");
             query.append_pair("client_id", &self.client_id);
 
-            if let Some(ref redirect_uri) = self.redirect_uri {
+            if let Some(redirect_uri) = redirect_uri {
                 query.append_pair("redirect_uri", redirect_uri);
             }
             if let Some(scope) = scope {
@@ -416,59 +1762,245 @@ where
         uri
     }
 
-    async fn post_token(&self, body: String) -> Result<Value, ClientError> {
-        let json = self
-            .http_client
-            .post(self.provider.token_uri().clone())
-            .basic_auth(&self.client_id, Some(&self.client_secret))
-            .header(ACCEPT, "application/json")
-            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
-            .body(body)
-            .send()
-            .await?
-            .json::<Value>()
-            .await?;
-
-        let error: Result<OAuth2Error, _> = serde_json::from_value(json.clone());
-
-        if let Ok(error) = error {
-            Err(ClientError::from(error))
+    /// The given redirect URI, if it is `redirect_uri` or one of `redirect_uris`.
+    fn registered_redirect_uri<'a>(&self, redirect_uri: &'a str) -> Result<&'a str, Error> {
+        let mut registered = self.redirect_uri.iter().chain(&self.redirect_uris);
+        if registered.any(|uri| uri == redirect_uri) {
+            Ok(redirect_uri)
         } else {
-            Ok(json)
+            Err(Error::UnregisteredRedirectUri(redirect_uri.to_string()))
         }
     }
 
-    /// Requests an access token using an authorization code.
-    ///
-    /// See [RFC 6749, section 4.1.3](http://tools.ietf.org/html/rfc6749#section-4.1.3).
-    pub async fn request_token(&self, code: &str) -> Result<Bearer, ClientError> {
-        // Ensure the non thread-safe `Serializer` is not kept across
-        // an `await` boundary by localizing it to this inner scope.
-        let body = {
-            let mut body = Serializer::new(String::new());
-            body.append_pair("grant_type", "authorization_code");
-            body.append_pair("code", code);
-
-            if let Some(ref redirect_uri) = self.redirect_uri {
-                body.append_pair("redirect_uri", redirect_uri);
+    /// Appends the client credentials to a token request body. The secret is only sent if
+    /// `in_body` is set, the client certificate authenticates a mTLS client instead.
+    pub(crate) fn append_credentials(&self, body: &mut Serializer<String>, in_body: bool) {
+        match self.client_auth {
+            ClientAuth::ClientSecret if in_body => {
+                body.append_pair("client_id", &self.client_id);
+                body.append_pair("client_secret", self.client_secret.as_str());
             }
-
-            if self.provider.credentials_in_body() {
+            ClientAuth::ClientSecret => {}
+            ClientAuth::MutualTls => {
                 body.append_pair("client_id", &self.client_id);
-                body.append_pair("client_secret", &self.client_secret);
             }
-            body.finish()
-        };
+        }
+    }
 
-        let json = self.post_token(body).await?;
-        let token: Bearer = serde_json::from_value(json)?;
-        Ok(token)
+    /// Posts the form to another endpoint of the provider than the token endpoint, e.g. pushed
+    /// authorization or device authorization. The client ID is always sent in the form, the
+    /// client is authenticated as for token requests.
+    pub(crate) async fn post_form(
+        &self,
+        url: &Url,
+        operation: Operation,
+        mut body: Serializer<'_, String>,
+    ) -> Result<Value, Error> {
+        let in_body = self.provider.credentials_in_body();
+        if self.client_auth == ClientAuth::ClientSecret && !in_body {
+            body.append_pair("client_id", &self.client_id);
+        }
+        self.append_credentials(&mut body, in_body);
+        let request = self.form_request(url).body(body.finish());
+
+        trace::request(operation, url, self.observer.as_deref(), async {
+            let resp = request.send().await?;
+            let status = resp.status();
+            let json: Value = resp.json().await?;
+            token_response(status, json).map_err(Error::from)
+        })
+        .await
     }
 
-    /// Requests an access token using the Resource Owner Password Credentials Grant flow
-    ///
-    /// See [RFC 6749, section 4.3](https://tools.ietf.org/html/rfc6749#section-4.3)
-    pub async fn request_token_using_password_credentials(
+    /// Form POST request to the url, with Basic authentication of a client with a secret.
+    fn form_request(&self, url: &Url) -> reqwest::RequestBuilder {
+        let request = self
+            .http_client
+            .post(url.clone())
+            .header(ACCEPT, "application/json")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+        match self.client_auth {
+            ClientAuth::ClientSecret => {
+                request.basic_auth(&self.client_id, Some(self.client_secret.as_str()))
+            }
+            ClientAuth::MutualTls => request,
+        }
+    }
+
+    /// Posts the body to the token endpoint, aborting after the timeout if given instead of the
+    /// timeout of the http client.
+    pub(crate) async fn post_token(
+        &self,
+        body: String,
+        timeout: Option<time::Duration>,
+    ) -> Result<Value, ClientError> {
+        let (mut status, mut json, dpop_nonce) =
+            self.send_token_request(body.clone(), None, timeout).await?;
+
+        // The server asks to retry with its nonce in the DPoP proof
+        if let Some(dpop_nonce) = dpop_nonce {
+            if json.get("error").and_then(Value::as_str) == Some(USE_DPOP_NONCE) {
+                (status, json, _) = self
+                    .send_token_request(body, Some(&dpop_nonce), timeout)
+                    .await?;
+            }
+        }
+
+        token_response(status, json)
+    }
+
+    async fn send_token_request(
+        &self,
+        body: String,
+        dpop_nonce: Option<&str>,
+        timeout: Option<time::Duration>,
+    ) -> Result<(StatusCode, Value, Option<String>), ClientError> {
+        let token_uri = self.provider.token_uri();
+        let mut request = self.form_request(token_uri);
+        if let Some(ref dpop) = self.dpop {
+            request = request.header("DPoP", dpop.proof("POST", token_uri, None, dpop_nonce)?);
+        }
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+
+        trace::request(
+            Operation::Token,
+            token_uri,
+            self.observer.as_deref(),
+            async {
+                let response = request.body(body).send().await?;
+                let dpop_nonce = response
+                    .headers()
+                    .get(DPOP_NONCE)
+                    .and_then(|nonce| nonce.to_str().ok())
+                    .map(String::from);
+                let content_type = response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|content_type| content_type.to_str().ok())
+                    .map(String::from);
+                let status = response.status();
+                let json = token_body(content_type.as_deref(), &response.bytes().await?)?;
+                Ok((status, json, dpop_nonce))
+            },
+        )
+        .await
+    }
+
+    /// Requests an access token using an authorization code.
+    ///
+    /// See [RFC 6749, section 4.1.3](http://tools.ietf.org/html/rfc6749#section-4.1.3).
+    pub async fn request_token(&self, code: &str) -> Result<Bearer, ClientError> {
+        self.request_token_with_verifier(code, None).await
+    }
+
+    /// Requests an access token using an authorization code, sending the PKCE code verifier if
+    /// given.
+    ///
+    /// See [RFC 7636, section 4.5](https://www.rfc-editor.org/rfc/rfc7636#section-4.5).
+    pub async fn request_token_with_verifier(
+        &self,
+        code: &str,
+        code_verifier: Option<&str>,
+    ) -> Result<Bearer, ClientError> {
+        self.request_authorization_code_token(
+            code,
+            code_verifier,
+            None,
+            self.redirect_uri.as_deref(),
+            None,
+        )
+        .await
+    }
+
+    /// Like [`Client::request_token`], aborting after the given timeout instead of the timeout
+    /// of the http client, e.g. to fail an interactive code exchange sooner. Without a timeout
+    /// it behaves like `request_token`.
+    pub async fn request_token_with_timeout(
+        &self,
+        code: &str,
+        timeout: Option<time::Duration>,
+    ) -> Result<Bearer, ClientError> {
+        self.request_authorization_code_token(
+            code,
+            None,
+            None,
+            self.redirect_uri.as_deref(),
+            timeout,
+        )
+        .await
+    }
+
+    /// Requests an access token using an authorization code, sending the given redirect URI
+    /// instead of `redirect_uri`. It must match the one of the auth url, see
+    /// [`Client::auth_url_with_redirect`].
+    pub async fn request_token_with_redirect(
+        &self,
+        code: &str,
+        redirect_uri: &str,
+    ) -> Result<Bearer, Error> {
+        let redirect_uri = self.registered_redirect_uri(redirect_uri)?;
+        let bearer = self
+            .request_authorization_code_token(code, None, None, Some(redirect_uri), None)
+            .await?;
+        Ok(bearer)
+    }
+
+    /// Requests an access token using an authorization code, with the nonce in the request body
+    /// if `nonce_in_token_request` is set.
+    async fn request_authorization_code_token(
+        &self,
+        code: &str,
+        code_verifier: Option<&str>,
+        nonce: Option<&str>,
+        redirect_uri: Option<&str>,
+        timeout: Option<time::Duration>,
+    ) -> Result<Bearer, ClientError> {
+        let body = self.authorization_code_body(code, code_verifier, nonce, redirect_uri)?;
+        let json = self.post_token(body, timeout).await?;
+        let token: Bearer = serde_json::from_value(json)?;
+        Ok(token)
+    }
+
+    /// Token request body of the authorization code grant. Errors with ClientError::EmptyCode if
+    /// the code is empty or only whitespace, which the provider would reject anyway.
+    pub(crate) fn authorization_code_body(
+        &self,
+        code: &str,
+        code_verifier: Option<&str>,
+        nonce: Option<&str>,
+        redirect_uri: Option<&str>,
+    ) -> Result<String, ClientError> {
+        let code = code.trim();
+        if code.is_empty() {
+            return Err(ClientError::EmptyCode);
+        }
+
+        let mut body = Serializer::new(String::new());
+        body.append_pair("grant_type", "authorization_code");
+        body.append_pair("code", code);
+
+        if let Some(redirect_uri) = redirect_uri {
+            body.append_pair("redirect_uri", redirect_uri);
+        }
+
+        if let Some(code_verifier) = code_verifier {
+            body.append_pair("code_verifier", code_verifier);
+        }
+
+        if let Some(nonce) = nonce.filter(|_| self.nonce_in_token_request) {
+            body.append_pair("nonce", nonce);
+        }
+
+        self.append_credentials(&mut body, self.provider.credentials_in_body());
+        Ok(body.finish())
+    }
+
+    /// Requests an access token using the Resource Owner Password Credentials Grant flow
+    ///
+    /// See [RFC 6749, section 4.3](https://tools.ietf.org/html/rfc6749#section-4.3)
+    pub async fn request_token_using_password_credentials(
         &self,
         username: &str,
         password: &str,
@@ -481,8 +2013,7 @@ where
             body.append_pair("grant_type", "password");
             body.append_pair("username", username);
             body.append_pair("password", password);
-            body.append_pair("client_id", &self.client_id);
-            body.append_pair("client_secret", &self.client_secret);
+            self.append_credentials(&mut body, true);
 
             if let Some(scope) = scope {
                 body.append_pair("scope", scope);
@@ -491,7 +2022,7 @@ where
             body.finish()
         };
 
-        let json = self.post_token(body).await?;
+        let json = self.post_token(body, None).await?;
         let token: Bearer = serde_json::from_value(json)?;
         Ok(token)
     }
@@ -505,12 +2036,11 @@ where
         let body = {
             let mut body = Serializer::new(String::new());
             body.append_pair("grant_type", "client_credentials");
-            body.append_pair("client_id", &self.client_id);
-            body.append_pair("client_secret", &self.client_secret);
+            self.append_credentials(&mut body, true);
             body.finish()
         };
 
-        let json = self.post_token(body).await?;
+        let json = self.post_token(body, None).await?;
         let token: Bearer = serde_json::from_value(json)?;
         Ok(token)
     }
@@ -523,32 +2053,20 @@ where
         token: Bearer,
         scope: Option<&str>,
     ) -> Result<Bearer, ClientError> {
-        // Ensure the non thread-safe `Serializer` is not kept across
-        // an `await` boundary by localizing it to this inner scope.
-        let body = {
-            let mut body = Serializer::new(String::new());
-            body.append_pair("grant_type", "refresh_token");
-            body.append_pair(
-                "refresh_token",
-                token
-                    .refresh_token
-                    .as_deref()
-                    .expect("No refresh_token field"),
-            );
-
-            if let Some(scope) = scope {
-                body.append_pair("scope", scope);
-            }
-
-            if self.provider.credentials_in_body() {
-                body.append_pair("client_id", &self.client_id);
-                body.append_pair("client_secret", &self.client_secret);
-            }
+        self.refresh_token_with_timeout(token, scope, None).await
+    }
 
-            body.finish()
-        };
+    /// Like [`Client::refresh_token`], aborting after the given timeout instead of the timeout
+    /// of the http client.
+    pub async fn refresh_token_with_timeout(
+        &self,
+        token: Bearer,
+        scope: Option<&str>,
+        timeout: Option<time::Duration>,
+    ) -> Result<Bearer, ClientError> {
+        let body = self.refresh_token_body(&token, scope);
 
-        let json = self.post_token(body).await?;
+        let json = self.post_token(body, timeout).await?;
         let mut new_token: Bearer = serde_json::from_value(json)?;
         if new_token.refresh_token.is_none() {
             new_token.refresh_token = token.refresh_token.clone();
@@ -556,6 +2074,31 @@ where
         Ok(new_token)
     }
 
+    /// Token request body of the refresh token grant.
+    ///
+    /// # Panics
+    ///
+    /// If the token has no refresh token.
+    pub(crate) fn refresh_token_body(&self, token: &Bearer, scope: Option<&str>) -> String {
+        let mut body = Serializer::new(String::new());
+        body.append_pair("grant_type", "refresh_token");
+        body.append_pair(
+            "refresh_token",
+            token
+                .refresh_token
+                .as_deref()
+                .expect("No refresh_token field"),
+        );
+
+        if let Some(scope) = scope {
+            body.append_pair("scope", scope);
+        }
+
+        self.append_credentials(&mut body, self.provider.credentials_in_body());
+
+        body.finish()
+    }
+
     /// Ensures an access token is valid by refreshing it if necessary.
     pub async fn ensure_token(&self, token: Bearer) -> Result<Bearer, ClientError> {
         if token.expired() {
@@ -569,7 +2112,23 @@ where
 #[cfg(test)]
 mod tests {
     use super::Client;
-    use crate::provider::Provider;
+    use crate::{
+        error::{Decode, Error, Mismatch, Missing, Validation},
+        provider::Provider,
+        testing::{self, config_json, MockServer, Response},
+        AuthSession, Bearer, Config, DiscoveredClient, FragmentResponse, IdToken, Options, Prompt,
+        StandardClaims, Token,
+    };
+    use biscuit::{
+        errors::Error as Jose,
+        jwa::SignatureAlgorithm,
+        jwk::JWKSet,
+        jws::{RegisteredHeader, Secret},
+        Empty,
+    };
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+    use std::{collections::HashMap, sync::Arc};
     use url::Url;
 
     struct Test {
@@ -660,4 +2219,2273 @@ mod tests {
             client.auth_uri(None, Some("baz")).as_str()
         );
     }
+
+    #[tokio::test]
+    async fn discover_with_jwks_skips_jwks_request() {
+        let server = MockServer::start(|request| match request.path.as_str() {
+            "/.well-known/openid-configuration" => {
+                let base = Url::parse(&format!("http://{}/", request.header("host").unwrap()));
+                Response::json(config_json(&base.unwrap()))
+            }
+            _ => Response::with_status(404, json!({})),
+        });
+        let jwks: JWKSet<Empty> = serde_json::from_value(json!({
+            "keys": [{"kty": "oct", "k": "c2VjcmV0", "kid": "local"}]
+        }))
+        .unwrap();
+
+        let client = DiscoveredClient::discover_with_jwks(
+            String::from("foo"),
+            String::from("bar"),
+            None,
+            server.url("/"),
+            jwks,
+        )
+        .await
+        .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(1, requests.len());
+        assert_eq!("GET", requests[0].method);
+        assert!(requests[0].body.is_empty());
+        assert!(server.requests_to("/jwks").is_empty());
+        assert!(client.jwks.unwrap().find("local").is_some());
+    }
+
+    fn advertising_client(algorithms: &[&str]) -> DiscoveredClient {
+        let base = Url::parse("https://example.com/").unwrap();
+        let mut config: Config = serde_json::from_value(config_json(&base)).unwrap();
+        config.id_token_signing_alg_values_supported =
+            algorithms.iter().map(|alg| alg.to_string()).collect();
+        let mut client = Client::new(
+            config.into(),
+            String::from("client"),
+            String::from("bar"),
+            None,
+            reqwest::Client::new(),
+            Some(testing::jwks("key")),
+        );
+        client.enforce_advertised_algorithms = true;
+        client
+    }
+
+    fn id_token() -> IdToken<StandardClaims> {
+        let claims = testing::claims_json("https://example.com", "client");
+        IdToken::new_encoded(&testing::sign(claims, Some("key")))
+    }
+
+    #[test]
+    fn decode_token_rejects_unadvertised_algorithm() {
+        let client = advertising_client(&["RS256"]);
+        let mut token = id_token();
+        let error = client.decode_token(&mut token).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Decode(Decode::UnadvertisedAlgorithm(ref alg)) if alg == "HS256"
+        ));
+    }
+
+    #[test]
+    fn decode_token_accepts_advertised_algorithm() {
+        let client = advertising_client(&["RS256", "HS256"]);
+        let mut token = id_token();
+        client.decode_token(&mut token).unwrap();
+        assert!(token.payload().is_ok());
+    }
+
+    #[test]
+    fn decode_token_ignores_advertised_algorithms_by_default() {
+        let mut client = advertising_client(&["RS256"]);
+        client.enforce_advertised_algorithms = false;
+        let mut token = id_token();
+        client.decode_token(&mut token).unwrap();
+    }
+
+    #[test]
+    fn decode_token_without_advertised_algorithms() {
+        let client = advertising_client(&[]);
+        let mut token = id_token();
+        client.decode_token(&mut token).unwrap();
+    }
+
+    #[test]
+    fn decode_token_rejects_thumbprint_mismatch() {
+        let mut client = advertising_client(&[]);
+        let key = &mut client.jwks.as_mut().unwrap().keys[0].common;
+        key.x509_chain = Some(vec![base64::encode(testing::RSA_CERTIFICATE)]);
+        key.x509_fingerprint = Some(base64::encode_config([0; 20], base64::URL_SAFE_NO_PAD));
+        let mut token = id_token();
+        assert!(matches!(
+            client.decode_token(&mut token),
+            Err(Error::Decode(Decode::ThumbprintMismatch(_)))
+        ));
+    }
+
+    #[test]
+    fn decode_token_rejects_single_key_with_other_kid() {
+        let client = advertising_client(&[]);
+        let claims = testing::claims_json("https://example.com", "client");
+        let mut token = IdToken::new_encoded(&testing::sign(claims.clone(), Some("other")));
+        assert!(matches!(
+            client.decode_token(&mut token),
+            Err(Error::Decode(Decode::MissingKey(kid))) if kid == "other"
+        ));
+
+        // Without a key id on the token the single key is used
+        let mut token = IdToken::new_encoded(&testing::sign(claims, None));
+        client.decode_token(&mut token).unwrap();
+    }
+
+    #[test]
+    fn decode_token_rejects_disallowed_algorithm() {
+        let mut client = advertising_client(&[]);
+        client.allowed_algorithms = Some(vec![SignatureAlgorithm::RS256]);
+        let mut token = id_token();
+        assert!(matches!(
+            client.decode_token(&mut token),
+            Err(Error::Decode(Decode::DisallowedAlgorithm(alg))) if alg == "HS256"
+        ));
+    }
+
+    #[test]
+    fn decode_token_requires_asymmetric_signing() {
+        let mut client = advertising_client(&[]);
+        client.require_asymmetric_signing = true;
+        let mut token = id_token();
+        assert!(matches!(
+            client.decode_token(&mut token),
+            Err(Error::Decode(Decode::SymmetricAlgorithm(alg))) if alg == "HS256"
+        ));
+
+        let pem = format!(
+            "{}/src/testing/rsa_public_key.pem",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        client.jwks = Some(crate::pem::jwks_from_pem(pem, Some("rsa")).unwrap());
+        let claims = testing::claims_json("https://example.com", "client");
+        let mut token = IdToken::new_encoded(&testing::sign_rs256(claims, Some("rsa")));
+        client.decode_token(&mut token).unwrap();
+        assert_eq!("subject", token.payload().unwrap().sub);
+    }
+
+    #[test]
+    fn debug_redacts_client_secret() {
+        let client = advertising_client(&[]);
+        assert_eq!("bar", client.client_secret.as_str());
+        let debug = format!("{:?}", client);
+        assert!(debug.contains(r#"client_secret: "***""#));
+        assert!(!debug.contains("bar"));
+    }
+
+    fn typed_id_token(typ: &str) -> IdToken<StandardClaims> {
+        let claims = testing::claims_json("https://example.com", "client");
+        let header = RegisteredHeader {
+            algorithm: SignatureAlgorithm::HS256,
+            media_type: Some(typ.to_string()),
+            ..Default::default()
+        };
+        IdToken::new_encoded(&testing::sign_with_header(header, claims))
+    }
+
+    #[test]
+    fn decode_token_rejects_access_token_typ() {
+        let mut client = advertising_client(&[]);
+        client.enforce_id_token_typ = true;
+        let mut token = typed_id_token("at+jwt");
+        assert!(matches!(
+            client.decode_token(&mut token),
+            Err(Error::Decode(Decode::UnexpectedType(typ))) if typ == "at+jwt"
+        ));
+    }
+
+    #[test]
+    fn decode_token_accepts_jwt_typ() {
+        let mut client = advertising_client(&[]);
+        client.enforce_id_token_typ = true;
+        client.decode_token(&mut typed_id_token("JWT")).unwrap();
+        client.decode_token(&mut typed_id_token("jwt")).unwrap();
+        client.decode_token(&mut id_token()).unwrap();
+    }
+
+    #[test]
+    fn from_config_auth_url() {
+        let base = Url::parse("https://example.com/").unwrap();
+        let config: Config = serde_json::from_value(config_json(&base)).unwrap();
+        let client = DiscoveredClient::from_config(
+            config,
+            None,
+            String::from("foo"),
+            String::from("bar"),
+            Some(String::from("https://app.example.com/callback")),
+            reqwest::Client::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            "https://example.com/auth?response_type=code&client_id=foo&redirect_uri=https%3A%2F%2Fapp.example.com%2Fcallback&scope=openid",
+            client.auth_url(&Default::default()).as_str()
+        );
+    }
+
+    #[test]
+    fn auth_url_with_session() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let options = Options {
+            state: Some(String::from("ignored")),
+            max_age: Some(Duration::minutes(5)),
+            ..Default::default()
+        };
+
+        let (url, session) = client.auth_url_with_session(&options);
+
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(Some(&session.state), query.get("state"));
+        assert_eq!(Some(&session.nonce), query.get("nonce"));
+        assert_eq!(
+            session.code_challenge().as_ref(),
+            query.get("code_challenge")
+        );
+        assert_eq!("S256", query["code_challenge_method"]);
+        assert_eq!("300", query["max_age"]);
+        assert_eq!(Some(Duration::minutes(5)), session.max_age);
+        assert!(!url
+            .as_str()
+            .contains(session.code_verifier.as_deref().unwrap()));
+    }
+
+    fn discovered_client(base: &Url) -> DiscoveredClient {
+        let config: Config = serde_json::from_value(config_json(base)).unwrap();
+        DiscoveredClient::from_config(
+            config,
+            Some(testing::jwks("key")),
+            String::from("client"),
+            String::from("secret"),
+            None,
+            reqwest::Client::new(),
+        )
+        .unwrap()
+    }
+
+    fn expired_token(id_token: Option<String>) -> Token {
+        let bearer = Bearer {
+            access_token: String::from("expired"),
+            token_type: None,
+            scope: None,
+            refresh_token: Some(String::from("refresh")),
+            expires: Some(Utc::now() - Duration::hours(1)),
+            id_token,
+        };
+        bearer.into()
+    }
+
+    #[tokio::test]
+    async fn ensure_and_revalidate_with_new_id_token() {
+        let server = MockServer::start(|request| {
+            let host = request.header("host").unwrap();
+            let claims = testing::claims_json(&format!("http://{}", host), "client");
+            Response::json(json!({
+                "access_token": "fresh",
+                "token_type": "Bearer",
+                "expires_in": 3600,
+                "id_token": testing::sign(claims, Some("key")),
+            }))
+        });
+        let client = discovered_client(&server.url("/"));
+
+        let token = client
+            .ensure_and_revalidate(expired_token(None))
+            .await
+            .unwrap();
+
+        assert_eq!("fresh", token.bearer.access_token);
+        assert_eq!(Some("refresh"), token.bearer.refresh_token.as_deref());
+        let id_token = token.id_token.unwrap();
+        assert_eq!("subject", id_token.payload().unwrap().sub);
+        assert_eq!(1, server.requests_to("/token").len());
+    }
+
+    #[tokio::test]
+    async fn ensure_and_revalidate_rejects_invalid_new_id_token() {
+        let server = MockServer::start(|_| {
+            let claims = testing::claims_json("https://evil.example.com", "client");
+            Response::json(json!({
+                "access_token": "fresh",
+                "token_type": "Bearer",
+                "id_token": testing::sign(claims, Some("key")),
+            }))
+        });
+        let client = discovered_client(&server.url("/"));
+
+        let result = client.ensure_and_revalidate(expired_token(None)).await;
+        assert!(matches!(result, Err(Error::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn ensure_and_revalidate_without_nonce() {
+        let server = refreshed_id_token_server(json!({}));
+        let client = discovered_client(&server.url("/"));
+
+        let token = client
+            .ensure_and_revalidate(expired_token_with_nonce(&server))
+            .await
+            .unwrap();
+
+        assert_eq!("fresh", token.bearer.access_token);
+        let claims = token.id_token.unwrap();
+        assert_eq!(None, claims.payload().unwrap().nonce);
+    }
+
+    #[tokio::test]
+    async fn ensure_and_revalidate_rejects_other_subject() {
+        let server = refreshed_id_token_server(json!({"sub": "other"}));
+        let client = discovered_client(&server.url("/"));
+
+        let result = client
+            .ensure_and_revalidate(expired_token_with_nonce(&server))
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::RefreshedToken { claim: "sub", .. }
+            )))
+        ));
+    }
+
+    #[tokio::test]
+    async fn ensure_and_revalidate_without_new_id_token() {
+        let server = MockServer::start(|_| {
+            Response::json(json!({
+                "access_token": "fresh",
+                "token_type": "Bearer",
+                "expires_in": 3600,
+            }))
+        });
+        let client = discovered_client(&server.url("/"));
+        let previous = String::from("previous.id.token");
+
+        let token = client
+            .ensure_and_revalidate(expired_token(Some(previous.clone())))
+            .await
+            .unwrap();
+
+        assert_eq!("fresh", token.bearer.access_token);
+        assert_eq!(Some(previous), token.bearer.id_token);
+        assert!(token.id_token.is_some());
+    }
+
+    #[tokio::test]
+    async fn ensure_and_revalidate_without_refresh_token() {
+        let server = MockServer::start(|_| Response::with_status(500, json!({})));
+        let client = discovered_client(&server.url("/"));
+        let mut token = expired_token(None);
+        token.bearer.refresh_token = None;
+
+        let result = client.ensure_and_revalidate(token).await;
+
+        assert!(matches!(result, Err(Error::MissingRefreshToken)));
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn ensure_and_revalidate_keeps_valid_token() {
+        let server = MockServer::start(|_| Response::with_status(500, json!({})));
+        let client = discovered_client(&server.url("/"));
+        let mut token = expired_token(None);
+        token.bearer.expires = Some(Utc::now() + Duration::hours(1));
+
+        let token = client.ensure_and_revalidate(token).await.unwrap();
+
+        assert_eq!("expired", token.bearer.access_token);
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn supported_userinfo_claims() {
+        let base = Url::parse("https://example.com/").unwrap();
+        let mut config = config_json(&base);
+        config["claims_supported"] = json!(["sub", "email", "name"]);
+        let client = DiscoveredClient::from_config(
+            serde_json::from_value(config).unwrap(),
+            None,
+            String::from("foo"),
+            String::from("bar"),
+            None,
+            reqwest::Client::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            Some(&["sub".to_string(), "email".to_string(), "name".to_string()][..]),
+            client.supported_userinfo_claims()
+        );
+
+        let client = discovered_client(&base);
+        assert_eq!(None, client.supported_userinfo_claims());
+    }
+
+    fn userinfo_server(refresh: Response) -> MockServer {
+        MockServer::start(move |request| match request.path.as_str() {
+            "/userinfo" if request.header("authorization") == Some("Bearer fresh") => {
+                Response::json(json!({"sub": "subject", "name": "Jane Doe"}))
+            }
+            "/userinfo" => Response::with_status(401, json!({"error": "invalid_token"})),
+            "/token" => refresh.clone(),
+            _ => Response::with_status(404, json!({})),
+        })
+    }
+
+    #[tokio::test]
+    async fn request_userinfo_auto_refresh() {
+        let server = userinfo_server(Response::json(json!({
+            "access_token": "fresh",
+            "token_type": "Bearer",
+        })));
+        let client = discovered_client(&server.url("/"));
+        let mut token = expired_token(None);
+
+        let userinfo = client
+            .request_userinfo_auto_refresh(&mut token)
+            .await
+            .unwrap();
+
+        assert_eq!(Some("Jane Doe"), userinfo.name.as_deref());
+        assert_eq!("fresh", token.bearer.access_token);
+        assert_eq!(Some("refresh"), token.bearer.refresh_token.as_deref());
+        assert_eq!(2, server.requests_to("/userinfo").len());
+        assert_eq!(1, server.requests_to("/token").len());
+    }
+
+    #[tokio::test]
+    async fn request_userinfo_auto_refresh_fails() {
+        let server = userinfo_server(Response::with_status(
+            400,
+            json!({"error": "invalid_grant"}),
+        ));
+        let client = discovered_client(&server.url("/"));
+        let mut token = expired_token(None);
+
+        let error = client
+            .request_userinfo_auto_refresh(&mut token)
+            .await
+            .unwrap_err();
+
+        match error {
+            Error::Http(error) => {
+                assert_eq!(Some(reqwest::StatusCode::UNAUTHORIZED), error.status())
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+        assert_eq!("expired", token.bearer.access_token);
+        assert_eq!(1, server.requests_to("/userinfo").len());
+        assert_eq!(1, server.requests_to("/token").len());
+    }
+
+    fn dpop_proof(request: &crate::testing::Request) -> serde_json::Value {
+        let proof = request.header("dpop").expect("DPoP header");
+        let claims = proof.split('.').nth(1).unwrap();
+        serde_json::from_slice(&base64::decode_config(claims, base64::URL_SAFE).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn dpop_token_and_userinfo_requests() {
+        let server = MockServer::start(|request| match request.path.as_str() {
+            "/token" if dpop_proof(request).get("nonce").is_none() => {
+                Response::with_status(400, json!({"error": "use_dpop_nonce"}))
+                    .header("DPoP-Nonce", "server-nonce")
+            }
+            "/token" => Response::json(json!({
+                "access_token": "fresh",
+                "token_type": "DPoP",
+            })),
+            "/userinfo" => Response::json(json!({"sub": "subject"})),
+            _ => Response::with_status(404, json!({})),
+        });
+        let mut client = discovered_client(&server.url("/"));
+        client.dpop = Some(crate::DPoPKey::generate().unwrap());
+
+        let token = client
+            .request_token_using_client_credentials()
+            .await
+            .unwrap();
+        assert_eq!(Some("DPoP"), token.token_type.as_deref());
+
+        let requests = server.requests_to("/token");
+        assert_eq!(2, requests.len());
+        let proof = dpop_proof(&requests[0]);
+        assert_eq!("POST", proof["htm"]);
+        assert_eq!(server.url("/token").as_str(), proof["htu"]);
+        assert!(proof.get("ath").is_none());
+        assert_eq!("server-nonce", dpop_proof(&requests[1])["nonce"]);
+
+        let mut token = expired_token(None);
+        token.bearer.access_token = String::from("fresh");
+        client.request_userinfo(&token).await.unwrap();
+
+        let request = &server.requests_to("/userinfo")[0];
+        assert_eq!(Some("DPoP fresh"), request.header("authorization"));
+        let proof = dpop_proof(request);
+        assert_eq!("GET", proof["htm"]);
+        assert_eq!(
+            crate::dpop::access_token_hash("fresh").as_str(),
+            proof["ath"]
+        );
+    }
+
+    fn token_server() -> MockServer {
+        MockServer::start(|request| match request.path.as_str() {
+            "/token" => Response::json(json!({"access_token": "fresh"})),
+            _ => Response::with_status(404, json!({})),
+        })
+    }
+
+    #[tokio::test]
+    async fn mutual_tls_sends_no_client_secret() {
+        let server = token_server();
+        let mut client = discovered_client(&server.url("/"));
+        client.client_auth = crate::ClientAuth::MutualTls;
+
+        client
+            .request_token_using_client_credentials()
+            .await
+            .unwrap();
+        client.request_token("code").await.unwrap();
+
+        for request in server.requests_to("/token") {
+            assert_eq!(None, request.header("authorization"));
+            assert!(request.body.contains("client_id=client"));
+            assert!(!request.body.contains("client_secret"));
+        }
+    }
+
+    #[tokio::test]
+    async fn client_secret_sent_by_default() {
+        let server = token_server();
+        let client = discovered_client(&server.url("/"));
+
+        client
+            .request_token_using_client_credentials()
+            .await
+            .unwrap();
+
+        let request = &server.requests_to("/token")[0];
+        assert!(request
+            .header("authorization")
+            .unwrap()
+            .starts_with("Basic "));
+        assert!(request.body.contains("client_secret="));
+    }
+
+    fn id_token_server(nonce: &'static str) -> MockServer {
+        MockServer::start(move |request| {
+            let base = Url::parse(&format!("http://{}/", request.header("host").unwrap())).unwrap();
+            let mut claims = testing::claims_json(base.as_str().trim_end_matches('/'), "client");
+            claims["nonce"] = json!(nonce);
+            Response::json(json!({
+                "access_token": "fresh",
+                "id_token": testing::sign(claims, Some("key")),
+            }))
+        })
+    }
+
+    #[tokio::test]
+    async fn authenticate_session() {
+        let server = id_token_server("nonce");
+        let client = discovered_client(&server.url("/"));
+        let session = AuthSession {
+            state: String::from("state"),
+            nonce: String::from("nonce"),
+            code_verifier: Some(String::from("verifier")),
+            max_age: None,
+            redirect_uri: None,
+        };
+
+        let token = client.authenticate_session("code", &session).await.unwrap();
+        assert!(token.id_token.is_some());
+
+        let request = &server.requests_to("/token")[0];
+        assert!(request.body.contains("code_verifier=verifier"));
+    }
+
+    #[tokio::test]
+    async fn authenticate_session_nonce_mismatch() {
+        let server = id_token_server("other");
+        let client = discovered_client(&server.url("/"));
+        let session = AuthSession::generate(None);
+
+        let token = client.authenticate_session("code", &session).await;
+        assert!(matches!(
+            token,
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::Nonce { .. }
+            )))
+        ));
+    }
+
+    fn fragment(nonce: &str, state: &str) -> String {
+        let mut claims = testing::claims_json("https://example.com", "client");
+        claims["nonce"] = json!(nonce);
+        let hash = ring::digest::digest(&ring::digest::SHA256, b"fresh");
+        claims["at_hash"] = json!(base64::encode_config(
+            &hash.as_ref()[..16],
+            base64::URL_SAFE_NO_PAD
+        ));
+        url::form_urlencoded::Serializer::new(String::from("#"))
+            .append_pair("access_token", "fresh")
+            .append_pair("token_type", "Bearer")
+            .append_pair("id_token", &testing::sign(claims, Some("key")))
+            .append_pair("state", state)
+            .append_pair("expires_in", "3600")
+            .finish()
+    }
+
+    /// Fragment of `response_type=id_token`, or with an access_token but no at_hash
+    fn fragment_without_at_hash(access_token: Option<&str>) -> String {
+        let mut claims = testing::claims_json("https://example.com", "client");
+        claims["nonce"] = json!("nonce");
+        let mut fragment = url::form_urlencoded::Serializer::new(String::from("#"));
+        if let Some(access_token) = access_token {
+            fragment.append_pair("access_token", access_token);
+            fragment.append_pair("token_type", "Bearer");
+        }
+        fragment
+            .append_pair("id_token", &testing::sign(claims, Some("key")))
+            .append_pair("state", "state")
+            .finish()
+    }
+
+    fn session() -> AuthSession {
+        AuthSession {
+            state: String::from("state"),
+            nonce: String::from("nonce"),
+            code_verifier: None,
+            max_age: None,
+            redirect_uri: None,
+        }
+    }
+
+    #[test]
+    fn parse_fragment_response() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+
+        let token = match client
+            .parse_fragment_response(&fragment("nonce", "state"), &session())
+            .unwrap()
+        {
+            FragmentResponse::Token(token) => token,
+            FragmentResponse::IdToken(_) => panic!("access_token of the fragment is missing"),
+        };
+
+        assert_eq!("fresh", token.bearer.access_token);
+        assert!(token.bearer.expires.is_some());
+        assert!(token.id_token.unwrap().payload().is_ok());
+    }
+
+    #[test]
+    fn parse_fragment_response_without_access_token() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+
+        let token = client
+            .parse_fragment_response(&fragment_without_at_hash(None), &session())
+            .unwrap();
+
+        match token {
+            FragmentResponse::IdToken(id_token) => assert!(id_token.payload().is_ok()),
+            FragmentResponse::Token(_) => panic!("fragment has no access_token"),
+        }
+    }
+
+    #[test]
+    fn parse_fragment_response_requires_at_hash() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+
+        for access_token in ["fresh", ""] {
+            let token = client
+                .parse_fragment_response(&fragment_without_at_hash(Some(access_token)), &session());
+            assert!(matches!(
+                token,
+                Err(Error::Validation(Validation::Missing(
+                    Missing::AccessTokenHash
+                )))
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_fragment_response_nonce_mismatch() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+
+        let token = client.parse_fragment_response(&fragment("other", "state"), &session());
+        assert!(matches!(
+            token,
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::Nonce { .. }
+            )))
+        ));
+    }
+
+    #[test]
+    fn parse_fragment_response_state_mismatch() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+
+        let token = client.parse_fragment_response(&fragment("nonce", "other"), &session());
+        assert!(matches!(
+            token,
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::State { .. }
+            )))
+        ));
+    }
+
+    #[tokio::test]
+    async fn nonce_in_token_request() {
+        let server = id_token_server("nonce");
+        let mut client = discovered_client(&server.url("/"));
+
+        client
+            .authenticate("code", Some("nonce"), None)
+            .await
+            .unwrap();
+        client.nonce_in_token_request = true;
+        client
+            .authenticate("code", Some("nonce"), None)
+            .await
+            .unwrap();
+
+        let requests = server.requests_to("/token");
+        assert!(!requests[0].body.contains("nonce="));
+        assert!(requests[1].body.contains("nonce=nonce"));
+    }
+
+    #[tokio::test]
+    async fn request_userinfo_cached() {
+        let server = MockServer::start(|request| match request.header("if-none-match") {
+            Some("\"v1\"") => Response {
+                status: 304,
+                headers: vec![],
+                body: String::new(),
+            },
+            _ => Response::json(json!({"sub": "subject", "email": "user@example.com"}))
+                .header("ETag", "\"v1\""),
+        });
+        let client = discovered_client(&server.url("/"));
+        let token: Token = Bearer {
+            access_token: String::from("access"),
+            token_type: None,
+            scope: None,
+            refresh_token: None,
+            expires: None,
+            id_token: None,
+        }
+        .into();
+        let mut cache = crate::UserinfoCache::new();
+
+        let first = client
+            .request_userinfo_cached(&token, &mut cache)
+            .await
+            .unwrap();
+        assert_eq!(Some("user@example.com"), first.email.as_deref());
+        assert_eq!(Some("\"v1\""), cache.etag());
+        assert_eq!(Some(&first), cache.userinfo());
+
+        let second = client
+            .request_userinfo_cached(&token, &mut cache)
+            .await
+            .unwrap();
+        assert_eq!(first, second);
+
+        let requests = server.requests_to("/userinfo");
+        assert_eq!(None, requests[0].header("if-none-match"));
+        assert_eq!(Some("\"v1\""), requests[1].header("if-none-match"));
+    }
+
+    #[tokio::test]
+    async fn request_userinfo_with_access_token() {
+        let server = MockServer::start(|request| match request.path.as_str() {
+            "/userinfo" => Response::json(json!({"sub": "other", "email": "user@example.com"})),
+            _ => Response::with_status(404, json!({})),
+        });
+        let client = discovered_client(&server.url("/"));
+
+        let userinfo = client
+            .request_userinfo_with_access_token("gateway")
+            .await
+            .unwrap();
+
+        assert_eq!(Some("other"), userinfo.sub.as_deref());
+        let request = &server.requests_to("/userinfo")[0];
+        assert_eq!(Some("Bearer gateway"), request.header("authorization"));
+    }
+
+    async fn request_userinfo_email(
+        token_email: Option<&str>,
+        info_email: Option<&'static str>,
+    ) -> Result<crate::Userinfo, Error> {
+        let server = MockServer::start(move |request| match request.path.as_str() {
+            "/userinfo" => Response::json(json!({"sub": "subject", "email": info_email})),
+            _ => Response::with_status(404, json!({})),
+        });
+        let mut client = discovered_client(&server.url("/"));
+        client.enforce_userinfo_email = true;
+
+        let mut claims = testing::claims_json("https://example.com", "client");
+        claims["email"] = json!(token_email);
+        let mut token: Token = Bearer {
+            access_token: String::from("access"),
+            token_type: None,
+            scope: None,
+            refresh_token: None,
+            expires: None,
+            id_token: Some(testing::sign(claims, Some("key"))),
+        }
+        .into();
+        client
+            .decode_token(token.id_token.as_mut().unwrap())
+            .unwrap();
+        client.request_userinfo(&token).await
+    }
+
+    #[tokio::test]
+    async fn request_userinfo_matching_email() {
+        let userinfo = request_userinfo_email(Some("user@example.com"), Some("user@example.com"))
+            .await
+            .unwrap();
+        assert_eq!(Some("user@example.com"), userinfo.email.as_deref());
+    }
+
+    #[tokio::test]
+    async fn request_userinfo_mismatching_email() {
+        let error = request_userinfo_email(Some("user@example.com"), Some("other@example.com"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Userinfo(crate::error::Userinfo::MismatchEmail { expected, actual })
+                if expected == "user@example.com" && actual == "other@example.com"
+        ));
+    }
+
+    #[tokio::test]
+    async fn request_userinfo_email_on_one_side() {
+        request_userinfo_email(None, Some("user@example.com"))
+            .await
+            .unwrap();
+        let userinfo = request_userinfo_email(Some("user@example.com"), None)
+            .await
+            .unwrap();
+        assert_eq!(None, userinfo.email);
+    }
+
+    #[tokio::test]
+    async fn discover_with_proxy() {
+        let issuer = Url::parse("http://issuer.example/").unwrap();
+        let config = config_json(&issuer);
+        let proxy = MockServer::start(move |request| match request.path.as_str() {
+            "http://issuer.example/.well-known/openid-configuration" => {
+                Response::json(config.clone())
+            }
+            "http://issuer.example/jwks" => {
+                Response::json(serde_json::to_value(testing::jwks("key")).unwrap())
+            }
+            _ => Response::with_status(404, json!({})),
+        });
+
+        let client = DiscoveredClient::discover_with_proxy(
+            String::from("client"),
+            String::from("secret"),
+            None,
+            issuer,
+            proxy.url("/"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            "http://issuer.example/token",
+            client.config().token_endpoint.as_str()
+        );
+        assert_eq!(2, proxy.requests().len());
+    }
+
+    #[tokio::test]
+    async fn discover_without_jwks_uri() {
+        let server = MockServer::start(|request| {
+            let base = Url::parse(&format!("http://{}/", request.header("host").unwrap())).unwrap();
+            let mut config = config_json(&base);
+            config.as_object_mut().unwrap().remove("jwks_uri");
+            match request.path.as_str() {
+                "/.well-known/openid-configuration" => Response::json(config),
+                _ => Response::with_status(404, json!({})),
+            }
+        });
+
+        let client = DiscoveredClient::discover_with_client(
+            reqwest::Client::new(),
+            String::from("client"),
+            String::from("secret"),
+            None,
+            server.url("/"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(None, client.config().jwks_uri);
+        assert!(client.jwks.is_none());
+        assert_eq!(1, server.requests().len());
+    }
+
+    fn rollover_server() -> MockServer {
+        MockServer::start(|request| match request.path.as_str() {
+            "/jwks" => Response::json(serde_json::to_value(testing::jwks("new")).unwrap()),
+            _ => Response::with_status(404, json!({})),
+        })
+    }
+
+    fn rollover_token() -> IdToken<StandardClaims> {
+        let claims = testing::claims_json("https://example.com", "client");
+        IdToken::new_encoded(&testing::sign(claims, Some("new")))
+    }
+
+    #[tokio::test]
+    async fn decode_token_refreshing_fetches_new_keys() {
+        let server = rollover_server();
+        let mut client = discovered_client(&server.url("/"));
+        client
+            .jwks
+            .as_mut()
+            .unwrap()
+            .keys
+            .push(testing::jwks("old").keys.remove(0));
+
+        let mut token = rollover_token();
+        assert!(client.decode_token(&mut token).is_err());
+        client.decode_token_refreshing(&mut token).await.unwrap();
+
+        // The fetched keys are used from now on
+        client
+            .decode_token_refreshing(&mut rollover_token())
+            .await
+            .unwrap();
+        assert_eq!(1, server.requests_to("/jwks").len());
+    }
+
+    #[tokio::test]
+    async fn decode_token_refreshing_updates_all_decodes() {
+        let server = rollover_server();
+        let client = discovered_client(&server.url("/"));
+        let jwt = rollover_token().encoded().unwrap().encode();
+        assert!(client.verify_signature(&jwt).is_err());
+
+        client
+            .decode_token_refreshing(&mut rollover_token())
+            .await
+            .unwrap();
+
+        client.decode_token(&mut rollover_token()).unwrap();
+        assert!(client.verify_signature(&jwt).unwrap());
+        // Clones share the fetched key set
+        client.clone().decode_token(&mut rollover_token()).unwrap();
+    }
+
+    fn failing_jwks_server(failures: usize) -> MockServer {
+        let fetches = std::sync::atomic::AtomicUsize::new(0);
+        MockServer::start(move |request| match request.path.as_str() {
+            "/jwks" if fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < failures => {
+                Response::with_status(500, json!({}))
+            }
+            "/jwks" => Response::json(serde_json::to_value(testing::jwks("new")).unwrap()),
+            _ => Response::with_status(404, json!({})),
+        })
+    }
+
+    #[tokio::test]
+    async fn decode_token_refreshing_backs_off_after_failed_fetch() {
+        let server = failing_jwks_server(usize::MAX);
+        let client = discovered_client(&server.url("/"));
+
+        assert!(client
+            .decode_token_refreshing(&mut rollover_token())
+            .await
+            .is_err());
+        assert!(matches!(
+            client.decode_token_refreshing(&mut rollover_token()).await,
+            Err(Error::Decode(Decode::MissingKey(_)))
+        ));
+        assert_eq!(1, server.requests_to("/jwks").len());
+    }
+
+    #[tokio::test]
+    async fn decode_token_refreshing_retries_failed_fetch() {
+        let server = failing_jwks_server(1);
+        let mut client = discovered_client(&server.url("/"));
+        client.jwks_refresh_interval = Duration::milliseconds(100);
+
+        assert!(client
+            .decode_token_refreshing(&mut rollover_token())
+            .await
+            .is_err());
+        std::thread::sleep(std::time::Duration::from_millis(150));
+        client
+            .decode_token_refreshing(&mut rollover_token())
+            .await
+            .unwrap();
+        assert_eq!(2, server.requests_to("/jwks").len());
+    }
+
+    #[tokio::test]
+    async fn decode_token_refreshing_uses_jwks_uri_override() {
+        let server = MockServer::start(|request| match request.path.as_str() {
+            "/mirror" => Response::json(serde_json::to_value(testing::jwks("new")).unwrap()),
+            _ => Response::with_status(404, json!({})),
+        });
+        let mut client = discovered_client(&server.url("/"));
+        client.jwks_uri = Some(server.url("/mirror"));
+
+        client
+            .decode_token_refreshing(&mut rollover_token())
+            .await
+            .unwrap();
+        assert_eq!(1, server.requests_to("/mirror").len());
+        assert!(server.requests_to("/jwks").is_empty());
+    }
+
+    #[tokio::test]
+    async fn decode_token_refreshing_is_rate_limited() {
+        let server = rollover_server();
+        let mut client = discovered_client(&server.url("/"));
+        client
+            .jwks
+            .as_mut()
+            .unwrap()
+            .keys
+            .push(testing::jwks("old").keys.remove(0));
+        let client = std::sync::Arc::new(client);
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..100 {
+            let client = client.clone();
+            tasks.spawn(async move {
+                let mut token = rollover_token();
+                client.decode_token_refreshing(&mut token).await.is_ok()
+            });
+        }
+        let mut decoded = 0;
+        while let Some(result) = tasks.join_next().await {
+            decoded += result.unwrap() as usize;
+        }
+
+        assert!(decoded >= 1);
+        assert_eq!(1, server.requests_to("/jwks").len());
+    }
+
+    #[test]
+    fn flight_registers_waker_once_per_waiter() {
+        use std::{
+            future::Future,
+            pin::pin,
+            task::{Context, Poll, Wake, Waker},
+        };
+
+        struct Noop;
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        let flight = super::Flight::new();
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+        let mut wait = pin!(flight.wait());
+        for _ in 0..10 {
+            assert!(wait.as_mut().poll(&mut cx).is_pending());
+        }
+        assert_eq!(1, flight.waiters());
+
+        // Polled by another task, the waker is replaced
+        let other = Waker::from(Arc::new(Noop));
+        assert!(wait
+            .as_mut()
+            .poll(&mut Context::from_waker(&other))
+            .is_pending());
+        assert_eq!(1, flight.waiters());
+
+        let mut second = pin!(flight.wait());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(2, flight.waiters());
+
+        flight.finish();
+        assert_eq!(Poll::Ready(()), wait.poll(&mut cx));
+        assert_eq!(Poll::Ready(()), second.poll(&mut cx));
+    }
+
+    #[tokio::test]
+    async fn decode_token_refreshing_is_single_flight() {
+        let server = MockServer::start(|request| match request.path.as_str() {
+            "/jwks" => {
+                // Slow enough for all decodes to arrive during the fetch
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                Response::json(serde_json::to_value(testing::jwks("new")).unwrap())
+            }
+            _ => Response::with_status(404, json!({})),
+        });
+        let mut client = discovered_client(&server.url("/"));
+        client
+            .jwks
+            .as_mut()
+            .unwrap()
+            .keys
+            .push(testing::jwks("old").keys.remove(0));
+        let client = std::sync::Arc::new(client);
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..50 {
+            let client = client.clone();
+            tasks.spawn(async move {
+                let mut token = rollover_token();
+                client.decode_token_refreshing(&mut token).await
+            });
+        }
+        while let Some(result) = tasks.join_next().await {
+            // All wait for the one fetch instead of failing with Decode::MissingKey
+            result.unwrap().unwrap();
+        }
+
+        assert_eq!(1, server.requests_to("/jwks").len());
+    }
+
+    fn iss_client(required: bool) -> DiscoveredClient {
+        let base = Url::parse("https://example.com/").unwrap();
+        let mut config: Config = serde_json::from_value(config_json(&base)).unwrap();
+        config.authorization_response_iss_parameter_supported = required;
+        DiscoveredClient::from_config(
+            config,
+            None,
+            String::from("client"),
+            String::from("secret"),
+            None,
+            reqwest::Client::new(),
+        )
+        .unwrap()
+    }
+
+    fn redirect(query: &str) -> Url {
+        Url::parse(&format!("https://app.example.com/callback?{}", query)).unwrap()
+    }
+
+    #[test]
+    fn parse_authorization_response_matching_iss() {
+        let client = iss_client(true);
+        for iss in ["https%3A%2F%2Fexample.com", "https%3A%2F%2Fexample.com%2F"] {
+            let redirect = redirect(&format!("code=code&state=state&iss={}", iss));
+            let code = client.parse_authorization_response(&redirect, &session());
+            assert_eq!("code", code.unwrap());
+        }
+    }
+
+    #[test]
+    fn parse_authorization_response_mismatched_iss() {
+        let client = iss_client(false);
+        // Equal as urls, but not as strings
+        for iss in [
+            "https%3A%2F%2Fevil.example.com",
+            "https%3A%2F%2FEXAMPLE.com",
+            "https%3A%2F%2Fexample.com%3A443",
+            "https%3A%2F%2Fexample.com%2F.",
+        ] {
+            let redirect = redirect(&format!("code=code&state=state&iss={}", iss));
+            assert!(matches!(
+                client.parse_authorization_response(&redirect, &session()),
+                Err(Error::Validation(Validation::Mismatch(
+                    Mismatch::ResponseIssuer { .. }
+                )))
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_authorization_response_missing_iss() {
+        let redirect = redirect("code=code&state=state");
+        assert!(iss_client(false)
+            .parse_authorization_response(&redirect, &session())
+            .is_ok());
+        assert!(matches!(
+            iss_client(true).parse_authorization_response(&redirect, &session()),
+            Err(Error::Validation(Validation::Missing(
+                Missing::ResponseIssuer
+            )))
+        ));
+    }
+
+    #[test]
+    fn parse_logout_response() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let redirect = Url::parse("https://app.example.com/logged-out?state=state").unwrap();
+        client.parse_logout_response(&redirect, "state").unwrap();
+
+        assert!(matches!(
+            client.parse_logout_response(&redirect, "other"),
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::State { .. }
+            )))
+        ));
+        let redirect = Url::parse("https://app.example.com/logged-out").unwrap();
+        assert!(matches!(
+            client.parse_logout_response(&redirect, "state"),
+            Err(Error::Validation(Validation::Missing(Missing::State)))
+        ));
+    }
+
+    #[test]
+    fn parse_authorization_response_error() {
+        let redirect = redirect("error=access_denied&state=state");
+        assert!(matches!(
+            iss_client(false).parse_authorization_response(&redirect, &session()),
+            Err(Error::ClientError(crate::error::ClientError::OAuth(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn request_userinfo_unexpected_content_type() {
+        let server = MockServer::start(|_| Response {
+            status: 200,
+            headers: vec![("Content-Type".into(), "text/html; charset=utf-8".into())],
+            body: String::from("<html>Login</html>"),
+        });
+        let client = discovered_client(&server.url("/"));
+
+        let userinfo = client.request_userinfo_with_access_token("access").await;
+
+        match userinfo {
+            Err(Error::Userinfo(crate::error::Userinfo::UnexpectedContentType {
+                expected,
+                actual,
+            })) => {
+                assert_eq!("application/json or application/jwt", expected);
+                assert_eq!("text/html; charset=utf-8", actual);
+            }
+            _ => panic!("expected an unexpected content type error"),
+        }
+    }
+
+    fn multi_redirect_client(base: &Url) -> DiscoveredClient {
+        let mut client = discovered_client(base);
+        client.redirect_uri = Some("https://app.example.com/callback".to_string());
+        client.redirect_uris = vec!["app://callback".to_string()];
+        client
+    }
+
+    #[tokio::test]
+    async fn redirect_override_in_auth_url_and_token_request() {
+        let server = token_server();
+        let client = multi_redirect_client(&server.url("/"));
+
+        let url = client
+            .auth_url_with_redirect(&Options::default(), "app://callback")
+            .unwrap();
+        assert!(url
+            .query_pairs()
+            .any(|(key, value)| key == "redirect_uri" && value == "app://callback"));
+
+        client
+            .request_token_with_redirect("code", "app://callback")
+            .await
+            .unwrap();
+        let request = &server.requests_to("/token")[0];
+        assert!(request.body.contains("redirect_uri=app%3A%2F%2Fcallback"));
+    }
+
+    #[tokio::test]
+    async fn redirect_override_must_be_registered() {
+        let server = token_server();
+        let client = multi_redirect_client(&server.url("/"));
+
+        assert!(matches!(
+            client.auth_url_with_redirect(&Options::default(), "https://evil.example.com"),
+            Err(Error::UnregisteredRedirectUri(_))
+        ));
+        assert!(matches!(
+            client
+                .request_token_with_redirect("code", "https://evil.example.com")
+                .await,
+            Err(Error::UnregisteredRedirectUri(_))
+        ));
+        assert!(server.requests().is_empty());
+        assert!(client
+            .auth_url_with_redirect(&Options::default(), "https://app.example.com/callback")
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn authenticate_session_sends_session_redirect() {
+        let server = id_token_server("nonce");
+        let client = multi_redirect_client(&server.url("/"));
+        let (url, mut session) = client
+            .auth_url_with_session_and_redirect(&Options::default(), "app://callback")
+            .unwrap();
+        assert_eq!(Some("app://callback"), session.redirect_uri.as_deref());
+        assert!(url
+            .query_pairs()
+            .any(|(key, value)| key == "redirect_uri" && value == "app://callback"));
+
+        session.nonce = String::from("nonce");
+        client.authenticate_session("code", &session).await.unwrap();
+
+        let request = &server.requests_to("/token")[0];
+        assert!(request.body.contains("redirect_uri=app%3A%2F%2Fcallback"));
+    }
+
+    #[tokio::test]
+    async fn refresh_token_full_decodes_new_id_token() {
+        let server = MockServer::start(|request| {
+            let host = request.header("host").unwrap();
+            let mut claims = testing::claims_json(&format!("http://{}", host), "client");
+            claims["sub"] = json!("refreshed");
+            Response::json(json!({
+                "access_token": "fresh",
+                "id_token": testing::sign(claims, Some("key")),
+            }))
+        });
+        let client = discovered_client(&server.url("/"));
+        let mut token = expired_token(None);
+        token.bearer.expires = Some(Utc::now() + Duration::hours(1));
+
+        let token = client
+            .refresh_token_full(token, Some("openid"))
+            .await
+            .unwrap();
+
+        assert_eq!("fresh", token.bearer.access_token);
+        assert_eq!(Some("refresh"), token.bearer.refresh_token.as_deref());
+        let id_token = token.id_token.unwrap();
+        assert_eq!("refreshed", id_token.payload().unwrap().sub);
+        assert!(server.requests_to("/token")[0]
+            .body
+            .contains("scope=openid"));
+    }
+
+    /// Token server refreshing with an id_token with the extra claims.
+    fn refreshed_id_token_server(extra: serde_json::Value) -> MockServer {
+        MockServer::start(move |request| {
+            let host = request.header("host").unwrap();
+            let mut claims = testing::claims_json(&format!("http://{}", host), "client");
+            claims
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            Response::json(json!({
+                "access_token": "fresh",
+                "id_token": testing::sign(claims, Some("key")),
+            }))
+        })
+    }
+
+    /// Expired token with an id_token of the server, with the nonce `original`.
+    fn expired_token_with_nonce(server: &MockServer) -> Token {
+        let mut claims = testing::claims_json(server.url("/").as_str(), "client");
+        claims["nonce"] = json!("original");
+        expired_token(Some(testing::sign(claims, Some("key"))))
+    }
+
+    #[tokio::test]
+    async fn refresh_token_full_without_nonce() {
+        let server = refreshed_id_token_server(json!({}));
+        let client = discovered_client(&server.url("/"));
+
+        let token = client
+            .refresh_token_full(expired_token_with_nonce(&server), None)
+            .await
+            .unwrap();
+
+        let claims = token.id_token.unwrap();
+        assert_eq!(None, claims.payload().unwrap().nonce);
+    }
+
+    #[tokio::test]
+    async fn refresh_token_full_with_nonce() {
+        let server = refreshed_id_token_server(json!({"nonce": "original"}));
+        let client = discovered_client(&server.url("/"));
+        client
+            .refresh_token_full(expired_token_with_nonce(&server), None)
+            .await
+            .unwrap();
+
+        let server = refreshed_id_token_server(json!({"nonce": "other"}));
+        let client = discovered_client(&server.url("/"));
+        let result = client
+            .refresh_token_full(expired_token_with_nonce(&server), None)
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::Nonce { .. }
+            )))
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_token_full_without_refresh_token() {
+        let server = MockServer::start(|_| Response::with_status(500, json!({})));
+        let client = discovered_client(&server.url("/"));
+        let mut token = expired_token(None);
+        token.bearer.refresh_token = None;
+
+        let result = client.refresh_token_full(token, None).await;
+
+        assert!(matches!(result, Err(Error::MissingRefreshToken)));
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn refresh_token_full_rejects_other_subject() {
+        let server = refreshed_id_token_server(json!({"sub": "other"}));
+        let client = discovered_client(&server.url("/"));
+
+        let result = client
+            .refresh_token_full(expired_token_with_nonce(&server), None)
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::RefreshedToken { claim: "sub", expected, actual }
+            ))) if expected == "subject" && actual == "other"
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_token_full_rejects_invalid_id_token() {
+        let server = MockServer::start(|_| {
+            let claims = testing::claims_json("https://evil.example.com", "client");
+            Response::json(json!({
+                "access_token": "fresh",
+                "id_token": testing::sign(claims, Some("key")),
+            }))
+        });
+        let client = discovered_client(&server.url("/"));
+
+        let token = client.refresh_token_full(expired_token(None), None).await;
+        assert!(matches!(
+            token,
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::Issuer { .. }
+            )))
+        ));
+    }
+
+    #[test]
+    fn validate_token_all_collects_failures() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let mut claims = testing::claims_json("https://example.com", "other");
+        claims["nonce"] = json!("other");
+        let mut token = IdToken::new_encoded(&testing::sign(claims, Some("key")));
+        client.decode_token(&mut token).unwrap();
+
+        assert!(matches!(
+            client.validate_token(&token, Some("nonce"), None),
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::Nonce { .. }
+            )))
+        ));
+        match client.validate_token_all(&token, Some("nonce"), None) {
+            Err(Error::Validation(Validation::Multiple(failures))) => {
+                assert!(matches!(
+                    failures.as_slice(),
+                    [
+                        Validation::Mismatch(Mismatch::Nonce { .. }),
+                        Validation::Missing(Missing::Audience),
+                    ]
+                ));
+            }
+            other => panic!("expected multiple failures, got {:?}", other),
+        }
+    }
+
+    fn step_up_token(acr: &str, auth_age: i64) -> IdToken<StandardClaims> {
+        let mut claims = testing::claims_json("https://example.com", "client");
+        claims["acr"] = json!(acr);
+        claims["auth_time"] = json!(Utc::now().timestamp() - auth_age);
+        let mut token = IdToken::new_encoded(&testing::sign(claims, Some("key")));
+        discovered_client(&Url::parse("https://example.com/").unwrap())
+            .decode_token(&mut token)
+            .unwrap();
+        token
+    }
+
+    #[test]
+    fn require_step_up() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let token = step_up_token("urn:mace:incommon:iap:silver", 60);
+        client
+            .require_step_up(
+                &token,
+                "urn:mace:incommon:iap:silver",
+                &Duration::minutes(5),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn require_step_up_failures() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let silver = "urn:mace:incommon:iap:silver";
+        let max_age = Duration::minutes(5);
+
+        let token = step_up_token("urn:mace:incommon:iap:bronze", 60);
+        let step_up = match client.require_step_up(&token, silver, &max_age) {
+            Err(Error::StepUpRequired(step_up)) => step_up,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(Some(silver), step_up.acr.as_deref());
+        assert_eq!(None, step_up.max_age);
+
+        let token = step_up_token(silver, 600);
+        let step_up = match client.require_step_up(&token, silver, &max_age) {
+            Err(Error::StepUpRequired(step_up)) => step_up,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(None, step_up.acr);
+        assert_eq!(Some(max_age), step_up.max_age);
+
+        let token = step_up_token("urn:mace:incommon:iap:bronze", 600);
+        let step_up = match client.require_step_up(&token, silver, &max_age) {
+            Err(Error::StepUpRequired(step_up)) => step_up,
+            other => panic!("unexpected {:?}", other),
+        };
+        let options = step_up.options();
+        assert!(options.prompt.unwrap().contains(&Prompt::Login));
+        assert_eq!(Some(silver), options.acr_values.as_deref());
+        assert_eq!(Some(max_age), options.max_age);
+    }
+
+    #[test]
+    fn validate_token_without_subject() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let mut claims = testing::claims_json("https://example.com", "client");
+        claims["sub"] = json!("");
+        let mut token: IdToken<StandardClaims> =
+            IdToken::new_encoded(&testing::sign(claims.clone(), Some("key")));
+        client.decode_token(&mut token).unwrap();
+        assert!(matches!(
+            client.validate_token(&token, None, None),
+            Err(Error::Validation(Validation::Missing(Missing::Subject)))
+        ));
+        assert!(client.validate_token_all(&token, None, None).is_err());
+
+        // Without sub, the token does not even decode into StandardClaims
+        claims.as_object_mut().unwrap().remove("sub");
+        let mut token: IdToken<StandardClaims> =
+            IdToken::new_encoded(&testing::sign(claims, Some("key")));
+        assert!(client.decode_token(&mut token).is_err());
+    }
+
+    #[test]
+    fn validate_token_additional_issuer() {
+        let mut client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let claims = testing::claims_json("https://old.example.com", "client");
+        let mut token: IdToken<StandardClaims> =
+            IdToken::new_encoded(&testing::sign(claims, Some("key")));
+        client.decode_token(&mut token).unwrap();
+
+        assert!(matches!(
+            client.validate_token(&token, None, None),
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::Issuer { .. }
+            )))
+        ));
+
+        client.additional_issuers = vec![Url::parse("https://old.example.com").unwrap()];
+        client.validate_token(&token, None, None).unwrap();
+        client.validate_token_all(&token, None, None).unwrap();
+
+        // The provider issuer is still accepted
+        let claims = testing::claims_json("https://example.com", "client");
+        let mut token: IdToken<StandardClaims> =
+            IdToken::new_encoded(&testing::sign(claims, Some("key")));
+        client.decode_token(&mut token).unwrap();
+        client.validate_token(&token, None, None).unwrap();
+    }
+
+    #[test]
+    fn decode_token_caches_key_secret() {
+        let mut client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        for nonce in 0..100 {
+            let mut claims = testing::claims_json("https://example.com", "client");
+            claims["nonce"] = json!(nonce.to_string());
+            let mut token = IdToken::new_encoded(&testing::sign(claims, Some("key")));
+            client.decode_token(&mut token).unwrap();
+            client
+                .validate_token(&token, Some(&nonce.to_string()), None)
+                .unwrap();
+        }
+        assert_eq!(1, client.key_secrets.len());
+
+        // A replaced key set is used rather than the cached secret
+        let mut jwks = testing::jwks("key");
+        if let biscuit::jwk::AlgorithmParameters::OctetKey(ref mut parameters) =
+            jwks.keys[0].algorithm
+        {
+            parameters.value = b"other".to_vec();
+        }
+        client.jwks = Some(jwks);
+        let claims = testing::claims_json("https://example.com", "client");
+        let mut token: IdToken<StandardClaims> =
+            IdToken::new_encoded(&testing::sign(claims, Some("key")));
+        assert!(matches!(
+            client.decode_token(&mut token),
+            Err(Error::Jose(Jose::ValidationError(
+                biscuit::errors::ValidationError::InvalidSignature
+            )))
+        ));
+        // The secret of the replaced key is dropped
+        assert_eq!(1, client.key_secrets.len());
+    }
+
+    #[test]
+    fn key_secrets_lookup_does_not_allocate() {
+        let secrets = super::KeySecrets::default();
+        let jwks = testing::jwks("key");
+        let key = &jwks.keys[0];
+
+        let (_, built) = testing::allocations(|| secrets.with_secret(key, |_| ()).unwrap());
+        assert!(built > 0);
+        let (_, cached) = testing::allocations(|| secrets.with_secret(key, |_| ()).unwrap());
+        assert_eq!(0, cached);
+    }
+
+    #[test]
+    fn key_secrets_are_bounded() {
+        let secrets = super::KeySecrets::default();
+        for i in 0..=super::MAX_KEY_SECRETS {
+            let jwks = testing::jwks(&i.to_string());
+            secrets.with_secret(&jwks.keys[0], |_| ()).unwrap();
+        }
+        assert!(secrets.len() <= super::MAX_KEY_SECRETS);
+    }
+
+    #[test]
+    fn validate_token_all_valid() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let claims = testing::claims_json("https://example.com", "client");
+        let mut token = IdToken::new_encoded(&testing::sign(claims, Some("key")));
+        client.decode_token(&mut token).unwrap();
+
+        assert!(client.validate_token_all(&token, None, None).is_ok());
+    }
+
+    #[test]
+    fn verify_signature() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        // Claims are not validated
+        let claims = testing::claims_json("https://evil.example.com", "other");
+        let token = testing::sign(claims.clone(), Some("key"));
+        assert!(client.verify_signature(&token).unwrap());
+
+        let mut parts: Vec<_> = token.split('.').collect();
+        let mut tampered = claims;
+        tampered["sub"] = json!("admin");
+        let payload = base64::encode_config(tampered.to_string(), base64::URL_SAFE_NO_PAD);
+        parts[1] = &payload;
+        assert!(!client.verify_signature(&parts.join(".")).unwrap());
+    }
+
+    #[test]
+    fn verify_signature_rs256() {
+        let jwks = serde_json::json!({
+            "keys": [{"kty": "RSA", "kid": "key", "x5c": [base64::encode(testing::RSA_CERTIFICATE)]}]
+        });
+        let mut client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        client.jwks = Some(crate::jwks_from_reader(jwks.to_string().as_bytes()).unwrap());
+        let claims = testing::claims_json("https://example.com", "client");
+        let token = testing::sign_rs256(claims, Some("key"));
+        assert!(client.verify_signature(&token).unwrap());
+
+        let (content, _) = token.rsplit_once('.').unwrap();
+        let signature = base64::encode_config([0u8; 256], base64::URL_SAFE_NO_PAD);
+        let forged = format!("{}.{}", content, signature);
+        assert!(!client.verify_signature(&forged).unwrap());
+    }
+
+    #[test]
+    fn verify_signature_errors() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        assert!(client.verify_signature("not a jwt").is_err());
+
+        let claims = testing::claims_json("https://example.com", "client");
+        let mut client = client;
+        client.jwks = None;
+        assert!(matches!(
+            client.verify_signature(&testing::sign(claims, Some("key"))),
+            Err(Error::Decode(Decode::EmptySet))
+        ));
+    }
+
+    fn auth_url_scope(client: &DiscoveredClient, scope: Option<&str>) -> Option<String> {
+        let options = Options {
+            scope: scope.map(String::from),
+            ..Default::default()
+        };
+        let url = client.auth_url(&options);
+        url.query_pairs()
+            .find(|(key, _)| key == "scope")
+            .map(|(_, value)| value.into_owned())
+    }
+
+    #[test]
+    fn auth_url_injects_openid_scope() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        assert_eq!(Some("openid"), auth_url_scope(&client, None).as_deref());
+        assert_eq!(
+            Some("openid email"),
+            auth_url_scope(&client, Some("email")).as_deref()
+        );
+        assert_eq!(
+            Some("email openid"),
+            auth_url_scope(&client, Some("email openid")).as_deref()
+        );
+    }
+
+    #[test]
+    fn auth_url_without_openid_scope_injection() {
+        let mut client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        client.inject_openid_scope = false;
+        assert_eq!(None, auth_url_scope(&client, None));
+        assert_eq!(
+            Some("repo user"),
+            auth_url_scope(&client, Some("repo user")).as_deref()
+        );
+    }
+
+    #[test]
+    fn auth_url_locales_list() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let options = Options {
+            ui_locales_list: Some(vec![String::from("fr-CA"), String::from("en")]),
+            claims_locales_list: Some(vec![String::from("de")]),
+            ..Default::default()
+        };
+
+        let url = client.auth_url(&options);
+        // Spaces are form encoded as `+`, which is equivalent to `%20`
+        assert!(url.as_str().contains("ui_locales=fr-CA+en"));
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!("fr-CA en", query["ui_locales"]);
+        assert_eq!("de", query["claims_locales"]);
+    }
+
+    #[test]
+    fn auth_url_acr_values_list() {
+        let mut client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let options = Options {
+            acr_values_list: Some(vec![String::from("mfa"), String::from("pwd")]),
+            ..Default::default()
+        };
+
+        let url = client.auth_url(&options);
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!("mfa pwd", query["acr_values"]);
+
+        assert!(client.unsupported_acr_values(&options).is_empty());
+        client.provider = {
+            let mut config = client.config().clone();
+            config.acr_values_supported = Some(vec![String::from("pwd")]);
+            config.into()
+        };
+        assert_eq!(vec!["mfa"], client.unsupported_acr_values(&options));
+    }
+
+    #[test]
+    fn claims_request() {
+        let mut client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        client.provider = {
+            let mut config = client.config().clone();
+            config.claims_supported = Some(vec![String::from("sub"), String::from("email")]);
+            config.into()
+        };
+        let claims = json!({
+            "userinfo": {"email": {"essential": true}},
+            "id_token": {"email": null, "ssn": null},
+        });
+        let options = Options {
+            claims: Some(claims.clone()),
+            ..Default::default()
+        };
+
+        let url = client.auth_url(&options);
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(
+            claims,
+            serde_json::from_str::<serde_json::Value>(&query["claims"]).unwrap()
+        );
+
+        match client.validate_claims_request(&options) {
+            Err(Error::UnsupportedClaims(claims)) => assert_eq!(vec!["ssn"], claims),
+            result => panic!("expected unsupported claims, got {:?}", result),
+        }
+        client.provider = {
+            let mut config = client.config().clone();
+            config.claims_supported = None;
+            config.into()
+        };
+        client.validate_claims_request(&options).unwrap();
+    }
+
+    #[test]
+    fn auth_url_with_request_uri() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let options = Options {
+            state: Some(String::from("state")),
+            nonce: Some(String::from("nonce")),
+            request_uri: Some(String::from("https://app.example.com/request.jwt")),
+            ..Default::default()
+        };
+
+        let url = client.try_auth_url(&options).unwrap();
+        let query: Vec<_> = url.query_pairs().into_owned().collect();
+        let query: Vec<_> = query
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("response_type", "code"),
+                ("client_id", "client"),
+                ("scope", "openid"),
+                ("request_uri", "https://app.example.com/request.jwt"),
+            ],
+            query
+        );
+    }
+
+    #[test]
+    fn auth_url_rejects_request_and_request_uri() {
+        let client = multi_redirect_client(&Url::parse("https://example.com/").unwrap());
+        let options = Options {
+            request: Some(String::from("eyJhbGciOiJub25lIn0.e30.")),
+            request_uri: Some(String::from("https://app.example.com/request.jwt")),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            client.try_auth_url(&options),
+            Err(Error::RequestAndRequestUri)
+        ));
+        assert!(matches!(
+            client.auth_url_with_redirect(&options, "https://app.example.com/callback"),
+            Err(Error::RequestAndRequestUri)
+        ));
+    }
+
+    #[test]
+    fn auth_url_locales_string_takes_precedence() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let options = Options {
+            ui_locales: Some(String::from("en")),
+            ui_locales_list: Some(vec![String::from("fr-CA")]),
+            ..Default::default()
+        };
+
+        let url = client.auth_url(&options);
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!("en", query["ui_locales"]);
+    }
+
+    fn auth_url_prompt(prompt: &[Prompt]) -> String {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let options = Options {
+            prompt: Some(prompt.iter().cloned().collect()),
+            ..Default::default()
+        };
+        let url = client.auth_url(&options);
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        query["prompt"].clone()
+    }
+
+    #[test]
+    fn auth_url_prompt_none_is_exclusive() {
+        assert_eq!("none", auth_url_prompt(&[Prompt::None, Prompt::Login]));
+        assert_eq!("none", auth_url_prompt(&[Prompt::None]));
+        let prompt = auth_url_prompt(&[Prompt::Login, Prompt::Consent]);
+        assert!(prompt == "login consent" || prompt == "consent login");
+    }
+
+    #[test]
+    fn auth_url_prompt_create() {
+        assert_eq!("create", auth_url_prompt(&[Prompt::Create]));
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        operations: std::sync::Mutex<Vec<(crate::Operation, bool)>>,
+    }
+
+    impl crate::ClientObserver for RecordingObserver {
+        fn on_operation(
+            &self,
+            operation: crate::Operation,
+            _duration: std::time::Duration,
+            success: bool,
+        ) {
+            self.operations.lock().unwrap().push((operation, success));
+        }
+    }
+
+    #[tokio::test]
+    async fn observer_called_per_token_request() {
+        let server = token_server();
+        let observer = Arc::new(RecordingObserver::default());
+        let mut client = discovered_client(&server.url("/"));
+        client.observer = Some(observer.clone());
+
+        client.request_token("code").await.unwrap();
+        assert_eq!(
+            vec![(crate::Operation::Token, true)],
+            *observer.operations.lock().unwrap()
+        );
+
+        // The token server answers userinfo requests with 404
+        let result = client.request_userinfo_with_access_token("fresh").await;
+        assert!(result.is_err());
+        assert_eq!(
+            (crate::Operation::Userinfo, false),
+            observer.operations.lock().unwrap()[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn request_token_with_timeout() {
+        let server = MockServer::start(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            Response::json(json!({"access_token": "fresh"}))
+        });
+        let mut client = discovered_client(&server.url("/"));
+        client.http_client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .unwrap();
+
+        let timeout = Some(std::time::Duration::from_millis(1));
+        match client.request_token_with_timeout("code", timeout).await {
+            Err(crate::error::ClientError::Transport(error)) => {
+                assert!(error.is_timeout(), "{}", error)
+            }
+            result => panic!("expected a timeout, got {:?}", result),
+        }
+
+        let bearer = client.request_token_with_timeout("code", None).await;
+        assert_eq!("fresh", bearer.unwrap().access_token);
+    }
+
+    #[tokio::test]
+    async fn empty_code_is_rejected() {
+        let server = token_server();
+        let client = discovered_client(&server.url("/"));
+
+        for code in ["", " \t\n"] {
+            assert!(matches!(
+                client.request_token(code).await,
+                Err(crate::error::ClientError::EmptyCode)
+            ));
+            assert!(matches!(
+                client.authenticate(code, None, None).await,
+                Err(Error::ClientError(crate::error::ClientError::EmptyCode))
+            ));
+        }
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn form_encoded_token_response() {
+        let server = MockServer::start(|_| Response {
+            status: 200,
+            headers: vec![(
+                "Content-Type".into(),
+                "application/x-www-form-urlencoded; charset=utf-8".into(),
+            )],
+            body: "access_token=form%20token&token_type=bearer&expires_in=3600&scope=read+write"
+                .into(),
+        });
+        let client = discovered_client(&server.url("/"));
+
+        let bearer = client.request_token("code").await.unwrap();
+        assert_eq!("form token", bearer.access_token);
+        assert_eq!(Some("Bearer"), bearer.token_type.as_deref());
+        assert_eq!(Some("read write"), bearer.scope.as_deref());
+        assert!(bearer.expires.is_some());
+    }
+
+    #[tokio::test]
+    async fn form_encoded_token_error() {
+        let server = MockServer::start(|_| Response {
+            status: 400,
+            headers: vec![(
+                "Content-Type".into(),
+                "application/x-www-form-urlencoded".into(),
+            )],
+            body: "error=invalid_grant".into(),
+        });
+        let client = discovered_client(&server.url("/"));
+
+        assert!(matches!(
+            client.request_token("code").await,
+            Err(crate::error::ClientError::OAuth(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn request_token_transport_error() {
+        // Nothing listens on the port of a dropped listener
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        drop(listener);
+        let client = discovered_client(&base);
+
+        let error = client.request_token("code").await.unwrap_err();
+        assert!(matches!(error, crate::error::ClientError::Transport(_)));
+    }
+
+    #[tokio::test]
+    async fn request_token_oauth2_error() {
+        let server = MockServer::start(|_| {
+            Response::with_status(
+                400,
+                json!({"error": "invalid_grant", "error_description": "Code expired"}),
+            )
+        });
+        let client = discovered_client(&server.url("/"));
+
+        let error = client.request_token("code").await.unwrap_err();
+        assert!(matches!(
+            error,
+            crate::error::ClientError::OAuth(ref error)
+                if error.error == crate::OAuth2ErrorCode::InvalidGrant
+        ));
+    }
+
+    #[tokio::test]
+    async fn request_token_decode_error() {
+        let server = MockServer::start(|_| Response::json(json!({"token_type": "Bearer"})));
+        let client = discovered_client(&server.url("/"));
+
+        let error = client.request_token("code").await.unwrap_err();
+        assert!(matches!(error, crate::error::ClientError::Decode(_)));
+    }
+
+    #[tokio::test]
+    async fn token_response_with_unrelated_error_field() {
+        let server = MockServer::start(|_| {
+            Response::json(json!({"access_token": "fresh", "error": "invalid_request"}))
+        });
+        let client = discovered_client(&server.url("/"));
+
+        let bearer = client.request_token("code").await.unwrap();
+        assert_eq!("fresh", bearer.access_token);
+    }
+
+    #[tokio::test]
+    async fn error_response_with_success_status() {
+        let server = MockServer::start(|_| Response::json(json!({"error": "invalid_grant"})));
+        let client = discovered_client(&server.url("/"));
+
+        assert!(matches!(
+            client.request_token("code").await,
+            Err(crate::error::ClientError::OAuth(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn authorize_offline() {
+        let server = MockServer::start(|_| {
+            Response::json(json!({"access_token": "fresh", "refresh_token": "refresh"}))
+        });
+        let mut client = discovered_client(&server.url("/"));
+        client.inject_openid_scope = false;
+
+        let token = client.authorize_offline("code", &session()).await.unwrap();
+        assert_eq!(Some("refresh"), token.bearer.refresh_token.as_deref());
+    }
+
+    #[tokio::test]
+    async fn authorize_offline_without_refresh_token() {
+        let server = token_server();
+        let mut client = discovered_client(&server.url("/"));
+        client.inject_openid_scope = false;
+
+        assert!(matches!(
+            client.authorize_offline("code", &session()).await,
+            Err(Error::MissingRefreshToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn authenticate_without_id_token() {
+        let server = token_server();
+        let mut client = discovered_client(&server.url("/"));
+
+        for token in [
+            client.authenticate("code", None, None).await,
+            client.authenticate_session("code", &session()).await,
+        ] {
+            assert!(matches!(
+                token,
+                Err(Error::Validation(Validation::Missing(Missing::IdToken)))
+            ));
+        }
+
+        // Pure OAuth 2.0 flows without the openid scope have no id_token
+        client.inject_openid_scope = false;
+        let token = client.authenticate("code", None, None).await.unwrap();
+        assert!(token.id_token.is_none());
+    }
+
+    #[tokio::test]
+    async fn authenticate_granted_openid_without_id_token() {
+        let server = MockServer::start(|_| {
+            Response::json(json!({"access_token": "fresh", "scope": "email openid"}))
+        });
+        let mut client = discovered_client(&server.url("/"));
+        client.inject_openid_scope = false;
+
+        assert!(matches!(
+            client.authenticate("code", None, None).await,
+            Err(Error::Validation(Validation::Missing(Missing::IdToken)))
+        ));
+    }
+
+    /// Token endpoint signing an id_token with the nonce sent in the token request.
+    fn nonce_echo_server() -> MockServer {
+        MockServer::start(|request| {
+            let base = Url::parse(&format!("http://{}/", request.header("host").unwrap())).unwrap();
+            let body: HashMap<_, _> = url::form_urlencoded::parse(request.body.as_bytes())
+                .into_owned()
+                .collect();
+            let mut claims = testing::claims_json(base.as_str().trim_end_matches('/'), "client");
+            claims["nonce"] = json!(body["nonce"]);
+            Response::json(json!({
+                "access_token": "fresh",
+                "id_token": testing::sign(claims, Some("key")),
+            }))
+        })
+    }
+
+    #[tokio::test]
+    async fn authenticate_with_store() {
+        let server = nonce_echo_server();
+        let mut client = discovered_client(&server.url("/"));
+        client.nonce_in_token_request = true;
+        let store = crate::MemorySessionStore::new();
+
+        let url = client.auth_url_with_store(&Options::default(), &store);
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(1, store.len());
+
+        let redirect = Url::parse(&format!(
+            "https://app.example.com/callback?code=code&state={}",
+            query["state"]
+        ))
+        .unwrap();
+        let token = client
+            .authenticate_with_store(&redirect, &store)
+            .await
+            .unwrap();
+        assert!(token.id_token.is_some());
+        let body = &server.requests_to("/token")[0].body;
+        assert!(body.contains("code_verifier="));
+        assert!(store.is_empty());
+
+        // The session is used once
+        assert!(matches!(
+            client.authenticate_with_store(&redirect, &store).await,
+            Err(Error::UnknownSession(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn authenticate_with_store_without_state() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let store = crate::MemorySessionStore::new();
+
+        let redirect = Url::parse("https://app.example.com/callback?code=code").unwrap();
+        assert!(matches!(
+            client.authenticate_with_store(&redirect, &store).await,
+            Err(Error::Validation(Validation::Missing(Missing::State)))
+        ));
+        let redirect = Url::parse("https://app.example.com/callback?error=access_denied").unwrap();
+        assert!(matches!(
+            client.authenticate_with_store(&redirect, &store).await,
+            Err(Error::ClientError(crate::error::ClientError::OAuth(_)))
+        ));
+    }
+
+    /// The DER encoded key of a PEM document.
+    fn pem_to_der(pem: &str) -> Vec<u8> {
+        let base64: String = pem
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        base64::decode(base64).unwrap()
+    }
+
+    #[test]
+    fn decode_token_with_pem_key() {
+        let client = discovered_client(&Url::parse("https://example.com/").unwrap());
+        let secret = Secret::PublicKey(pem_to_der(testing::RSA_PUBLIC_KEY_PEM));
+        let claims = testing::claims_json("https://example.com", "client");
+
+        let mut token = IdToken::new_encoded(&testing::sign_rs256(claims.clone(), None));
+        client
+            .decode_token_with_key(&mut token, &secret, SignatureAlgorithm::RS256)
+            .unwrap();
+        assert_eq!("subject", token.payload().unwrap().sub);
+
+        // The signature must match the key
+        let mut token = IdToken::new_encoded(&testing::sign_rs256(claims.clone(), None));
+        let other = Secret::PublicKey(vec![0; 270]);
+        assert!(client
+            .decode_token_with_key(&mut token, &other, SignatureAlgorithm::RS256)
+            .is_err());
+
+        // The token alg must be the given one
+        let mut token = IdToken::new_encoded(&testing::sign(claims, None));
+        assert!(matches!(
+            client.decode_token_with_key(&mut token, &secret, SignatureAlgorithm::RS256),
+            Err(Error::Jose(Jose::WrongKeyType { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn userinfo_from_id_token_without_endpoint() {
+        let base = Url::parse("https://example.com/").unwrap();
+        let mut config = config_json(&base);
+        config.as_object_mut().unwrap().remove("userinfo_endpoint");
+        let client = DiscoveredClient::from_config(
+            serde_json::from_value(config).unwrap(),
+            Some(testing::jwks("key")),
+            String::from("client"),
+            String::from("secret"),
+            None,
+            reqwest::Client::new(),
+        )
+        .unwrap();
+
+        let mut claims = testing::claims_json("https://example.com", "client");
+        claims["name"] = json!("Jane Doe");
+        claims["email"] = json!("jane@example.com");
+        let bearer: Bearer = serde_json::from_value(json!({
+            "access_token": "fresh",
+            "id_token": testing::sign(claims, Some("key")),
+        }))
+        .unwrap();
+        let mut token: Token = bearer.into();
+        client
+            .decode_token(token.id_token.as_mut().unwrap())
+            .unwrap();
+
+        let info = client.userinfo_or_idtoken(&token).await.unwrap();
+        assert_eq!(Some("subject"), info.sub.as_deref());
+        assert_eq!(Some("Jane Doe"), info.name.as_deref());
+        assert_eq!(Some("jane@example.com"), info.email.as_deref());
+
+        token.id_token = None;
+        assert!(matches!(
+            client.userinfo_or_idtoken(&token).await,
+            Err(Error::Userinfo(crate::error::Userinfo::NoUrl))
+        ));
+    }
 }
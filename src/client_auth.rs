@@ -0,0 +1,14 @@
+/// How the client authenticates at the token endpoint.
+///
+/// See [RFC 6749, section 2.3](http://tools.ietf.org/html/rfc6749#section-2.3) and
+/// [RFC 8705, section 2](https://www.rfc-editor.org/rfc/rfc8705#section-2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClientAuth {
+    /// `client_secret_basic`, with the credentials also sent in the request body where the
+    /// provider or the grant requires it.
+    #[default]
+    ClientSecret,
+    /// `tls_client_auth` or `self_signed_tls_client_auth`. The client certificate has to be
+    /// configured on the `http_client`, only the `client_id` is sent in the request body.
+    MutualTls,
+}
@@ -0,0 +1,47 @@
+use std::fmt;
+use zeroize::Zeroizing;
+
+/// Client secret, zeroed in memory on drop and never printed by `Debug`.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct ClientSecret(Zeroizing<String>);
+
+impl ClientSecret {
+    pub fn new(secret: String) -> Self {
+        Self(Zeroizing::new(secret))
+    }
+
+    /// The secret itself, e.g. to send it to the provider.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for ClientSecret {
+    fn from(secret: String) -> Self {
+        Self::new(secret)
+    }
+}
+
+impl From<&str> for ClientSecret {
+    fn from(secret: &str) -> Self {
+        Self::new(secret.to_string())
+    }
+}
+
+impl fmt::Debug for ClientSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ClientSecret(***)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_redacts_secret() {
+        let secret = ClientSecret::from("hunter2");
+        assert_eq!("hunter2", secret.as_str());
+        assert!(!format!("{:?}", secret).contains("hunter2"));
+    }
+}
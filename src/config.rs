@@ -15,7 +15,9 @@ pub struct Config {
     pub userinfo_endpoint: Option<Url>,
     #[serde(default)]
     pub end_session_endpoint: Option<Url>,
-    pub jwks_uri: Url,
+    /// Required by OpenID Connect, but missing for some pure OAuth 2.0 servers.
+    #[serde(default)]
+    pub jwks_uri: Option<Url>,
     #[serde(default)]
     pub registration_endpoint: Option<Url>,
     #[serde(default)]
@@ -31,7 +33,14 @@ pub struct Config {
     pub grant_types_supported: Option<Vec<String>>,
     #[serde(default)]
     pub acr_values_supported: Option<Vec<String>>,
-    // pairwise and public are valid by spec, but servers can add more
+    /// Subject identifier types, `public` and/or `pairwise`.
+    ///
+    /// With `pairwise` every client (or sector, see `sector_identifier_uri` in client
+    /// registration) sees a different `sub` for the same user. The userinfo subject-match check
+    /// is unaffected, as the id_token and userinfo of one client carry the same pairwise `sub`,
+    /// but subjects must not be correlated across clients of different sectors.
+    ///
+    /// See [spec 8](https://openid.net/specs/openid-connect-core-1_0.html#SubjectIDTypes)
     #[serde(default = "empty_string_vec")]
     pub subject_types_supported: Vec<String>,
     // Must include at least RS256, none is only allowed with response types without id tokens
@@ -89,6 +98,55 @@ pub struct Config {
     // This is a NONSTANDARD extension Google uses that is a part of the Oauth discovery draft
     #[serde(default)]
     pub code_challenge_methods_supported: Option<Vec<String>>,
+    /// The provider sends its issuer as `iss` parameter in authorization responses, see
+    /// [RFC 9207](https://www.rfc-editor.org/rfc/rfc9207).
+    #[serde(default)]
+    pub authorization_response_iss_parameter_supported: bool,
+    /// See [RFC 9126](https://www.rfc-editor.org/rfc/rfc9126).
+    #[serde(default)]
+    pub pushed_authorization_request_endpoint: Option<Url>,
+    #[serde(default)]
+    pub require_pushed_authorization_requests: bool,
+    /// See [RFC 8628](https://www.rfc-editor.org/rfc/rfc8628).
+    #[serde(default)]
+    pub device_authorization_endpoint: Option<Url>,
+}
+
+/// Predicates on the advertised capabilities of the provider. A missing `*_supported` array
+/// means the provider does not tell, so everything is assumed to be supported. Only a present
+/// array without the value means it is not supported.
+impl Config {
+    pub fn supports_scope(&self, scope: &str) -> bool {
+        supports(self.scopes_supported.as_deref(), scope)
+    }
+
+    pub fn supports_grant_type(&self, grant_type: &str) -> bool {
+        supports(self.grant_types_supported.as_deref(), grant_type)
+    }
+
+    /// `response_types_supported` is required by the spec, an empty array is treated as missing.
+    pub fn supports_response_type(&self, response_type: &str) -> bool {
+        let supported = Some(self.response_types_supported.as_slice()).filter(|s| !s.is_empty());
+        supports(supported, response_type)
+    }
+
+    pub fn supports_acr_value(&self, acr_value: &str) -> bool {
+        supports(self.acr_values_supported.as_deref(), acr_value)
+    }
+
+    pub fn supports_claim(&self, claim: &str) -> bool {
+        supports(self.claims_supported.as_deref(), claim)
+    }
+
+    /// `subject_types_supported` is required by the spec, an empty array is treated as missing.
+    pub fn supports_subject_type(&self, subject_type: &str) -> bool {
+        let supported = Some(self.subject_types_supported.as_slice()).filter(|s| !s.is_empty());
+        supports(supported, subject_type)
+    }
+}
+
+fn supports(supported: Option<&[String]>, value: &str) -> bool {
+    supported.map_or(true, |supported| supported.iter().any(|s| s == value))
 }
 
 // This seems really dumb...
@@ -99,3 +157,73 @@ fn tru() -> bool {
 fn empty_string_vec() -> Vec<String> {
     vec![]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn config_with(supported: Value) -> Config {
+        let mut config = json!({
+            "issuer": "https://example.com",
+            "authorization_endpoint": "https://example.com/auth",
+            "token_endpoint": "https://example.com/token",
+            "jwks_uri": "https://example.com/jwks",
+            "response_types_supported": [],
+        });
+        config
+            .as_object_mut()
+            .unwrap()
+            .extend(supported.as_object().unwrap().clone());
+        serde_json::from_value(config).unwrap()
+    }
+
+    #[test]
+    fn supports_scope() {
+        let config = config_with(json!({"scopes_supported": ["openid", "email"]}));
+        assert!(config.supports_scope("email"));
+        assert!(!config.supports_scope("phone"));
+        assert!(config_with(json!({})).supports_scope("phone"));
+    }
+
+    #[test]
+    fn supports_grant_type() {
+        let config = config_with(json!({"grant_types_supported": ["authorization_code"]}));
+        assert!(config.supports_grant_type("authorization_code"));
+        assert!(!config.supports_grant_type("password"));
+        assert!(config_with(json!({})).supports_grant_type("password"));
+    }
+
+    #[test]
+    fn supports_response_type() {
+        let config = config_with(json!({"response_types_supported": ["code"]}));
+        assert!(config.supports_response_type("code"));
+        assert!(!config.supports_response_type("id_token"));
+        assert!(config_with(json!({})).supports_response_type("id_token"));
+    }
+
+    #[test]
+    fn supports_acr_value() {
+        let config = config_with(json!({"acr_values_supported": ["urn:mace:incommon:iap:silver"]}));
+        assert!(config.supports_acr_value("urn:mace:incommon:iap:silver"));
+        assert!(!config.supports_acr_value("urn:mace:incommon:iap:bronze"));
+        assert!(config_with(json!({})).supports_acr_value("urn:mace:incommon:iap:bronze"));
+    }
+
+    #[test]
+    fn supports_claim() {
+        let config = config_with(json!({"claims_supported": ["sub", "email"]}));
+        assert!(config.supports_claim("email"));
+        assert!(!config.supports_claim("phone_number"));
+        assert!(config_with(json!({})).supports_claim("phone_number"));
+    }
+
+    #[test]
+    fn subject_types_supported() {
+        let config = config_with(json!({"subject_types_supported": ["pairwise"]}));
+        assert_eq!(vec!["pairwise"], config.subject_types_supported);
+        assert!(config.supports_subject_type("pairwise"));
+        assert!(!config.supports_subject_type("public"));
+        assert!(config_with(json!({})).supports_subject_type("public"));
+    }
+}
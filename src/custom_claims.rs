@@ -52,7 +52,7 @@ where
     fn aud(&self) -> &crate::SingleOrMultiple<String> {
         self.standard_claims().aud()
     }
-    fn exp(&self) -> i64 {
+    fn exp(&self) -> Option<i64> {
         self.standard_claims().exp()
     }
     fn iat(&self) -> i64 {
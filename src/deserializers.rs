@@ -1,7 +1,10 @@
+use biscuit::SingleOrMultiple;
 use de::Visitor;
-use serde::de;
-use serde::Deserializer;
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
 
+/// Deserializes a boolean, which may also arrive as the string `"true"` or `"false"`.
 pub fn bool_from_str_or_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: Deserializer<'de>,
@@ -9,6 +12,20 @@ where
     deserializer.deserialize_any(BoolOrStringVisitor)
 }
 
+/// Like [`bool_from_str_or_bool`], keeping an absent or null value apart from `false`.
+pub fn option_bool_from_str_or_bool<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .deserialize_any(BoolOrStringVisitor)
+            .map(Some)
+            .map_err(de::Error::custom),
+    }
+}
+
 struct BoolOrStringVisitor;
 
 impl<'de> Visitor<'de> for BoolOrStringVisitor {
@@ -36,3 +53,151 @@ impl<'de> Visitor<'de> for BoolOrStringVisitor {
         }
     }
 }
+
+/// Deserializes an unsigned integer, which may also arrive as a string.
+pub fn u64_from_str_or_u64<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(U64OrStringVisitor)
+}
+
+struct U64OrStringVisitor;
+
+impl<'de> Visitor<'de> for U64OrStringVisitor {
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an unsigned integer or a string containing one.")
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<u64, E>
+    where
+        E: de::Error,
+    {
+        Ok(value)
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<u64, E>
+    where
+        E: de::Error,
+    {
+        u64::try_from(value).map_err(|_| E::custom(format!("Negative value: {}", value)))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<u64, E>
+    where
+        E: de::Error,
+    {
+        value
+            .trim()
+            .parse()
+            .map_err(|_| E::custom(format!("Unknown string value: {}", value)))
+    }
+}
+
+/// Deserializes an optional JSON object, which may also arrive encoded as a JSON string.
+pub fn option_from_json_str_or_object<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => serde_json::from_str(&s)
+            .map(Some)
+            .map_err(de::Error::custom),
+        Some(value) => serde_json::from_value(value)
+            .map(Some)
+            .map_err(de::Error::custom),
+    }
+}
+
+/// Deserializes an `aud` claim, splitting a single space delimited string like
+/// `"client1 client2"` into multiple audiences, as some providers send it. Arrays and single
+/// audiences are deserialized as usual.
+///
+/// # Examples
+///
+/// ```
+/// use openid::SingleOrMultiple;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct MyClaims {
+///     #[serde(deserialize_with = "openid::deserializers::aud_from_space_delimited_str")]
+///     aud: SingleOrMultiple<String>,
+/// }
+///
+/// let claims: MyClaims = serde_json::from_str(r#"{"aud": "client1 client2"}"#).unwrap();
+/// assert!(claims.aud.contains("client2"));
+/// ```
+pub fn aud_from_space_delimited_str<'de, D>(
+    deserializer: D,
+) -> Result<SingleOrMultiple<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match SingleOrMultiple::<String>::deserialize(deserializer)? {
+        SingleOrMultiple::Single(aud) if aud.contains(' ') => Ok(SingleOrMultiple::Multiple(
+            aud.split_whitespace().map(String::from).collect(),
+        )),
+        aud => Ok(aud),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::Error, validation::validate_token_aud, StandardClaims};
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct Audience {
+        #[serde(deserialize_with = "aud_from_space_delimited_str")]
+        aud: SingleOrMultiple<String>,
+    }
+
+    fn aud(value: Value) -> SingleOrMultiple<String> {
+        serde_json::from_value::<Audience>(json!({ "aud": value }))
+            .unwrap()
+            .aud
+    }
+
+    #[test]
+    fn space_delimited_aud() {
+        let mut claims: StandardClaims = serde_json::from_value(json!({
+            "iss": "https://example.com",
+            "sub": "subject",
+            "aud": "other client",
+            "azp": "client",
+            "exp": 0,
+            "iat": 0,
+        }))
+        .unwrap();
+        // The standard deserialization sees a single audience
+        assert!(matches!(
+            validate_token_aud(&claims, "client"),
+            Err(Error::Validation(_))
+        ));
+
+        claims.aud = aud(json!("other client"));
+        assert_eq!(
+            SingleOrMultiple::Multiple(vec!["other".to_string(), "client".into()]),
+            claims.aud
+        );
+        validate_token_aud(&claims, "client").unwrap();
+    }
+
+    #[test]
+    fn compliant_aud() {
+        assert_eq!(
+            SingleOrMultiple::Single("client".to_string()),
+            aud(json!("client"))
+        );
+        assert_eq!(
+            SingleOrMultiple::Multiple(vec!["client".to_string(), "other".into()]),
+            aud(json!(["client", "other"]))
+        );
+    }
+}
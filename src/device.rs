@@ -0,0 +1,297 @@
+use crate::{
+    error::{ClientError, Error},
+    Bearer, Claims, Client, Configurable, OAuth2ErrorCode, Operation, Provider,
+};
+use biscuit::CompactJson;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+use url::{form_urlencoded::Serializer, Url};
+
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Seconds between the token requests if the provider sends no `interval`.
+const DEFAULT_INTERVAL: u64 = 5;
+
+/// Minimum seconds between the token requests, also if the provider sends a lower `interval`.
+const MIN_INTERVAL: u64 = 1;
+
+/// Increase of the interval on each `slow_down` of the provider.
+const SLOW_DOWN: Duration = Duration::from_secs(5);
+
+/// Response of the device authorization endpoint. Show the `verification_uri` and `user_code` to
+/// the user, then wait for the token with [`Client::await_device_token`].
+///
+/// See [RFC 8628, section 3.2](https://www.rfc-editor.org/rfc/rfc8628#section-3.2).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    /// Code the user enters at the `verification_uri`.
+    pub user_code: String,
+    pub verification_uri: Url,
+    /// The `verification_uri` including the `user_code`, e.g. to show as QR code.
+    #[serde(default)]
+    pub verification_uri_complete: Option<Url>,
+    /// Seconds until the `device_code` expires.
+    pub expires_in: u64,
+    /// Minimum seconds between the token requests, 5 if not sent.
+    #[serde(default)]
+    pub interval: Option<u64>,
+}
+
+impl<C: CompactJson + Claims, P: Provider + Configurable> Client<P, C> {
+    /// Starts the device authorization grant, for devices without a browser or with limited
+    /// input. Errors with NoDeviceAuthorizationUrl if the provider has no device authorization
+    /// endpoint.
+    ///
+    /// See [RFC 8628](https://www.rfc-editor.org/rfc/rfc8628).
+    pub async fn request_device_authorization(
+        &self,
+        scope: Option<&str>,
+    ) -> Result<DeviceAuthorization, Error> {
+        let url = self
+            .config()
+            .device_authorization_endpoint
+            .as_ref()
+            .ok_or(Error::NoDeviceAuthorizationUrl)?;
+
+        let mut body = Serializer::new(String::new());
+        if let Some(scope) = scope {
+            body.append_pair("scope", scope);
+        }
+        let json = self
+            .post_form(url, Operation::DeviceAuthorization, body)
+            .await?;
+        Ok(serde_json::from_value(json).map_err(ClientError::from)?)
+    }
+
+    /// Requests the token of a device authorization once. Until the user approved the device,
+    /// the provider answers with the OAuth2 error `authorization_pending`, or `slow_down` if
+    /// polled too often.
+    ///
+    /// See [RFC 8628, section 3.4](https://www.rfc-editor.org/rfc/rfc8628#section-3.4).
+    pub async fn poll_device_token(
+        &self,
+        device_authorization: &DeviceAuthorization,
+    ) -> Result<Bearer, ClientError> {
+        let body = {
+            let mut body = Serializer::new(String::new());
+            body.append_pair("grant_type", DEVICE_CODE_GRANT);
+            body.append_pair("device_code", &device_authorization.device_code);
+            self.append_credentials(&mut body, self.provider.credentials_in_body());
+            body.finish()
+        };
+
+        let json = self.post_token(body, None).await?;
+        Ok(serde_json::from_value(json)?)
+    }
+
+    /// Polls the token of a device authorization with [`Client::poll_device_token`] every
+    /// `interval`, at least every second, until the user approved the device. A `slow_down` of
+    /// the provider increases the interval by 5 seconds. Errors with DeviceCodeExpired once the
+    /// `expires_in` of the device authorization passed, counted from this call, or if the
+    /// provider reports it expired. Other errors of the provider, e.g. `access_denied`, are
+    /// returned as they are.
+    ///
+    /// Polling stops when the returned future is dropped, e.g. to let the user cancel. Waits with
+    /// the timer of tokio, so the future must run on a tokio runtime, as reqwest requires anyway.
+    pub async fn await_device_token(
+        &self,
+        device_authorization: &DeviceAuthorization,
+    ) -> Result<Bearer, Error> {
+        self.await_device_token_with(device_authorization, SLOW_DOWN)
+            .await
+    }
+
+    async fn await_device_token_with(
+        &self,
+        device_authorization: &DeviceAuthorization,
+        slow_down: Duration,
+    ) -> Result<Bearer, Error> {
+        let expires = Instant::now() + Duration::from_secs(device_authorization.expires_in);
+        let interval = device_authorization.interval.unwrap_or(DEFAULT_INTERVAL);
+        let mut interval = Duration::from_secs(interval.max(MIN_INTERVAL));
+        loop {
+            let remaining = expires.saturating_duration_since(Instant::now());
+            // The next request would be too late
+            if remaining <= interval {
+                sleep(remaining).await;
+                return Err(Error::DeviceCodeExpired);
+            }
+            sleep(interval).await;
+
+            let error = match self.poll_device_token(device_authorization).await {
+                Ok(bearer) => return Ok(bearer),
+                Err(ClientError::OAuth(error)) => error,
+                Err(error) => return Err(error.into()),
+            };
+            match error.error {
+                OAuth2ErrorCode::Unrecognized(ref code) if code == "authorization_pending" => {}
+                OAuth2ErrorCode::Unrecognized(ref code) if code == "slow_down" => {
+                    interval += slow_down;
+                }
+                OAuth2ErrorCode::Unrecognized(ref code) if code == "expired_token" => {
+                    return Err(Error::DeviceCodeExpired);
+                }
+                _ => return Err(ClientError::OAuth(error).into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{config_json, MockServer, Response},
+        Config, DiscoveredClient,
+    };
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn device_client(server: &MockServer) -> DiscoveredClient {
+        let mut json = config_json(&server.url("/"));
+        json["device_authorization_endpoint"] = json!(server.url("/device"));
+        let config: Config = serde_json::from_value(json).unwrap();
+        DiscoveredClient::from_config(
+            config,
+            None,
+            String::from("client"),
+            String::from("secret"),
+            None,
+            reqwest::Client::new(),
+        )
+        .unwrap()
+    }
+
+    fn device_authorization(expires_in: u64, interval: u64) -> DeviceAuthorization {
+        serde_json::from_value(json!({
+            "device_code": "device",
+            "user_code": "WDJB-MJHT",
+            "verification_uri": "https://example.com/device",
+            "expires_in": expires_in,
+            "interval": interval,
+        }))
+        .unwrap()
+    }
+
+    /// Answers the token requests with the errors in order, then with a token.
+    fn token_server(errors: &'static [&'static str]) -> MockServer {
+        let polls = AtomicUsize::new(0);
+        MockServer::start(move |request| match request.path.as_str() {
+            "/token" => match errors.get(polls.fetch_add(1, Ordering::SeqCst)) {
+                Some(error) => Response::with_status(400, json!({ "error": error })),
+                None => Response::json(json!({"access_token": "device_token"})),
+            },
+            _ => Response::with_status(404, json!({})),
+        })
+    }
+
+    #[tokio::test]
+    async fn request_device_authorization() {
+        let server = MockServer::start(|request| match request.path.as_str() {
+            "/device" => Response::json(json!({
+                "device_code": "device",
+                "user_code": "WDJB-MJHT",
+                "verification_uri": "https://example.com/device",
+                "verification_uri_complete": "https://example.com/device?user_code=WDJB-MJHT",
+                "expires_in": 1800,
+            })),
+            _ => Response::with_status(404, json!({})),
+        });
+        let client = device_client(&server);
+
+        let device_authorization = client
+            .request_device_authorization(Some("openid profile"))
+            .await
+            .unwrap();
+
+        assert_eq!("WDJB-MJHT", device_authorization.user_code);
+        assert_eq!(1800, device_authorization.expires_in);
+        assert_eq!(None, device_authorization.interval);
+        let body = &server.requests_to("/device")[0].body;
+        assert!(body.contains("client_id=client"));
+        assert!(body.contains("scope=openid+profile"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn await_device_token_after_pending_and_slow_down() {
+        let server = token_server(&["authorization_pending", "slow_down"]);
+        let client = device_client(&server);
+
+        let start = Instant::now();
+        let bearer = client
+            .await_device_token_with(&device_authorization(60, 0), Duration::from_millis(10))
+            .await
+            .unwrap();
+
+        assert_eq!("device_token", bearer.access_token);
+        // An interval of 0 is raised to a second
+        assert!(start.elapsed() >= Duration::from_millis(3010));
+        let requests = server.requests_to("/token");
+        assert_eq!(3, requests.len());
+        assert!(requests[0]
+            .body
+            .contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Adevice_code"));
+        assert!(requests[0].body.contains("device_code=device"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn await_device_token_times_out() {
+        let server = token_server(&["authorization_pending"; 10]);
+        let client = device_client(&server);
+
+        let start = Instant::now();
+        let error = client
+            .await_device_token(&device_authorization(2, 1))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, Error::DeviceCodeExpired));
+        assert!(start.elapsed() >= Duration::from_secs(2));
+        // A second request would be after the expiry
+        assert_eq!(1, server.requests_to("/token").len());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn await_device_token_expired_or_denied() {
+        let server = token_server(&["expired_token"]);
+        let client = device_client(&server);
+        let error = client
+            .await_device_token(&device_authorization(60, 0))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::DeviceCodeExpired));
+
+        let server = token_server(&["access_denied"]);
+        let client = device_client(&server);
+        let error = client
+            .await_device_token(&device_authorization(60, 0))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::ClientError(ClientError::OAuth(ref error))
+                if error.error == OAuth2ErrorCode::Unrecognized("access_denied".into())
+        ));
+    }
+
+    #[tokio::test]
+    async fn request_device_authorization_without_endpoint() {
+        let server = token_server(&[]);
+        let config: Config = serde_json::from_value(config_json(&server.url("/"))).unwrap();
+        let client = DiscoveredClient::from_config(
+            config,
+            None,
+            String::from("client"),
+            String::from("secret"),
+            None,
+            reqwest::Client::new(),
+        )
+        .unwrap();
+        assert!(matches!(
+            client.request_device_authorization(None).await,
+            Err(Error::NoDeviceAuthorizationUrl)
+        ));
+    }
+}
@@ -1,7 +1,11 @@
-use crate::{error::Error, Config, Configurable, Provider};
+use crate::{
+    certificate, error::Error, trace, ClientObserver, Config, Configurable, Operation, Provider,
+};
 use biscuit::jwk::JWKSet;
 use biscuit::Empty;
 use reqwest::Client;
+use serde_json::Value;
+use std::io::Read;
 use url::Url;
 
 #[derive(Debug, Clone)]
@@ -29,19 +33,258 @@ impl From<Config> for Discovered {
     }
 }
 
-pub async fn discover(client: &Client, mut issuer: Url) -> Result<Config, Error> {
-    issuer
-        .path_segments_mut()
+/// Fetches the discovery document of the issuer, trying the urls of [`discovery_urls`] in
+/// order. The error of the last url is returned if none succeeds.
+pub async fn discover(client: &Client, issuer: Url) -> Result<Config, Error> {
+    let mut result = Err(Error::CannotBeABase);
+    for url in discovery_urls(&issuer)? {
+        result = trace::request(Operation::Discovery, &url, None, async {
+            let resp = client.get(url.clone()).send().await?.error_for_status()?;
+            resp.json().await.map_err(Error::from)
+        })
+        .await;
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+/// The url of the discovery document of the issuer, as fetched by [`discover`], without
+/// fetching it. `/.well-known/openid-configuration` is appended to the path of the issuer, with
+/// or without trailing slash, as required by
+/// [OpenID Connect Discovery](https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderConfigurationRequest).
+/// Unlike the `oauth-authorization-server` document of RFC 8414, it is not inserted before the
+/// path, so `https://a.com/tenant` becomes `https://a.com/tenant/.well-known/openid-configuration`.
+///
+/// Errors with CannotBeABase if the issuer has no path, e.g. a `mailto:` url.
+///
+/// See [`discovery_urls`] for the urls [`discover`] tries.
+pub fn well_known_url(issuer: &Url) -> Result<Url, Error> {
+    let mut url = issuer.clone();
+    url.set_query(None);
+    url.set_fragment(None);
+    url.path_segments_mut()
         .map_err(|_| Error::CannotBeABase)?
+        .pop_if_empty()
         .extend(&[".well-known", "openid-configuration"]);
+    Ok(url)
+}
+
+/// The urls the discovery document of the issuer may be served at: the [`well_known_url`] and,
+/// if the issuer has a path, the url with `/.well-known/openid-configuration` inserted between
+/// host and path, as specified by
+/// [RFC 8414, section 5](https://www.rfc-editor.org/rfc/rfc8414#section-5).
+pub(crate) fn discovery_urls(issuer: &Url) -> Result<Vec<Url>, Error> {
+    let mut urls = vec![well_known_url(issuer)?];
 
-    let resp = client.get(issuer).send().await?;
-    resp.json().await.map_err(Error::from)
+    let path = issuer.path().trim_end_matches('/');
+    if !path.is_empty() {
+        let mut url = urls[0].clone();
+        url.set_path(&format!("/.well-known/openid-configuration{}", path));
+        urls.push(url);
+    }
+    Ok(urls)
 }
 
 /// Get the JWK set from the given Url. Errors are either a reqwest error or an Insecure error if
 /// the url isn't https.
 pub async fn jwks(client: &Client, url: Url) -> Result<JWKSet<Empty>, Error> {
-    let resp = client.get(url).send().await?;
-    resp.json().await.map_err(Error::from)
+    jwks_observed(client, url, None).await
+}
+
+/// Like [`jwks`], notifying the observer of the request.
+pub(crate) async fn jwks_observed(
+    client: &Client,
+    url: Url,
+    observer: Option<&dyn ClientObserver>,
+) -> Result<JWKSet<Empty>, Error> {
+    trace::request(Operation::Jwks, &url, observer, async {
+        let resp = client.get(url.clone()).send().await?;
+        jwks_from_value(resp.json().await?)
+    })
+    .await
+}
+
+/// RSA keys only supplying a certificate chain get their public key from the certificate.
+pub(crate) fn jwks_from_value(mut value: Value) -> Result<JWKSet<Empty>, Error> {
+    certificate::complete_rsa_keys(&mut value)?;
+    serde_json::from_value(value).map_err(Error::from)
+}
+
+/// Read a discovery document from the given reader, e.g. a file distributed with the application.
+pub fn config_from_reader<R: Read>(reader: R) -> Result<Config, Error> {
+    serde_json::from_reader(reader).map_err(Error::from)
+}
+
+/// Read a JWK set from the given reader, e.g. a file distributed with the application.
+pub fn jwks_from_reader<R: Read>(reader: R) -> Result<JWKSet<Empty>, Error> {
+    jwks_from_value(serde_json::from_reader(reader)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::Decode, testing, DiscoveredClient, IdToken, StandardClaims};
+
+    fn well_known(issuer: &str) -> String {
+        well_known_url(&Url::parse(issuer).unwrap())
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn well_known_url_of_issuer() {
+        let expected = "https://a.com/.well-known/openid-configuration";
+        assert_eq!(expected, well_known("https://a.com"));
+        assert_eq!(expected, well_known("https://a.com/"));
+
+        let expected = "https://a.com/tenant/.well-known/openid-configuration";
+        assert_eq!(expected, well_known("https://a.com/tenant"));
+        assert_eq!(expected, well_known("https://a.com/tenant/"));
+    }
+
+    #[test]
+    fn discovery_urls_of_issuer_with_path() {
+        let issuer = Url::parse("https://a.com/tenant/").unwrap();
+        let urls: Vec<_> = discovery_urls(&issuer)
+            .unwrap()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            vec![
+                "https://a.com/tenant/.well-known/openid-configuration",
+                "https://a.com/.well-known/openid-configuration/tenant",
+            ],
+            urls
+        );
+
+        let issuer = Url::parse("https://a.com").unwrap();
+        assert_eq!(1, discovery_urls(&issuer).unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn discover_falls_back_to_rfc8414_url() {
+        let server = testing::MockServer::start(|request| {
+            let base = Url::parse(&format!("http://{}/", request.header("host").unwrap())).unwrap();
+            match request.path.as_str() {
+                "/.well-known/openid-configuration/tenant" => {
+                    testing::Response::json(testing::config_json(&base))
+                }
+                _ => testing::Response::with_status(404, serde_json::json!({})),
+            }
+        });
+
+        let config = discover(&Client::new(), server.url("/tenant"))
+            .await
+            .unwrap();
+        assert_eq!(server.url("/token"), config.token_endpoint);
+        assert_eq!(
+            1,
+            server
+                .requests_to("/tenant/.well-known/openid-configuration")
+                .len()
+        );
+    }
+
+    #[test]
+    fn well_known_url_requires_base() {
+        let issuer = Url::parse("mailto:issuer@a.com").unwrap();
+        assert!(matches!(well_known_url(&issuer), Err(Error::CannotBeABase)));
+    }
+
+    #[test]
+    fn client_from_readers() {
+        let base = Url::parse("https://example.com/").unwrap();
+        let config = testing::config_json(&base).to_string();
+        let jwks = serde_json::to_string(&testing::jwks("key")).unwrap();
+
+        let config = config_from_reader(config.as_bytes()).unwrap();
+        let jwks = jwks_from_reader(jwks.as_bytes()).unwrap();
+        let client = DiscoveredClient::from_config(
+            config,
+            Some(jwks),
+            String::from("client"),
+            String::from("secret"),
+            None,
+            Client::new(),
+        )
+        .unwrap();
+
+        let claims = testing::claims_json("https://example.com", "client");
+        let mut token: IdToken<StandardClaims> =
+            IdToken::new_encoded(&testing::sign(claims, Some("key")));
+        client.decode_token(&mut token).unwrap();
+        client.validate_token(&token, None, None).unwrap();
+    }
+
+    #[test]
+    fn client_with_empty_jwks() {
+        let base = Url::parse("https://example.com/").unwrap();
+        let config = config_from_reader(testing::config_json(&base).to_string().as_bytes());
+        let client = DiscoveredClient::from_config(
+            config.unwrap(),
+            Some(JWKSet { keys: vec![] }),
+            String::from("client"),
+            String::from("secret"),
+            None,
+            Client::new(),
+        );
+        assert!(matches!(client, Err(Error::Decode(Decode::EmptySet))));
+    }
+
+    #[tokio::test]
+    async fn discover_empty_jwks() {
+        let server = testing::MockServer::start(|request| {
+            let base = Url::parse(&format!("http://{}/", request.header("host").unwrap())).unwrap();
+            match request.path.as_str() {
+                "/jwks" => testing::Response::json(serde_json::json!({"keys": []})),
+                _ => testing::Response::json(testing::config_json(&base)),
+            }
+        });
+        let client = DiscoveredClient::discover_with_client(
+            Client::new(),
+            String::from("client"),
+            String::from("secret"),
+            None,
+            server.url("/"),
+        )
+        .await;
+        assert!(matches!(client, Err(Error::Decode(Decode::EmptySet))));
+    }
+
+    #[test]
+    fn invalid_jwks_from_reader() {
+        assert!(matches!(jwks_from_reader(&b"{}"[..]), Err(Error::Json(_))));
+    }
+
+    #[test]
+    fn jwks_with_only_x5c() {
+        let jwks = serde_json::json!({
+            "keys": [{
+                "kty": "RSA",
+                "kid": "key",
+                "x5c": [base64::encode(testing::RSA_CERTIFICATE)],
+            }]
+        })
+        .to_string();
+        let jwks = jwks_from_reader(jwks.as_bytes()).unwrap();
+        let base = Url::parse("https://example.com/").unwrap();
+        let client = DiscoveredClient::from_config(
+            config_from_reader(testing::config_json(&base).to_string().as_bytes()).unwrap(),
+            Some(jwks),
+            String::from("client"),
+            String::from("secret"),
+            None,
+            Client::new(),
+        )
+        .unwrap();
+
+        let claims = testing::claims_json("https://example.com", "client");
+        let mut token: IdToken<StandardClaims> =
+            IdToken::new_encoded(&testing::sign_rs256(claims, Some("key")));
+        client.decode_token(&mut token).unwrap();
+        client.validate_token(&token, None, None).unwrap();
+    }
 }
@@ -1,4 +1,5 @@
 /// The four values for the preferred display parameter in the Options. See spec for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Display {
     Page,
     Popup,
@@ -17,3 +18,22 @@ impl Display {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_and_debug() {
+        let display = Display::Popup;
+        let copy = display;
+        assert_eq!(display, copy);
+        assert_ne!(Display::Page, Display::Wap);
+        assert_eq!("Touch", format!("{:?}", Display::Touch));
+        let values: Vec<_> = [Display::Page, Display::Popup, Display::Touch, Display::Wap]
+            .iter()
+            .map(Display::as_str)
+            .collect();
+        assert_eq!(vec!["page", "popup", "touch", "wap"], values);
+    }
+}
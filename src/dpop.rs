@@ -0,0 +1,189 @@
+use crate::error::{Error, Jose};
+use biscuit::{
+    jwa::SignatureAlgorithm,
+    jws::{Compact, Header, RegisteredHeader, Secret},
+    CompactJson,
+};
+use chrono::Utc;
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{fmt, sync::Arc};
+use url::Url;
+
+/// Header carrying the DPoP-Nonce provided by the server.
+pub(crate) const DPOP_NONCE: &str = "DPoP-Nonce";
+
+/// Error code signaling the proof must contain the server provided nonce.
+pub(crate) const USE_DPOP_NONCE: &str = "use_dpop_nonce";
+
+/// Key used to prove possession of DPoP-bound tokens.
+///
+/// See [RFC 9449](https://www.rfc-editor.org/rfc/rfc9449).
+#[derive(Clone)]
+pub struct DPoPKey {
+    key_pair: Arc<EcdsaKeyPair>,
+    jwk: Value,
+}
+
+#[derive(Deserialize, Serialize)]
+struct DPoPHeader {
+    jwk: Value,
+}
+
+#[derive(Deserialize, Serialize)]
+struct DPoPClaims {
+    jti: String,
+    htm: String,
+    htu: String,
+    iat: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ath: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+}
+
+impl CompactJson for DPoPClaims {}
+
+impl DPoPKey {
+    /// Generates a new P-256 key, proofs are signed with ES256.
+    pub fn generate() -> Result<Self, Error> {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+            .map_err(|_| Jose::UnspecifiedCryptographicError)?;
+        Self::from_pkcs8(pkcs8.as_ref())
+    }
+
+    /// Loads a PKCS#8 DER encoded P-256 private key, proofs are signed with ES256.
+    pub fn from_pkcs8(der: &[u8]) -> Result<Self, Error> {
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, der)
+            .map_err(Jose::KeyRejected)?;
+        // Uncompressed point: 0x04 || x || y
+        let point = key_pair.public_key().as_ref();
+        let (x, y) = point[1..].split_at(32);
+        let jwk = json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": base64::encode_config(x, base64::URL_SAFE_NO_PAD),
+            "y": base64::encode_config(y, base64::URL_SAFE_NO_PAD),
+        });
+        Ok(Self {
+            key_pair: Arc::new(key_pair),
+            jwk,
+        })
+    }
+
+    /// Public key as JWK, as sent in the proof header.
+    pub fn jwk(&self) -> &Value {
+        &self.jwk
+    }
+
+    /// Creates a DPoP proof for a request. The access token must be given for requests to
+    /// resources protected by a DPoP-bound token.
+    pub fn proof(
+        &self,
+        method: &str,
+        url: &Url,
+        access_token: Option<&str>,
+        nonce: Option<&str>,
+    ) -> Result<String, Jose> {
+        let mut htu = url.clone();
+        htu.set_query(None);
+        htu.set_fragment(None);
+
+        let mut jti = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut jti)
+            .map_err(|_| Jose::UnspecifiedCryptographicError)?;
+
+        let header = Header {
+            registered: RegisteredHeader {
+                algorithm: SignatureAlgorithm::ES256,
+                media_type: Some("dpop+jwt".to_string()),
+                ..Default::default()
+            },
+            private: DPoPHeader {
+                jwk: self.jwk.clone(),
+            },
+        };
+        let claims = DPoPClaims {
+            jti: base64::encode_config(jti, base64::URL_SAFE_NO_PAD),
+            htm: method.to_string(),
+            htu: htu.to_string(),
+            iat: Utc::now().timestamp(),
+            ath: access_token.map(access_token_hash),
+            nonce: nonce.map(String::from),
+        };
+
+        let secret = Secret::EcdsaKeyPair(self.key_pair.clone());
+        let proof = Compact::new_decoded(header, claims).into_encoded(&secret)?;
+        Ok(proof.unwrap_encoded().to_string())
+    }
+}
+
+impl fmt::Debug for DPoPKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DPoPKey").field("jwk", &self.jwk).finish()
+    }
+}
+
+/// Base64url encoded SHA-256 hash of the access token, the `ath` claim of the proof.
+pub(crate) fn access_token_hash(access_token: &str) -> String {
+    let hash = digest::digest(&digest::SHA256, access_token.as_bytes());
+    base64::encode_config(hash.as_ref(), base64::URL_SAFE_NO_PAD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proof_is_well_formed() {
+        let key = DPoPKey::generate().unwrap();
+        let url = Url::parse("https://example.com/token?query#fragment").unwrap();
+
+        let proof = key
+            .proof("POST", &url, Some("access"), Some("nonce"))
+            .unwrap();
+
+        let parts: Vec<_> = proof.split('.').collect();
+        let decode = |part| base64::decode_config(part, base64::URL_SAFE).unwrap();
+        let point = [
+            &[4u8][..],
+            &decode(key.jwk()["x"].as_str().unwrap()),
+            &decode(key.jwk()["y"].as_str().unwrap()),
+        ]
+        .concat();
+        SignatureAlgorithm::ES256
+            .verify(
+                &decode(parts[2]),
+                format!("{}.{}", parts[0], parts[1]).as_bytes(),
+                &Secret::PublicKey(point),
+            )
+            .unwrap();
+
+        let header: Value = serde_json::from_slice(&decode(parts[0])).unwrap();
+        assert_eq!("dpop+jwt", header["typ"]);
+        assert_eq!("ES256", header["alg"]);
+        assert_eq!(key.jwk(), &header["jwk"]);
+        let claims: DPoPClaims = serde_json::from_slice(&decode(parts[1])).unwrap();
+        assert_eq!("POST", claims.htm);
+        assert_eq!("https://example.com/token", claims.htu);
+        assert_eq!(Some(access_token_hash("access")), claims.ath);
+        assert_eq!(Some("nonce"), claims.nonce.as_deref());
+        assert!(!claims.jti.is_empty());
+    }
+
+    #[test]
+    fn access_token_hash_is_sha256() {
+        // Example from RFC 9449, section 7.1
+        assert_eq!(
+            "fUHyO2r2Z3DZ53EsNrWBb0xWXoaNy59IiKCAqksmQEo",
+            access_token_hash("Kz~8mXK1EalYznwH-LC-1fBAo.4Ljp~zsPE_NeO.gxU")
+        );
+    }
+}
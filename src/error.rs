@@ -42,6 +42,7 @@ impl error::Error for OAuth2Error {
 ///
 /// See [RFC 6749, section 5.2](http://tools.ietf.org/html/rfc6749#section-5.2).
 #[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "String")]
 pub enum OAuth2ErrorCode {
     /// The request is missing a required parameter, includes an unsupported parameter value (other
     /// than grant type), repeats a parameter, includes multiple credentials, utilizes more than
@@ -71,7 +72,7 @@ pub enum OAuth2ErrorCode {
     Unrecognized(String),
 }
 
-impl<'a> From<&'a str> for OAuth2ErrorCode {
+impl From<&str> for OAuth2ErrorCode {
     fn from(s: &str) -> OAuth2ErrorCode {
         match s {
             "invalid_request" => OAuth2ErrorCode::InvalidRequest,
@@ -84,6 +85,13 @@ impl<'a> From<&'a str> for OAuth2ErrorCode {
         }
     }
 }
+
+impl From<String> for OAuth2ErrorCode {
+    fn from(s: String) -> OAuth2ErrorCode {
+        OAuth2ErrorCode::from(s.as_str())
+    }
+}
+/// Error of a request to the provider.
 #[derive(Debug)]
 pub enum ClientError {
     /// IO error.
@@ -92,17 +100,23 @@ pub enum ClientError {
     /// URL error.
     Url(url::ParseError),
 
-    /// Reqwest error.
-    Reqwest(reqwest::Error),
+    /// The request failed, e.g. as the provider is unreachable or timed out. Worth retrying.
+    Transport(reqwest::Error),
+
+    /// The response of the provider could not be parsed.
+    Decode(serde_json::Error),
 
-    /// JSON error.
-    Json(serde_json::Error),
+    /// JOSE error.
+    Jose(Jose),
 
     /// Response parse error.
     //    Parse(ParseError),
 
-    /// OAuth 2.0 error.
-    OAuth2(OAuth2Error),
+    /// The provider rejected the request with an OAuth 2.0 error, e.g. `invalid_grant`.
+    OAuth(OAuth2Error),
+
+    /// The authorization code is empty or only whitespace.
+    EmptyCode,
 
     /// UMA2 error.
     #[cfg(feature = "uma2")]
@@ -112,25 +126,29 @@ pub enum ClientError {
 impl fmt::Display for ClientError {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match *self {
-            ClientError::Io(ref err) => write!(f, "{}", err),
-            ClientError::Url(ref err) => write!(f, "{}", err),
-            ClientError::Reqwest(ref err) => write!(f, "{}", err),
-            ClientError::Json(ref err) => write!(f, "{}", err),
-            ClientError::OAuth2(ref err) => write!(f, "{}", err),
+            ClientError::Io(ref err) => write!(f, "IO error: {}", err),
+            ClientError::Url(ref err) => write!(f, "Url error: {}", err),
+            ClientError::Transport(ref err) => write!(f, "Transport error: {}", err),
+            ClientError::Decode(ref err) => write!(f, "Decode error: {}", err),
+            ClientError::Jose(ref err) => write!(f, "Jose error: {}", err),
+            ClientError::OAuth(ref err) => write!(f, "OAuth 2.0 error: {}", err),
+            ClientError::EmptyCode => write!(f, "Authorization code is empty"),
             #[cfg(feature = "uma2")]
-            ClientError::Uma2(ref err) => write!(f, "{}", err),
+            ClientError::Uma2(ref err) => write!(f, "UMA2 error: {}", err),
         }
     }
 }
 
 impl std::error::Error for ClientError {
-    fn cause(&self) -> Option<&dyn std::error::Error> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match *self {
             ClientError::Io(ref err) => Some(err),
             ClientError::Url(ref err) => Some(err),
-            ClientError::Reqwest(ref err) => Some(err),
-            ClientError::Json(ref err) => Some(err),
-            ClientError::OAuth2(ref err) => Some(err),
+            ClientError::Transport(ref err) => Some(err),
+            ClientError::Decode(ref err) => Some(err),
+            ClientError::Jose(ref err) => Some(err),
+            ClientError::OAuth(ref err) => Some(err),
+            ClientError::EmptyCode => None,
             #[cfg(feature = "uma2")]
             ClientError::Uma2(ref err) => Some(err),
         }
@@ -149,13 +167,15 @@ macro_rules! impl_from {
 
 impl_from!(ClientError::Io, std::io::Error);
 impl_from!(ClientError::Url, url::ParseError);
-impl_from!(ClientError::Reqwest, reqwest::Error);
-impl_from!(ClientError::Json, serde_json::Error);
-impl_from!(ClientError::OAuth2, OAuth2Error);
+impl_from!(ClientError::Transport, reqwest::Error);
+impl_from!(ClientError::Decode, serde_json::Error);
+impl_from!(ClientError::Jose, Jose);
+impl_from!(ClientError::OAuth, OAuth2Error);
 
 pub use biscuit::errors::Error as Jose;
 pub use reqwest::Error as Http;
 pub use serde_json::Error as Json;
+pub use url::ParseError as Url;
 
 use thiserror::Error;
 
@@ -164,17 +184,19 @@ use crate::uma2::Uma2Error;
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error(transparent)]
+    #[error("Jose error: {0}")]
     Jose(#[from] Jose),
-    #[error(transparent)]
+    #[error("Http error: {0}")]
     Http(#[from] Http),
-    #[error(transparent)]
+    #[error("Json error: {0}")]
     Json(#[from] Json),
-    #[error(transparent)]
+    #[error("Url error: {0}")]
+    Url(#[from] Url),
+    #[error("Decode error: {0}")]
     Decode(#[from] Decode),
-    #[error(transparent)]
+    #[error("Validation error: {0}")]
     Validation(#[from] Validation),
-    #[error(transparent)]
+    #[error("Userinfo error: {0}")]
     Userinfo(#[from] Userinfo),
     #[error("Url must use TLS: '{0}'")]
     Insecure(::reqwest::Url),
@@ -182,8 +204,30 @@ pub enum Error {
     MissingOpenidScope,
     #[error("Url: Path segments is cannot-be-a-base")]
     CannotBeABase,
-    #[error(transparent)]
+    #[error("Client error: {0}")]
     ClientError(#[from] ClientError),
+    #[error("Client builder is missing the {0}")]
+    MissingBuilderField(&'static str),
+    #[error("Redirect URI is not registered for the client: '{0}'")]
+    UnregisteredRedirectUri(String),
+    #[error("The provider has no pushed authorization request endpoint")]
+    NoPushedAuthorizationRequestUrl,
+    #[error("The provider has no device authorization endpoint")]
+    NoDeviceAuthorizationUrl,
+    #[error("The device code expired before the user approved the device")]
+    DeviceCodeExpired,
+    #[error("FAPI requirement not met: {0}")]
+    Fapi(&'static str),
+    #[error("Token response is missing the refresh token for offline access")]
+    MissingRefreshToken,
+    #[error("Requested claims are not supported by the provider: {}", .0.join(", "))]
+    UnsupportedClaims(Vec<String>),
+    #[error("Options must not set both request and request_uri")]
+    RequestAndRequestUri,
+    #[error("No session is stored for the state: '{0}'")]
+    UnknownSession(String),
+    #[error("Step-up authentication required: {0}")]
+    StepUpRequired(StepUp),
 }
 
 #[derive(Debug, Error)]
@@ -198,6 +242,22 @@ pub enum Decode {
     UnsupportedEllipticCurve,
     #[error("No support for Octet key pair yet")]
     UnsupportedOctetKeyPair,
+    #[error("Invalid PEM encoded public key")]
+    InvalidPem,
+    #[error("Token algorithm is not advertised by the provider: {0}")]
+    UnadvertisedAlgorithm(String),
+    #[error("Token algorithm is not allowed by the client: {0}")]
+    DisallowedAlgorithm(String),
+    #[error(
+        "Token is signed with a symmetric algorithm, the client requires an asymmetric one: {0}"
+    )]
+    SymmetricAlgorithm(String),
+    #[error("Token type is not an ID Token: {0}")]
+    UnexpectedType(String),
+    #[error("JWK contains an invalid x5c certificate")]
+    InvalidCertificate,
+    #[error("JWK {0} thumbprint does not match its x5c certificate")]
+    ThumbprintMismatch(String),
 }
 
 #[derive(Debug, Error)]
@@ -208,6 +268,16 @@ pub enum Validation {
     Missing(#[from] Missing),
     #[error(transparent)]
     Expired(#[from] Expiry),
+    #[error("{}", display_all(.0))]
+    Multiple(Vec<Validation>),
+}
+
+fn display_all(errors: &[Validation]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
 }
 
 #[derive(Debug, Error)]
@@ -218,6 +288,20 @@ pub enum Mismatch {
     Issuer { expected: String, actual: String },
     #[error("Given nonce does not match token nonce: '{expected}', '{actual}'")]
     Nonce { expected: String, actual: String },
+    #[error("Configured issuer and response issuer mismatch: '{expected}', '{actual}'")]
+    ResponseIssuer { expected: String, actual: String },
+    #[error("Given state does not match response state: '{expected}', '{actual}'")]
+    State { expected: String, actual: String },
+    #[error("Access token hash does not match token at_hash")]
+    AccessTokenHash,
+    #[error("Client certificate and token thumbprint mismatch: '{expected}', '{actual}'")]
+    CertificateThumbprint { expected: String, actual: String },
+    #[error("Refreshed token and previous token {claim} mismatch: '{expected}', '{actual}'")]
+    RefreshedToken {
+        claim: &'static str,
+        expected: String,
+        actual: String,
+    },
 }
 
 #[derive(Debug, Error)]
@@ -230,6 +314,22 @@ pub enum Missing {
     AuthTime,
     #[error("Token missing Nonce")]
     Nonce,
+    #[error("Token missing Subject")]
+    Subject,
+    #[error("Token missing Expiry")]
+    Expiry,
+    #[error("Response missing State")]
+    State,
+    #[error("Response missing Issuer")]
+    ResponseIssuer,
+    #[error("Response missing Authorization Code")]
+    AuthorizationCode,
+    #[error("Response missing ID Token")]
+    IdToken,
+    #[error("Token missing Access Token Hash")]
+    AccessTokenHash,
+    #[error("Token missing certificate thumbprint (cnf.x5t#S256)")]
+    CertificateThumbprint,
 }
 
 #[derive(Debug, Error)]
@@ -246,4 +346,145 @@ pub enum Userinfo {
     NoUrl,
     #[error("Token and Userinfo Subjects mismatch: '{expected}', '{actual}'")]
     MismatchSubject { expected: String, actual: String },
+    #[error("Token and Userinfo Emails mismatch: '{expected}', '{actual}'")]
+    MismatchEmail { expected: String, actual: String },
+    #[error("Userinfo has content type '{actual}', expected {expected}")]
+    UnexpectedContentType { expected: String, actual: String },
+}
+
+/// What a token lacks for a step-up of [`crate::Client::require_step_up`]. Authenticate again
+/// with the auth url of [`StepUp::options`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepUp {
+    /// The required acr, if the token has another one.
+    pub acr: Option<String>,
+    /// The required max_age, if the authentication is older or its time unknown.
+    pub max_age: Option<::chrono::Duration>,
+}
+
+impl StepUp {
+    /// Options forcing a new authentication that satisfies the step-up.
+    pub fn options(&self) -> crate::Options {
+        crate::Options {
+            prompt: Some([crate::Prompt::Login].into()),
+            acr_values: self.acr.clone(),
+            max_age: self.max_age,
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for StepUp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let mut failures = Vec::new();
+        if let Some(ref acr) = self.acr {
+            failures.push(format!("acr '{}'", acr));
+        }
+        if let Some(max_age) = self.max_age {
+            failures.push(format!("authentication within {}s", max_age.num_seconds()));
+        }
+        f.write_str(&failures.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as StdError;
+
+    #[test]
+    fn oauth2_error_code_from_json() {
+        let error: OAuth2Error =
+            serde_json::from_str(r#"{"error":"invalid_grant","error_description":"Code expired"}"#)
+                .unwrap();
+        assert_eq!(OAuth2ErrorCode::InvalidGrant, error.error);
+        let error: OAuth2Error =
+            serde_json::from_str(r#"{"error":"invalid_redirect_uri"}"#).unwrap();
+        assert_eq!(
+            OAuth2ErrorCode::Unrecognized("invalid_redirect_uri".into()),
+            error.error
+        );
+    }
+
+    fn json_error() -> Json {
+        serde_json::from_str::<crate::Bearer>(r#"{"token_type":"Bearer"}"#).unwrap_err()
+    }
+
+    #[test]
+    fn error_source_is_inner_error() {
+        let error = Error::from(json_error());
+        let source = error.source().expect("source");
+        assert!(source.downcast_ref::<Json>().is_some());
+    }
+
+    #[test]
+    fn error_display_contains_cause() {
+        let error = Error::from(json_error());
+        let display = error.to_string();
+        assert!(display.starts_with("Json error: "));
+        assert!(display.contains("missing field `access_token`"));
+
+        let error = Error::from(Decode::MissingKey("kid".to_string()));
+        assert_eq!(
+            "Decode error: Token wants this key id not in the key set: kid",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn client_error_source_is_inner_error() {
+        let error = ClientError::from(json_error());
+        let source = error.source().expect("source");
+        assert!(source.downcast_ref::<Json>().is_some());
+
+        let error = Error::from(error);
+        let source = error.source().expect("source");
+        assert!(source.downcast_ref::<ClientError>().is_some());
+    }
+
+    #[test]
+    fn client_error_display_contains_cause() {
+        let error = ClientError::from(json_error());
+        let display = error.to_string();
+        assert!(display.starts_with("Decode error: "));
+        assert!(display.contains("missing field `access_token`"));
+
+        let error = Error::from(ClientError::from(url::ParseError::EmptyHost));
+        assert_eq!("Client error: Url error: empty host", error.to_string());
+    }
+
+    #[test]
+    fn error_from_http() {
+        let error = reqwest::Client::new().get("not a url").build().unwrap_err();
+        assert!(matches!(Error::from(error), Error::Http(_)));
+    }
+
+    #[test]
+    fn error_from_json() {
+        assert!(matches!(Error::from(json_error()), Error::Json(_)));
+    }
+
+    #[test]
+    fn error_from_jose() {
+        let error = Jose::UnsupportedOperation;
+        assert!(matches!(Error::from(error), Error::Jose(_)));
+    }
+
+    #[test]
+    fn error_from_url() {
+        let error = ::url::Url::parse("not a url").unwrap_err();
+        assert!(matches!(Error::from(error), Error::Url(_)));
+    }
+
+    #[test]
+    fn multiple_validation_display() {
+        let error = Error::from(Validation::Multiple(vec![
+            Missing::Nonce.into(),
+            Missing::Audience.into(),
+        ]));
+        assert_eq!(
+            "Validation error: Token missing Nonce; Token missing Audience",
+            error.to_string()
+        );
+    }
 }
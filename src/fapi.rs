@@ -0,0 +1,370 @@
+use crate::{
+    error::{ClientError, Error},
+    session, AuthSession, Claims, Client, Configurable, Operation, Options, Provider,
+};
+use biscuit::{
+    jwa::SignatureAlgorithm,
+    jws::{Compact, Header, RegisteredHeader, Secret},
+    CompactJson, Empty,
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+use url::{form_urlencoded::Serializer, Url};
+
+/// Lifetime of the request objects created by [`Client::request_object`].
+const REQUEST_OBJECT_LIFETIME: i64 = 60;
+
+/// Key signing request objects.
+///
+/// See [RFC 9101](https://www.rfc-editor.org/rfc/rfc9101).
+pub struct RequestSigningKey {
+    algorithm: SignatureAlgorithm,
+    secret: Secret,
+    key_id: Option<String>,
+}
+
+impl RequestSigningKey {
+    pub fn new(algorithm: SignatureAlgorithm, secret: Secret) -> Self {
+        Self {
+            algorithm,
+            secret,
+            key_id: None,
+        }
+    }
+
+    /// The `kid` of the key registered with the provider, sent in the request object header.
+    pub fn with_key_id(mut self, key_id: impl Into<String>) -> Self {
+        self.key_id = Some(key_id.into());
+        self
+    }
+
+    pub fn algorithm(&self) -> SignatureAlgorithm {
+        self.algorithm
+    }
+}
+
+impl fmt::Debug for RequestSigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestSigningKey")
+            .field("algorithm", &self.algorithm)
+            .field("key_id", &self.key_id)
+            .finish()
+    }
+}
+
+/// Response of the pushed authorization request endpoint.
+///
+/// See [RFC 9126, section 2.2](https://www.rfc-editor.org/rfc/rfc9126#section-2.2).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PushedAuthorizationResponse {
+    /// Reference to the pushed request, to be sent to the authorization endpoint.
+    pub request_uri: String,
+    /// Seconds until the `request_uri` expires.
+    pub expires_in: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(transparent)]
+struct RequestObject(Map<String, Value>);
+
+impl CompactJson for RequestObject {}
+
+impl<C: CompactJson + Claims, P: Provider + Configurable> Client<P, C> {
+    /// Creates a signed request object with the parameters of [`Client::auth_url`].
+    ///
+    /// See [RFC 9101](https://www.rfc-editor.org/rfc/rfc9101).
+    pub fn request_object(
+        &self,
+        options: &Options,
+        key: &RequestSigningKey,
+    ) -> Result<String, Error> {
+        let params = self.auth_params(&self.auth_url(options));
+        self.sign_request_object(params, key)
+    }
+
+    /// Pushes the authorization request parameters to the provider, returning the `request_uri`
+    /// to send to the authorization endpoint instead.
+    ///
+    /// See [RFC 9126](https://www.rfc-editor.org/rfc/rfc9126).
+    pub async fn push_authorization_request(
+        &self,
+        params: &[(String, String)],
+    ) -> Result<PushedAuthorizationResponse, Error> {
+        let url = self
+            .config()
+            .pushed_authorization_request_endpoint
+            .as_ref()
+            .ok_or(Error::NoPushedAuthorizationRequestUrl)?;
+
+        let mut body = Serializer::new(String::new());
+        body.extend_pairs(params.iter().filter(|(key, _)| key != "client_id"));
+        let json = self
+            .post_form(url, Operation::PushedAuthorization, body)
+            .await?;
+        Ok(serde_json::from_value(json).map_err(ClientError::from)?)
+    }
+
+    /// Constructs the auth url of a [FAPI 2.0](https://openid.net/specs/fapi-2_0-security-profile.html)
+    /// compliant code flow: the parameters of [`Client::auth_url_with_session`], including the
+    /// S256 PKCE code challenge, are signed as request object and pushed to the provider. The
+    /// returned url only contains the client ID and the `request_uri`. Keep the returned session
+    /// around to validate the response of the provider.
+    ///
+    /// Errors with Fapi if the key does not use PS256 or ES256, or the provider does not support
+    /// S256 code challenges, and with NoPushedAuthorizationRequestUrl if the provider has no
+    /// pushed authorization request endpoint.
+    ///
+    /// # Panics
+    ///
+    /// If the system random number generator fails.
+    pub async fn fapi_auth_url(
+        &self,
+        options: &Options,
+        signing_key: &RequestSigningKey,
+    ) -> Result<(Url, AuthSession), Error> {
+        if !matches!(
+            signing_key.algorithm,
+            SignatureAlgorithm::PS256 | SignatureAlgorithm::ES256
+        ) {
+            return Err(Error::Fapi(
+                "request objects must be signed with PS256 or ES256",
+            ));
+        }
+        if let Some(ref methods) = self.config().code_challenge_methods_supported {
+            if !methods.iter().any(|method| method == "S256") {
+                return Err(Error::Fapi(
+                    "the provider must support S256 code challenges",
+                ));
+            }
+        }
+        if self
+            .config()
+            .pushed_authorization_request_endpoint
+            .is_none()
+        {
+            return Err(Error::NoPushedAuthorizationRequestUrl);
+        }
+
+        let (url, session) = self.auth_url_with_session(options);
+        let request = self.sign_request_object(self.auth_params(&url), signing_key)?;
+        let params = [
+            ("client_id".to_string(), self.client_id.clone()),
+            ("request".to_string(), request),
+        ];
+        let pushed = self.push_authorization_request(&params).await?;
+
+        let mut url = self.provider.auth_uri().clone();
+        url.query_pairs_mut()
+            .append_pair("client_id", &self.client_id)
+            .append_pair("request_uri", &pushed.request_uri);
+        Ok((url, session))
+    }
+
+    /// The parameters the auth url adds to the authorization endpoint.
+    fn auth_params(&self, url: &Url) -> Vec<(String, String)> {
+        let endpoint: Vec<_> = self.provider.auth_uri().query_pairs().collect();
+        url.query_pairs()
+            .filter(|pair| !endpoint.contains(pair))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect()
+    }
+
+    fn sign_request_object(
+        &self,
+        params: Vec<(String, String)>,
+        key: &RequestSigningKey,
+    ) -> Result<String, Error> {
+        let mut claims: Map<String, Value> = params
+            .into_iter()
+            .map(|(key, value)| {
+                // Numbers and JSON objects keep their type in the request object
+                let typed = match key.as_str() {
+                    "max_age" => value.parse::<i64>().ok().map(Value::from),
+                    "claims" => serde_json::from_str(&value).ok(),
+                    _ => None,
+                };
+                (key, typed.unwrap_or(Value::String(value)))
+            })
+            .collect();
+        let now = Utc::now();
+        claims.insert("iss".into(), self.client_id.clone().into());
+        claims.insert("aud".into(), self.config().issuer.as_str().into());
+        claims.insert("iat".into(), now.timestamp().into());
+        claims.insert("nbf".into(), now.timestamp().into());
+        let exp = now + Duration::seconds(REQUEST_OBJECT_LIFETIME);
+        claims.insert("exp".into(), exp.timestamp().into());
+        claims.insert("jti".into(), session::random().into());
+
+        let header = Header::<Empty>::from_registered_header(RegisteredHeader {
+            algorithm: key.algorithm,
+            media_type: Some("oauth-authz-req+jwt".to_string()),
+            key_id: key.key_id.clone(),
+            ..Default::default()
+        });
+        let jwt = Compact::new_decoded(header, RequestObject(claims)).into_encoded(&key.secret)?;
+        Ok(jwt.unwrap_encoded().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{config_json, MockServer, Response},
+        Config, DiscoveredClient,
+    };
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+    use serde_json::json;
+    use std::{collections::HashMap, sync::Arc};
+
+    fn es256_key() -> RequestSigningKey {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap();
+        RequestSigningKey::new(
+            SignatureAlgorithm::ES256,
+            Secret::EcdsaKeyPair(Arc::new(key_pair)),
+        )
+        .with_key_id("signing")
+    }
+
+    fn par_server() -> MockServer {
+        MockServer::start(|request| match request.path.as_str() {
+            "/par" => Response::with_status(
+                201,
+                json!({"request_uri": "urn:ietf:params:oauth:request_uri:abc", "expires_in": 60}),
+            ),
+            _ => Response::with_status(404, json!({})),
+        })
+    }
+
+    fn fapi_client(base: &Url, config: Value) -> DiscoveredClient {
+        let mut json = config_json(base);
+        json.as_object_mut()
+            .unwrap()
+            .extend(config.as_object().unwrap().clone());
+        let config: Config = serde_json::from_value(json).unwrap();
+        DiscoveredClient::from_config(
+            config,
+            None,
+            String::from("client"),
+            String::from("secret"),
+            Some(String::from("https://app.example.com/callback")),
+            reqwest::Client::new(),
+        )
+        .unwrap()
+    }
+
+    fn decode_part(part: &str) -> Value {
+        serde_json::from_slice(&base64::decode_config(part, base64::URL_SAFE_NO_PAD).unwrap())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn fapi_auth_url_pushes_signed_request() {
+        let server = par_server();
+        let client = fapi_client(
+            &server.url("/"),
+            json!({
+                "pushed_authorization_request_endpoint": server.url("/par"),
+                "code_challenge_methods_supported": ["S256"],
+            }),
+        );
+
+        let (url, session) = client
+            .fapi_auth_url(&Options::default(), &es256_key())
+            .await
+            .unwrap();
+
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(2, query.len());
+        assert_eq!("client", query["client_id"]);
+        assert_eq!(
+            "urn:ietf:params:oauth:request_uri:abc",
+            query["request_uri"]
+        );
+        assert_eq!(server.url("/auth").path(), url.path());
+
+        let request = &server.requests_to("/par")[0];
+        assert!(request
+            .header("authorization")
+            .unwrap()
+            .starts_with("Basic "));
+        let body: HashMap<_, _> = url::form_urlencoded::parse(request.body.as_bytes())
+            .into_owned()
+            .collect();
+        assert_eq!(2, body.len());
+        assert_eq!("client", body["client_id"]);
+
+        let parts: Vec<_> = body["request"].split('.').collect();
+        let header = decode_part(parts[0]);
+        assert_eq!("ES256", header["alg"]);
+        assert_eq!("oauth-authz-req+jwt", header["typ"]);
+        assert_eq!("signing", header["kid"]);
+        let claims = decode_part(parts[1]);
+        assert_eq!("client", claims["iss"]);
+        assert_eq!(client.config().issuer.as_str(), claims["aud"]);
+        assert_eq!("code", claims["response_type"]);
+        assert_eq!("https://app.example.com/callback", claims["redirect_uri"]);
+        assert_eq!("openid", claims["scope"]);
+        assert_eq!(session.state, claims["state"]);
+        assert_eq!(session.nonce, claims["nonce"]);
+        assert_eq!("S256", claims["code_challenge_method"]);
+        assert_eq!(session.code_challenge().unwrap(), claims["code_challenge"]);
+        assert!(claims["exp"].as_i64().unwrap() > claims["iat"].as_i64().unwrap());
+    }
+
+    #[tokio::test]
+    async fn fapi_auth_url_requirements() {
+        let server = par_server();
+        let par = json!({"pushed_authorization_request_endpoint": server.url("/par")});
+
+        let client = fapi_client(&server.url("/"), par.clone());
+        let key = RequestSigningKey::new(SignatureAlgorithm::HS256, Secret::bytes_from_str("key"));
+        assert!(matches!(
+            client.fapi_auth_url(&Options::default(), &key).await,
+            Err(Error::Fapi(_))
+        ));
+
+        let mut config = par;
+        config["code_challenge_methods_supported"] = json!(["plain"]);
+        let client = fapi_client(&server.url("/"), config);
+        assert!(matches!(
+            client
+                .fapi_auth_url(&Options::default(), &es256_key())
+                .await,
+            Err(Error::Fapi(_))
+        ));
+
+        let client = fapi_client(&server.url("/"), json!({}));
+        assert!(matches!(
+            client
+                .fapi_auth_url(&Options::default(), &es256_key())
+                .await,
+            Err(Error::NoPushedAuthorizationRequestUrl)
+        ));
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn push_authorization_request_error() {
+        let server =
+            MockServer::start(|_| Response::with_status(400, json!({"error": "invalid_request"})));
+        let client = fapi_client(
+            &server.url("/"),
+            json!({"pushed_authorization_request_endpoint": server.url("/par")}),
+        );
+
+        let params = [("response_type".to_string(), "code".to_string())];
+        assert!(matches!(
+            client.push_authorization_request(&params).await,
+            Err(Error::ClientError(ClientError::OAuth(_)))
+        ));
+    }
+}
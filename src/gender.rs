@@ -0,0 +1,17 @@
+/// End-User's gender. Values defined by the spec are female and male, other values are kept as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Gender {
+    Male,
+    Female,
+    Other(String),
+}
+
+impl From<&str> for Gender {
+    fn from(s: &str) -> Self {
+        match s {
+            "male" => Gender::Male,
+            "female" => Gender::Female,
+            s => Gender::Other(s.to_owned()),
+        }
+    }
+}
@@ -23,14 +23,14 @@ Add dependency to Cargo.toml:
 
 ```toml
 [dependencies]
-openid = "0.10"
+openid = "0.11"
 ```
 
 By default it uses native tls, if you want to use `rustls`:
 
 ```toml
 [dependencies]
-openid = { version = "0.10", default-features = false, features = ["rustls"] }
+openid = { version = "0.11", default-features = false, features = ["rustls"] }
 ```
 
 ### Use case: [Warp](https://crates.io/crates/warp) web server with [JHipster](https://www.jhipster.tech/) generated frontend and [Google OpenID Connect](https://developers.google.com/identity/protocols/OpenIDConnect)
@@ -50,7 +50,7 @@ edition = '2018'
 anyhow = "1.0"
 cookie = "0.14"
 log = "0.4"
-openid = "0.10"
+openid = "0.11"
 pretty_env_logger = "0.4"
 reqwest = "0.11"
 serde = { version = "1", features = [ "derive" ] }
@@ -206,7 +206,7 @@ async fn reply_login(
                 last_name: user_info.family_name.clone(),
                 first_name: user_info.name.clone(),
                 email,
-                activated: user_info.email_verified,
+                activated: user_info.email_verified(),
                 image_url: user_info.picture.clone().map(|x| x.to_string()),
                 lang_key: Some("en".to_string()),
                 authorities: vec!["ROLE_USER".to_string()],
@@ -341,22 +341,42 @@ extern crate lazy_static;
 
 mod address;
 mod bearer;
+mod builder;
+mod certificate;
 mod claims;
 mod client;
+mod client_auth;
+mod client_secret;
 mod config;
 mod configurable;
 mod custom_claims;
-mod deserializers;
+/// Tolerant deserializers for non-compliant providers, e.g. to use with
+/// `#[serde(deserialize_with = "...")]` in own claims types.
+pub mod deserializers;
+mod device;
 mod discovered;
 mod display;
+mod dpop;
 pub mod error;
+mod fapi;
+mod gender;
+mod locale;
+mod observer;
 mod options;
+pub mod pem;
 mod prompt;
 pub mod provider;
+pub mod registration;
+mod scope;
+mod session;
 mod standard_claims;
+#[cfg(test)]
+mod testing;
 mod token;
+mod trace;
 mod userinfo;
 pub mod validation;
+mod validator;
 
 #[cfg(any(feature = "uma2", doc))]
 /// UMA2 OIDC/OAuth2 extension.
@@ -364,24 +384,40 @@ pub mod validation;
 /// See [Federated Authorization for User-Managed Access (UMA) 2.0](https://docs.kantarainitiative.org/uma/wg/oauth-uma-federated-authz-2.0-09.html)
 pub mod uma2;
 
+#[cfg(feature = "blocking")]
+/// Synchronous API for applications without an async runtime.
+pub mod blocking;
+
 pub use ::biscuit::jws::Compact as Jws;
 pub use ::biscuit::{Compact, CompactJson, Empty, SingleOrMultiple};
 pub use address::Address;
 pub use bearer::Bearer;
+pub use builder::ClientBuilder;
 pub use claims::Claims;
 pub use client::Client;
+pub use client_auth::ClientAuth;
+pub use client_secret::ClientSecret;
 pub use config::Config;
 pub use configurable::Configurable;
 pub use custom_claims::CustomClaims;
-pub use discovered::Discovered;
+pub use device::DeviceAuthorization;
+pub use discovered::{config_from_reader, jwks_from_reader, well_known_url, Discovered};
 pub use display::Display;
+pub use dpop::DPoPKey;
 pub use error::{OAuth2Error, OAuth2ErrorCode};
+pub use fapi::{PushedAuthorizationResponse, RequestSigningKey};
+pub use gender::Gender;
+pub use locale::Locale;
+pub use observer::{ClientObserver, Operation};
 pub use options::Options;
 pub use prompt::Prompt;
 pub use provider::Provider;
+pub use scope::Scope;
+pub use session::{AuthSession, MemorySessionStore, SessionStore};
 pub use standard_claims::StandardClaims;
-pub use token::Token;
-pub use userinfo::Userinfo;
+pub use token::{FragmentResponse, Token};
+pub use userinfo::{Userinfo, UserinfoCache};
+pub use validator::Validator;
 
 /// Reimport `biscuit` dependency.
 pub mod biscuit {
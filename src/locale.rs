@@ -0,0 +1,82 @@
+/// End-User's locale, a BCP47 language tag like `en-US` reduced to language and region.
+///
+/// As allowed by spec, an underscore separator (`en_US`) is accepted as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    /// ISO 639 language code in lowercase.
+    pub language: String,
+    /// ISO 3166-1 country code in uppercase or UN M.49 region code.
+    pub region: Option<String>,
+}
+
+impl Locale {
+    /// Parses a language tag, returns None if it is malformed.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let mut subtags = tag.split(['-', '_']);
+
+        let language = subtags.next()?;
+        if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic())
+        {
+            return None;
+        }
+
+        let mut region = None;
+        for subtag in subtags {
+            let alpha = subtag.chars().all(|c| c.is_ascii_alphabetic());
+            let digit = subtag.chars().all(|c| c.is_ascii_digit());
+            match subtag.len() {
+                // Script, e.g. zh-Hant-TW
+                4 if alpha && region.is_none() => {}
+                2 if alpha && region.is_none() => region = Some(subtag.to_ascii_uppercase()),
+                3 if digit && region.is_none() => region = Some(subtag.to_owned()),
+                _ => return None,
+            }
+        }
+
+        Some(Locale {
+            language: language.to_ascii_lowercase(),
+            region,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locale(language: &str, region: Option<&str>) -> Option<Locale> {
+        Some(Locale {
+            language: language.to_string(),
+            region: region.map(String::from),
+        })
+    }
+
+    #[test]
+    fn parse_dash() {
+        assert_eq!(locale("en", Some("US")), Locale::parse("en-US"));
+    }
+
+    #[test]
+    fn parse_underscore() {
+        assert_eq!(locale("en", Some("US")), Locale::parse("en_US"));
+    }
+
+    #[test]
+    fn parse_language_only() {
+        assert_eq!(locale("fr", None), Locale::parse("fr"));
+    }
+
+    #[test]
+    fn parse_script_and_numeric_region() {
+        assert_eq!(locale("zh", Some("TW")), Locale::parse("zh-Hant-TW"));
+        assert_eq!(locale("es", Some("419")), Locale::parse("es-419"));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(None, Locale::parse(""));
+        assert_eq!(None, Locale::parse("english"));
+        assert_eq!(None, Locale::parse("en-USA1"));
+        assert_eq!(None, Locale::parse("en-US-CA"));
+    }
+}
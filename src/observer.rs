@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+/// Kind of an HTTP operation reported to a [`ClientObserver`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Operation {
+    /// Fetch of the discovery document.
+    Discovery,
+    /// Fetch of the JWK set.
+    Jwks,
+    /// Request to the token endpoint, e.g. a code exchange or refresh.
+    Token,
+    /// Request to the userinfo endpoint.
+    Userinfo,
+    /// Request to the pushed authorization request endpoint.
+    PushedAuthorization,
+    /// Request to the device authorization endpoint.
+    DeviceAuthorization,
+}
+
+impl Operation {
+    /// Name of the operation, e.g. as a metrics label.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Discovery => "discovery",
+            Operation::Jwks => "jwks",
+            Operation::Token => "token",
+            Operation::Userinfo => "userinfo",
+            Operation::PushedAuthorization => "pushed_authorization",
+            Operation::DeviceAuthorization => "device_authorization",
+        }
+    }
+}
+
+/// Observer of the HTTP operations of a [`crate::Client`], e.g. to record request counts and
+/// latencies in a metrics backend.
+pub trait ClientObserver: Send + Sync {
+    /// Called once per operation after it completed, with its duration and whether it succeeded.
+    fn on_operation(&self, operation: Operation, duration: Duration, success: bool);
+}
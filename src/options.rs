@@ -1,10 +1,12 @@
-use crate::{Display, Prompt};
+use crate::{Claims, Display, Prompt, Token};
+use biscuit::CompactJson;
 use chrono::Duration;
+use serde_json::Value;
 use std::collections::HashSet;
 
 /// Optional parameters that [OpenID specifies](https://openid.net/specs/openid-connect-basic-1_0.html#RequestParameters) for the auth URI.
 /// Derives Default, so remember to ..Default::default() after you specify what you want.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Options {
     /// MUST contain openid. By default this is ONLY openid. Official optional scopes are
     /// email, profile, address, phone, offline_access. Check the Discovery config
@@ -13,11 +15,86 @@ pub struct Options {
     pub state: Option<String>,
     pub nonce: Option<String>,
     pub display: Option<Display>,
+    /// If it contains [`Prompt::None`], the other values are dropped, as `none` must not be
+    /// combined with them.
     pub prompt: Option<HashSet<Prompt>>,
     pub max_age: Option<Duration>,
+    /// Space separated preferred languages of the user interface, e.g. `fr-CA en`.
     pub ui_locales: Option<String>,
+    /// Space separated preferred languages of the claims, e.g. `fr-CA en`.
     pub claims_locales: Option<String>,
+    /// Preferred languages of the user interface as a list, used if `ui_locales` is not set.
+    pub ui_locales_list: Option<Vec<String>>,
+    /// Preferred languages of the claims as a list, used if `claims_locales` is not set.
+    pub claims_locales_list: Option<Vec<String>>,
     pub id_token_hint: Option<String>,
     pub login_hint: Option<String>,
+    /// Space separated requested authentication context classes, in order of preference.
     pub acr_values: Option<String>,
+    /// Requested authentication context classes as a list in order of preference, used if
+    /// `acr_values` is not set.
+    pub acr_values_list: Option<Vec<String>>,
+    /// Individual claims to return in the userinfo and/or id_token, e.g.
+    /// `{"userinfo": {"email": {"essential": true}}}`. Check them with
+    /// [`crate::Client::validate_claims_request`].
+    ///
+    /// See [OpenID Connect Core, section 5.5](https://openid.net/specs/openid-connect-core-1_0.html#ClaimsParameter).
+    pub claims: Option<Value>,
+    /// Signed and/or encrypted request object holding the parameters. The auth url then only
+    /// contains the parameters required outside of it. Mutually exclusive with `request_uri`.
+    ///
+    /// See [RFC 9101](https://www.rfc-editor.org/rfc/rfc9101).
+    pub request: Option<String>,
+    /// Url of a request object hosted by the client, like `request`.
+    pub request_uri: Option<String>,
+}
+
+impl Options {
+    /// Sets `login_hint` from a prior token with [`Token::login_hint`], keeping the current value
+    /// if the token has no hint.
+    pub fn login_hint_from<C: CompactJson + Claims>(&mut self, token: &Token<C>) {
+        if let Some(hint) = token.login_hint() {
+            self.login_hint = Some(hint.to_string());
+        }
+    }
+
+    /// The `ui_locales` parameter, joining the list if the string is not set.
+    pub(crate) fn joined_ui_locales(&self) -> Option<String> {
+        joined(&self.ui_locales, &self.ui_locales_list)
+    }
+
+    /// The `claims_locales` parameter, joining the list if the string is not set.
+    pub(crate) fn joined_claims_locales(&self) -> Option<String> {
+        joined(&self.claims_locales, &self.claims_locales_list)
+    }
+
+    /// The `acr_values` parameter, joining the list in order if the string is not set.
+    pub(crate) fn joined_acr_values(&self) -> Option<String> {
+        joined(&self.acr_values, &self.acr_values_list)
+    }
+
+    /// Names of the claims requested by the `claims` parameter for the userinfo or id_token,
+    /// without duplicates.
+    pub(crate) fn requested_claims(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for target in ["userinfo", "id_token"] {
+            let claims = self
+                .claims
+                .as_ref()
+                .and_then(|claims| claims.get(target))
+                .and_then(Value::as_object);
+            for name in claims.into_iter().flat_map(|claims| claims.keys()) {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+}
+
+fn joined(value: &Option<String>, list: &Option<Vec<String>>) -> Option<String> {
+    value
+        .clone()
+        .or_else(|| list.as_ref().map(|list| list.join(" ")))
 }
@@ -0,0 +1,251 @@
+//! Loading of public keys from PEM files, e.g. to verify tokens offline with
+//! [`crate::Client::decode_token_with_key`] or [`crate::Client::decode_token`].
+use crate::error::{ClientError, Decode, Error};
+use biscuit::{jwk::JWKSet, jws::Secret, Empty};
+use serde_json::json;
+use std::path::Path;
+
+/// Object identifier of RSA keys in a SubjectPublicKeyInfo, 1.2.840.113549.1.1.1.
+const RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+/// Object identifier of EC keys in a SubjectPublicKeyInfo, 1.2.840.10045.2.1.
+const EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+
+/// Loads an RSA public key for RS256, RS384 or RS512 from a PEM file, either a `PUBLIC KEY`
+/// or an `RSA PUBLIC KEY`.
+///
+/// Errors with Decode::InvalidPem if the file holds no RSA public key.
+pub fn rsa_public_from_pem(path: impl AsRef<Path>) -> Result<Secret, Error> {
+    Ok(Secret::PublicKey(rsa_public_key(&read(path)?)?))
+}
+
+/// Loads an EC public key for ES256 or ES384 from a PEM file holding a `PUBLIC KEY`.
+///
+/// Errors with Decode::InvalidPem if the file holds no EC public key.
+pub fn ec_public_from_pem(path: impl AsRef<Path>) -> Result<Secret, Error> {
+    let (label, der) = parse(&read(path)?)?;
+    match label.as_str() {
+        "PUBLIC KEY" => Ok(Secret::PublicKey(subject_public_key(&der, EC_PUBLIC_KEY)?)),
+        _ => Err(Decode::InvalidPem.into()),
+    }
+}
+
+/// Loads a key set with the RSA public key of a PEM file as its single key, so
+/// [`crate::Client::decode_token`] verifies tokens signed by it.
+///
+/// Errors with Decode::InvalidPem if the file holds no RSA public key. EC keys are not supported
+/// by `decode_token`, use [`ec_public_from_pem`] with `decode_token_with_key` instead.
+pub fn jwks_from_pem(path: impl AsRef<Path>, kid: Option<&str>) -> Result<JWKSet<Empty>, Error> {
+    let pkcs1 = rsa_public_key(&read(path)?)?;
+    let mut integers = Der::new(sequence(&pkcs1)?);
+    let n = integers.next(INTEGER)?;
+    let e = integers.next(INTEGER)?;
+
+    let encode = |integer: &[u8]| {
+        // The leading zero keeping the DER integer positive is not part of the value
+        let integer = match integer {
+            [0, rest @ ..] if !rest.is_empty() => rest,
+            _ => integer,
+        };
+        base64::encode_config(integer, base64::URL_SAFE_NO_PAD)
+    };
+    let mut key = json!({"kty": "RSA", "n": encode(n), "e": encode(e)});
+    if let Some(kid) = kid {
+        key["kid"] = json!(kid);
+    }
+    Ok(serde_json::from_value(json!({ "keys": [key] }))?)
+}
+
+fn read(path: impl AsRef<Path>) -> Result<String, Error> {
+    std::fs::read_to_string(path).map_err(|error| ClientError::from(error).into())
+}
+
+/// The PKCS#1 RSAPublicKey of a PEM document.
+fn rsa_public_key(pem: &str) -> Result<Vec<u8>, Error> {
+    let (label, der) = parse(pem)?;
+    match label.as_str() {
+        "RSA PUBLIC KEY" => Ok(der),
+        "PUBLIC KEY" => subject_public_key(&der, RSA_ENCRYPTION),
+        _ => Err(Decode::InvalidPem.into()),
+    }
+}
+
+/// The label and DER content of the first PEM block.
+fn parse(pem: &str) -> Result<(String, Vec<u8>), Error> {
+    let mut lines = pem.lines().map(str::trim);
+    let label = lines
+        .find_map(|line| line.strip_prefix("-----BEGIN ")?.strip_suffix("-----"))
+        .ok_or(Decode::InvalidPem)?;
+    let end = format!("-----END {}-----", label);
+    let mut base64 = String::new();
+    for line in lines.by_ref() {
+        if line == end {
+            let der = base64::decode(&base64).map_err(|_| Decode::InvalidPem)?;
+            return Ok((label.to_string(), der));
+        }
+        base64.push_str(line);
+    }
+    Err(Decode::InvalidPem.into())
+}
+
+/// The key of a SubjectPublicKeyInfo with the given algorithm.
+///
+/// See [RFC 5280, section 4.1](https://www.rfc-editor.org/rfc/rfc5280#section-4.1).
+fn subject_public_key(der: &[u8], algorithm: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut info = Der::new(sequence(der)?);
+    let mut identifier = Der::new(info.next(SEQUENCE)?);
+    if identifier.next(OBJECT_IDENTIFIER)? != algorithm {
+        return Err(Decode::InvalidPem.into());
+    }
+    match info.next(BIT_STRING)? {
+        // No unused bits
+        [0, key @ ..] => Ok(key.to_vec()),
+        _ => Err(Decode::InvalidPem.into()),
+    }
+}
+
+/// The content of a DER document that is a single sequence.
+fn sequence(der: &[u8]) -> Result<&[u8], Error> {
+    let mut der = Der::new(der);
+    let content = der.next(SEQUENCE)?;
+    if !der.0.is_empty() {
+        return Err(Decode::InvalidPem.into());
+    }
+    Ok(content)
+}
+
+/// Reader of consecutive DER values.
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    fn new(der: &'a [u8]) -> Self {
+        Self(der)
+    }
+
+    /// The content of the next value, which must have the given tag.
+    fn next(&mut self, tag: u8) -> Result<&'a [u8], Error> {
+        let (content, rest) = self.read(tag).ok_or(Decode::InvalidPem)?;
+        self.0 = rest;
+        Ok(content)
+    }
+
+    fn read(&self, tag: u8) -> Option<(&'a [u8], &'a [u8])> {
+        let (&actual, rest) = self.0.split_first()?;
+        if actual != tag {
+            return None;
+        }
+        let (&first, rest) = rest.split_first()?;
+        let (length, rest) = if first < 0x80 {
+            (first as usize, rest)
+        } else {
+            // Long form, the low bits are the number of length bytes
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > std::mem::size_of::<usize>() || rest.len() < count {
+                return None;
+            }
+            let (bytes, rest) = rest.split_at(count);
+            let length = bytes
+                .iter()
+                .fold(0usize, |length, &byte| (length << 8) | byte as usize);
+            (length, rest)
+        };
+        (rest.len() >= length).then(|| rest.split_at(length))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing, DiscoveredClient, IdToken};
+    use biscuit::jwa::SignatureAlgorithm;
+    use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use std::sync::Arc;
+    use url::Url;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/src/testing/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn client(jwks: Option<JWKSet<Empty>>) -> DiscoveredClient {
+        let base = Url::parse("https://example.com/").unwrap();
+        let config: crate::Config = serde_json::from_value(testing::config_json(&base)).unwrap();
+        crate::Client::new(
+            crate::Discovered::from(config),
+            String::from("client"),
+            String::from("secret"),
+            None,
+            reqwest::Client::new(),
+            jwks,
+        )
+    }
+
+    fn rs256_token() -> IdToken<crate::StandardClaims> {
+        let claims = testing::claims_json("https://example.com", "client");
+        IdToken::new_encoded(&testing::sign_rs256(claims, Some("pem")))
+    }
+
+    #[test]
+    fn rsa_public_key_formats() {
+        let client = client(None);
+        for name in ["rsa_public_key.pem", "rsa_spki_public_key.pem"] {
+            let secret = rsa_public_from_pem(fixture(name)).unwrap();
+            let mut token = rs256_token();
+            client
+                .decode_token_with_key(&mut token, &secret, SignatureAlgorithm::RS256)
+                .unwrap();
+            assert_eq!("subject", token.payload().unwrap().sub);
+        }
+    }
+
+    #[test]
+    fn ec_public_key() {
+        let pkcs8 = std::fs::read(fixture("ec_key.der")).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8).unwrap();
+        let header = biscuit::jws::RegisteredHeader {
+            algorithm: SignatureAlgorithm::ES256,
+            ..Default::default()
+        };
+        let claims = testing::claims_json("https://example.com", "client");
+        let encoded = testing::encode(header, claims, &Secret::EcdsaKeyPair(Arc::new(key)));
+
+        let secret = ec_public_from_pem(fixture("ec_public_key.pem")).unwrap();
+        let mut token: IdToken<crate::StandardClaims> = IdToken::new_encoded(&encoded);
+        client(None)
+            .decode_token_with_key(&mut token, &secret, SignatureAlgorithm::ES256)
+            .unwrap();
+
+        // An EC key is no RSA key and vice versa
+        assert!(rsa_public_from_pem(fixture("ec_public_key.pem")).is_err());
+        assert!(ec_public_from_pem(fixture("rsa_spki_public_key.pem")).is_err());
+    }
+
+    #[test]
+    fn jwks_decodes_token() {
+        let jwks = jwks_from_pem(fixture("rsa_public_key.pem"), Some("pem")).unwrap();
+        assert_eq!(Some("pem"), jwks.keys[0].common.key_id.as_deref());
+
+        let mut token = rs256_token();
+        client(Some(jwks)).decode_token(&mut token).unwrap();
+        assert_eq!("subject", token.payload().unwrap().sub);
+    }
+
+    #[test]
+    fn invalid_pem() {
+        assert!(matches!(
+            parse("no pem"),
+            Err(Error::Decode(Decode::InvalidPem))
+        ));
+        assert!(matches!(
+            rsa_public_key("-----BEGIN PUBLIC KEY-----\nMAA=\n-----END PUBLIC KEY-----"),
+            Err(Error::Decode(Decode::InvalidPem))
+        ));
+        assert!(matches!(
+            rsa_public_from_pem(fixture("missing.pem")),
+            Err(Error::ClientError(ClientError::Io(_)))
+        ));
+    }
+}
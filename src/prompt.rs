@@ -1,10 +1,14 @@
-/// The four possible values for the prompt parameter set in Options. See spec for details.
-#[derive(PartialEq, Eq, Hash)]
+/// The possible values for the prompt parameter set in Options. See spec for details.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub enum Prompt {
     None,
     Login,
     Consent,
     SelectAccount,
+    /// Show the registration page instead of the login page.
+    ///
+    /// See [Initiating User Registration via OpenID Connect](https://openid.net/specs/openid-connect-prompt-create-1_0.html).
+    Create,
 }
 
 impl Prompt {
@@ -15,6 +19,7 @@ impl Prompt {
             Login => "login",
             Consent => "consent",
             SelectAccount => "select_account",
+            Create => "create",
         }
     }
 }
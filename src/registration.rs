@@ -0,0 +1,321 @@
+/*!
+OpenID Connect Dynamic Client Registration.
+
+See [RFC 7591](https://www.rfc-editor.org/rfc/rfc7591) and for the management of registrations
+[RFC 7592](https://www.rfc-editor.org/rfc/rfc7592).
+*/
+use crate::{
+    client::USER_AGENT,
+    error::{ClientError, Error},
+    OAuth2Error,
+};
+use reqwest::header::USER_AGENT as USER_AGENT_HEADER;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use url::Url;
+
+/// Metadata of a client to register.
+///
+/// See [RFC 7591, section 2](https://www.rfc-editor.org/rfc/rfc7591#section-2).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClientMetadata {
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_endpoint_auth_method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grant_types: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_types: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contacts: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwks_uri: Option<String>,
+    /// Required by some providers for clients with `pairwise` subjects and several redirect
+    /// hosts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sector_identifier_uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject_type: Option<String>,
+    /// Any other metadata, e.g. provider specific extensions.
+    #[serde(flatten)]
+    pub additional: Map<String, Value>,
+}
+
+/// Response of a successful registration, with the metadata as registered by the provider.
+///
+/// See [RFC 7591, section 3.2.1](https://www.rfc-editor.org/rfc/rfc7591#section-3.2.1).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RegistrationResponse {
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    /// Token to read, update or delete the registration, see
+    /// [RFC 7592](https://www.rfc-editor.org/rfc/rfc7592).
+    #[serde(default)]
+    pub registration_access_token: Option<String>,
+    #[serde(default)]
+    pub registration_client_uri: Option<Url>,
+    #[serde(default)]
+    pub client_id_issued_at: Option<i64>,
+    /// 0 if the secret does not expire.
+    #[serde(default)]
+    pub client_secret_expires_at: Option<i64>,
+    #[serde(flatten)]
+    pub metadata: ClientMetadata,
+}
+
+/// Registers a client at the `registration_endpoint` of the provider.
+///
+/// Errors are ClientError::OAuth if the provider rejects the metadata, e.g. with
+/// `invalid_redirect_uri`, ClientError::Transport if the request fails or is answered with
+/// another error status, and ClientError::Decode if the response is malformed.
+pub async fn register_client(
+    http_client: &reqwest::Client,
+    registration_endpoint: &Url,
+    metadata: ClientMetadata,
+) -> Result<RegistrationResponse, Error> {
+    let resp = http_client
+        .post(registration_endpoint.clone())
+        .header(USER_AGENT_HEADER, USER_AGENT)
+        .json(&metadata)
+        .send()
+        .await
+        .map_err(ClientError::from)?;
+    registration_response(resp).await
+}
+
+/// Reads the current registration from the `registration_client_uri`.
+///
+/// See [RFC 7592, section 2.1](https://www.rfc-editor.org/rfc/rfc7592#section-2.1).
+pub async fn read_registration(
+    http_client: &reqwest::Client,
+    registration_client_uri: &Url,
+    registration_access_token: &str,
+) -> Result<RegistrationResponse, Error> {
+    let resp = http_client
+        .get(registration_client_uri.clone())
+        .header(USER_AGENT_HEADER, USER_AGENT)
+        .bearer_auth(registration_access_token)
+        .send()
+        .await
+        .map_err(ClientError::from)?;
+    registration_response(resp).await
+}
+
+/// Replaces the registered metadata. Metadata left out may be reset to defaults by the provider.
+///
+/// See [RFC 7592, section 2.2](https://www.rfc-editor.org/rfc/rfc7592#section-2.2).
+pub async fn update_registration(
+    http_client: &reqwest::Client,
+    registration_client_uri: &Url,
+    registration_access_token: &str,
+    client_id: &str,
+    metadata: ClientMetadata,
+) -> Result<RegistrationResponse, Error> {
+    let mut body = serde_json::to_value(metadata)?;
+    if let Some(body) = body.as_object_mut() {
+        body.insert("client_id".to_string(), client_id.into());
+    }
+    let resp = http_client
+        .put(registration_client_uri.clone())
+        .header(USER_AGENT_HEADER, USER_AGENT)
+        .bearer_auth(registration_access_token)
+        .json(&body)
+        .send()
+        .await
+        .map_err(ClientError::from)?;
+    registration_response(resp).await
+}
+
+/// Deletes the registration, the client can't be used afterwards.
+///
+/// See [RFC 7592, section 2.3](https://www.rfc-editor.org/rfc/rfc7592#section-2.3).
+pub async fn delete_registration(
+    http_client: &reqwest::Client,
+    registration_client_uri: &Url,
+    registration_access_token: &str,
+) -> Result<(), Error> {
+    let resp = http_client
+        .delete(registration_client_uri.clone())
+        .header(USER_AGENT_HEADER, USER_AGENT)
+        .bearer_auth(registration_access_token)
+        .send()
+        .await
+        .map_err(ClientError::from)?;
+    resp.error_for_status().map_err(ClientError::from)?;
+    Ok(())
+}
+
+/// Parses a registration response, or the OAuth 2.0 error returned instead.
+async fn registration_response<T: serde::de::DeserializeOwned>(
+    resp: reqwest::Response,
+) -> Result<T, Error> {
+    let status = resp.error_for_status_ref().map(|_| ());
+    let body = resp.bytes().await.map_err(ClientError::from)?;
+    if let Err(status) = status {
+        if status
+            .status()
+            .is_some_and(|status| status.is_client_error())
+        {
+            if let Ok(error) = serde_json::from_slice::<OAuth2Error>(&body) {
+                return Err(ClientError::from(error).into());
+            }
+        }
+        return Err(ClientError::from(status).into());
+    }
+    Ok(serde_json::from_slice(&body).map_err(ClientError::from)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{MockServer, Response},
+        OAuth2ErrorCode,
+    };
+    use serde_json::json;
+
+    fn metadata() -> ClientMetadata {
+        ClientMetadata {
+            redirect_uris: vec![String::from("https://app.example.com/callback")],
+            grant_types: Some(vec![String::from("authorization_code")]),
+            client_name: Some(String::from("App")),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn register() {
+        let server = MockServer::start(|request| {
+            let mut body: Value = serde_json::from_str(&request.body).unwrap();
+            body["client_id"] = json!("registered");
+            body["client_secret"] = json!("secret");
+            body["registration_access_token"] = json!("access");
+            Response::with_status(201, body)
+        });
+
+        let response = register_client(
+            &reqwest::Client::new(),
+            &server.url("/register"),
+            metadata(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!("registered", response.client_id);
+        assert_eq!(Some("secret"), response.client_secret.as_deref());
+        assert_eq!(
+            Some("access"),
+            response.registration_access_token.as_deref()
+        );
+        assert_eq!(metadata(), response.metadata);
+        let request = &server.requests_to("/register")[0];
+        assert_eq!("POST", request.method);
+        assert_eq!(Some("application/json"), request.header("content-type"));
+    }
+
+    #[tokio::test]
+    async fn register_error() {
+        let server = MockServer::start(|_| {
+            Response::with_status(
+                400,
+                json!({"error": "invalid_redirect_uri", "error_description": "Not https"}),
+            )
+        });
+
+        let response = register_client(
+            &reqwest::Client::new(),
+            &server.url("/register"),
+            metadata(),
+        )
+        .await;
+
+        match response {
+            Err(Error::ClientError(ClientError::OAuth(error))) => {
+                assert_eq!(
+                    OAuth2ErrorCode::Unrecognized(String::from("invalid_redirect_uri")),
+                    error.error
+                );
+                assert_eq!(Some("Not https"), error.error_description.as_deref());
+            }
+            _ => panic!("expected an OAuth 2.0 error"),
+        }
+    }
+
+    fn management_server() -> MockServer {
+        MockServer::start(|request| {
+            if request.header("authorization") != Some("Bearer access") {
+                return Response::with_status(401, json!({"error": "invalid_token"}));
+            }
+            match request.method.as_str() {
+                "GET" => Response::json(json!({
+                    "client_id": "registered",
+                    "redirect_uris": ["https://app.example.com/callback"],
+                })),
+                "PUT" => Response::json(serde_json::from_str(&request.body).unwrap()),
+                "DELETE" => Response::with_status(204, json!(null)),
+                _ => Response::with_status(405, json!({})),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn read() {
+        let server = management_server();
+
+        let response = read_registration(&reqwest::Client::new(), &server.url("/client"), "access")
+            .await
+            .unwrap();
+
+        assert_eq!("registered", response.client_id);
+        assert_eq!(
+            vec!["https://app.example.com/callback"],
+            response.metadata.redirect_uris
+        );
+    }
+
+    #[tokio::test]
+    async fn update() {
+        let server = management_server();
+
+        let response = update_registration(
+            &reqwest::Client::new(),
+            &server.url("/client"),
+            "access",
+            "registered",
+            metadata(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!("registered", response.client_id);
+        assert_eq!(metadata(), response.metadata);
+        assert_eq!("PUT", server.requests_to("/client")[0].method);
+    }
+
+    #[tokio::test]
+    async fn delete() {
+        let server = management_server();
+        let http_client = reqwest::Client::new();
+
+        delete_registration(&http_client, &server.url("/client"), "access")
+            .await
+            .unwrap();
+        let unauthorized = delete_registration(&http_client, &server.url("/client"), "other").await;
+
+        assert_eq!("DELETE", server.requests_to("/client")[0].method);
+        assert!(matches!(
+            unauthorized,
+            Err(Error::ClientError(ClientError::Transport(_)))
+        ));
+    }
+}
@@ -0,0 +1,91 @@
+use std::{fmt, ops::Deref};
+
+/// Space separated scope, built from single scopes and usable wherever a `&str` scope is
+/// expected, e.g. `Scope::from_iter(["openid", "email"])`. Empty and repeated scopes are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Scope(String);
+
+impl Scope {
+    /// The single scopes.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.split(' ').filter(|scope| !scope.is_empty())
+    }
+
+    /// Whether the scope contains the single scope.
+    pub fn contains(&self, scope: &str) -> bool {
+        self.iter().any(|s| s == scope)
+    }
+}
+
+impl<'a> FromIterator<&'a str> for Scope {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(scopes: I) -> Self {
+        let mut scope = Scope::default();
+        for s in scopes.into_iter().flat_map(str::split_whitespace) {
+            if !scope.contains(s) {
+                if !scope.0.is_empty() {
+                    scope.0.push(' ');
+                }
+                scope.0.push_str(s);
+            }
+        }
+        scope
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Deref for Scope {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<Scope> for String {
+    fn from(scope: Scope) -> Self {
+        scope.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{provider::google::Installed, Client};
+
+    #[test]
+    fn from_iter() {
+        let scope = Scope::from_iter(["openid", "email", "", "openid", "profile  phone"]);
+        assert_eq!("openid email profile phone", scope.to_string());
+        assert!(scope.contains("email"));
+        assert!(!scope.contains("address"));
+        assert_eq!(
+            vec!["openid", "email", "profile", "phone"],
+            scope.iter().collect::<Vec<_>>()
+        );
+        assert_eq!("", &*Scope::from_iter(Vec::<&str>::new()));
+    }
+
+    #[test]
+    fn auth_uri_with_scope() {
+        let client: Client<_> = Client::new(
+            Installed,
+            String::from("client"),
+            String::from("secret"),
+            None,
+            reqwest::Client::new(),
+            None,
+        );
+        let scope: Scope = ["openid", "email"].into_iter().collect();
+        let url = client.auth_uri(Some(&scope), None);
+        let scope = url
+            .query_pairs()
+            .find(|(key, _)| key == "scope")
+            .map(|(_, scope)| scope.into_owned());
+        assert_eq!(Some("openid email"), scope.as_deref());
+    }
+}
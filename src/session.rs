@@ -0,0 +1,140 @@
+use chrono::Duration;
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{collections::HashMap, sync::Mutex};
+
+/// Values generated for an authorization request, to be kept (e.g. in the user's session) until
+/// the provider redirects back, so the response can be validated against them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthSession {
+    /// The `state` sent to the provider, which must be echoed back in the redirect.
+    pub state: String,
+    /// The `nonce` the id_token must contain.
+    pub nonce: String,
+    /// The PKCE `code_verifier` to send with the token request.
+    ///
+    /// See [RFC 7636](https://www.rfc-editor.org/rfc/rfc7636).
+    pub code_verifier: Option<String>,
+    /// The `max_age` sent to the provider, if any.
+    pub max_age: Option<Duration>,
+    /// The `redirect_uri` sent to the provider, which the token request must repeat. The
+    /// client's `redirect_uri` is sent if not set.
+    pub redirect_uri: Option<String>,
+}
+
+impl AuthSession {
+    /// Generates a random state, nonce and PKCE code verifier, without a redirect URI.
+    ///
+    /// # Panics
+    ///
+    /// If the system random number generator fails.
+    pub fn generate(max_age: Option<Duration>) -> Self {
+        Self {
+            state: random(),
+            nonce: random(),
+            code_verifier: Some(random()),
+            max_age,
+            redirect_uri: None,
+        }
+    }
+
+    /// The S256 PKCE `code_challenge` of the code verifier.
+    pub fn code_challenge(&self) -> Option<String> {
+        self.code_verifier.as_deref().map(code_challenge)
+    }
+}
+
+/// Storage of the sessions of pending authorization requests, keyed by their state, e.g. a
+/// shared cache so the callback can be handled by another server instance than the auth url.
+///
+/// See [`crate::Client::auth_url_with_store`] and [`crate::Client::authenticate_with_store`].
+pub trait SessionStore: Send + Sync {
+    /// Stores the session under the key, replacing any session stored under it.
+    fn store(&self, key: String, session: AuthSession);
+
+    /// Removes and returns the session stored under the key, so each session is used once.
+    fn take(&self, key: &str) -> Option<AuthSession>;
+}
+
+/// In-memory [`SessionStore`] for a single server instance. Sessions of abandoned
+/// authorization requests are kept until the store is dropped.
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<HashMap<String, AuthSession>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of stored sessions.
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn store(&self, key: String, session: AuthSession) {
+        self.sessions.lock().unwrap().insert(key, session);
+    }
+
+    fn take(&self, key: &str) -> Option<AuthSession> {
+        self.sessions.lock().unwrap().remove(key)
+    }
+}
+
+/// Base64url encoded SHA-256 hash of the code verifier.
+fn code_challenge(code_verifier: &str) -> String {
+    let hash = digest::digest(&digest::SHA256, code_verifier.as_bytes());
+    base64::encode_config(hash.as_ref(), base64::URL_SAFE_NO_PAD)
+}
+
+/// 256 random bits, base64url encoded.
+pub(crate) fn random() -> String {
+    let mut bytes = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system random number generator failed");
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_challenge_is_s256() {
+        // Example from RFC 7636, appendix B
+        assert_eq!(
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM",
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk")
+        );
+    }
+
+    #[test]
+    fn generated_values_differ() {
+        let session = AuthSession::generate(None);
+        assert_ne!(session.state, session.nonce);
+        assert_eq!(43, session.state.len());
+        assert_ne!(session, AuthSession::generate(None));
+    }
+
+    #[test]
+    fn memory_store_takes_once() {
+        let store = MemorySessionStore::new();
+        let session = AuthSession::generate(None);
+        store.store(session.state.clone(), session.clone());
+        assert_eq!(1, store.len());
+
+        assert_eq!(Some(session.clone()), store.take(&session.state));
+        assert_eq!(None, store.take(&session.state));
+        assert!(store.is_empty());
+    }
+}
@@ -18,7 +18,10 @@ pub struct StandardClaims {
     //
     // Make sure this cannot silently underflow, see:
     // https://github.com/serde-rs/json/blob/8e01f44f479b3ea96b299efc0da9131e7aff35dc/src/de.rs#L341
-    pub exp: i64,
+    //
+    // Required, but optional here so a missing exp is reported by validation as such
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
     pub iat: i64,
     // required for max_age request
     #[serde(default)]
@@ -61,7 +64,7 @@ impl Claims for StandardClaims {
     fn aud(&self) -> &SingleOrMultiple<String> {
         &self.aud
     }
-    fn exp(&self) -> i64 {
+    fn exp(&self) -> Option<i64> {
         self.exp
     }
     fn iat(&self) -> i64 {
@@ -86,3 +89,25 @@ impl Claims for StandardClaims {
 
 // THIS IS CRAZY VOODOO WITCHCRAFT MAGIC
 impl CompactJson for StandardClaims {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn missing_exp_is_not_serialized() {
+        let claims: StandardClaims = serde_json::from_value(json!({
+            "iss": "https://example.com",
+            "sub": "subject",
+            "aud": "client",
+            "iat": 0,
+        }))
+        .unwrap();
+        assert_eq!(None, claims.exp);
+
+        let value = serde_json::to_value(&claims).unwrap();
+        assert!(value.get("exp").is_none());
+        assert_eq!("subject", value["sub"]);
+    }
+}
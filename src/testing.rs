@@ -0,0 +1,314 @@
+//! Helpers for tests talking to a local mock HTTP server.
+use crate::StandardClaims;
+use biscuit::{
+    jwa::SignatureAlgorithm,
+    jwk::JWKSet,
+    jws::{Compact, Header, RegisteredHeader, Secret},
+    CompactJson, Empty,
+};
+use ring::signature::RsaKeyPair;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+use url::Url;
+
+/// Counts the allocations of each thread, see [`allocations`].
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    // Fails while the thread is torn down, its allocations don't matter then
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Runs `f`, returning its result and the number of allocations it made on this thread.
+pub fn allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+/// A request received by the mock server.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A response returned by the mock server.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(value: Value) -> Self {
+        Self::with_status(200, value)
+    }
+
+    pub fn with_status(status: u16, value: Value) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".into(), "application/json".into())],
+            body: value.to_string(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+/// Minimal HTTP/1.1 server answering every request with the given handler.
+pub struct MockServer {
+    url: Url,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+                let handler = handler.clone();
+                let recorded = recorded.clone();
+                thread::spawn(move || serve(stream, handler.as_ref(), &recorded));
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    pub fn url(&self, path: &str) -> Url {
+        self.url.join(path).unwrap()
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn requests_to(&self, path: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.path.split('?').next() == Some(path))
+            .collect()
+    }
+}
+
+fn serve(stream: TcpStream, handler: &Handler, recorded: &Mutex<Vec<Request>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let mut line = String::new();
+    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+        return;
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+
+    let request = Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    };
+    let response = handler(&request);
+    recorded.lock().unwrap().push(request);
+
+    let mut stream = stream;
+    let mut head = format!("HTTP/1.1 {} Mock\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    ));
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(response.body.as_bytes());
+    let _ = stream.flush();
+}
+
+/// Discovery document with all endpoints pointing to the given base url.
+pub fn config_json(base: &Url) -> Value {
+    let issuer = base.as_str().trim_end_matches('/');
+    json!({
+        "issuer": issuer,
+        "authorization_endpoint": base.join("auth").unwrap(),
+        "token_endpoint": base.join("token").unwrap(),
+        "userinfo_endpoint": base.join("userinfo").unwrap(),
+        "jwks_uri": base.join("jwks").unwrap(),
+        "response_types_supported": ["code"],
+    })
+}
+
+/// Shared secret of the key set returned by [`jwks`].
+pub const SECRET: &str = "secret";
+
+/// Key set with a single HMAC key for [`SECRET`].
+pub fn jwks(kid: &str) -> JWKSet<Empty> {
+    serde_json::from_value(json!({
+        "keys": [{"kty": "oct", "k": base64::encode_config(SECRET, base64::URL_SAFE_NO_PAD), "kid": kid}]
+    }))
+    .unwrap()
+}
+
+/// Valid id_token claims for the given issuer and audience.
+pub fn claims_json(issuer: &str, aud: &str) -> Value {
+    let now = chrono::Utc::now().timestamp();
+    json!({
+        "iss": issuer,
+        "sub": "subject",
+        "aud": aud,
+        "exp": now + 300,
+        "iat": now,
+    })
+}
+
+/// The claims of [`claims_json`] for `https://example.com` and the audience `client`, with the
+/// `extra` claims added or replaced.
+pub fn claims(extra: Value) -> Value {
+    let mut claims = claims_json("https://example.com", "client");
+    for (name, value) in extra.as_object().unwrap() {
+        claims[name] = value.clone();
+    }
+    claims
+}
+
+/// [`claims`] as [`StandardClaims`].
+pub fn standard_claims(extra: Value) -> StandardClaims {
+    serde_json::from_value(claims(extra)).unwrap()
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(transparent)]
+struct RawClaims(Value);
+
+impl CompactJson for RawClaims {}
+
+/// Signs the claims with [`SECRET`] using HS256.
+pub fn sign(claims: Value, kid: Option<&str>) -> String {
+    sign_with_header(
+        RegisteredHeader {
+            algorithm: SignatureAlgorithm::HS256,
+            key_id: kid.map(String::from),
+            ..Default::default()
+        },
+        claims,
+    )
+}
+
+/// Signs the claims with [`SECRET`] using the given header, e.g. to set a `typ`.
+pub fn sign_with_header(header: RegisteredHeader, claims: Value) -> String {
+    encode(header, claims, &Secret::bytes_from_str(SECRET))
+}
+
+/// Self-signed DER encoded certificate of the key used by [`sign_rs256`].
+pub const RSA_CERTIFICATE: &[u8] = include_bytes!("testing/rsa_certificate.der");
+
+/// PEM encoded PKCS#1 public key of the key used by [`sign_rs256`].
+pub const RSA_PUBLIC_KEY_PEM: &str = include_str!("testing/rsa_public_key.pem");
+
+/// Signs the claims with the key of [`RSA_CERTIFICATE`] using RS256.
+pub fn sign_rs256(claims: Value, kid: Option<&str>) -> String {
+    let key = RsaKeyPair::from_pkcs8(include_bytes!("testing/rsa_key.der")).unwrap();
+    let header = RegisteredHeader {
+        algorithm: SignatureAlgorithm::RS256,
+        key_id: kid.map(String::from),
+        ..Default::default()
+    };
+    encode(header, claims, &Secret::RsaKeyPair(Arc::new(key)))
+}
+
+/// Signs the claims with the given key and header.
+pub fn encode(header: RegisteredHeader, claims: Value, secret: &Secret) -> String {
+    let header = Header::<Empty>::from_registered_header(header);
+    Compact::new_decoded(header, RawClaims(claims))
+        .into_encoded(secret)
+        .unwrap()
+        .unwrap_encoded()
+        .to_string()
+}
@@ -1,6 +1,8 @@
-use crate::{Bearer, Claims, IdToken, StandardClaims};
+use crate::{error::Error, Bearer, Claims, IdToken, StandardClaims};
 pub use biscuit::jws::Compact as Jws;
 use biscuit::CompactJson;
+use chrono::{DateTime, Duration, Utc};
+use serde::de::DeserializeOwned;
 
 /// An OpenID Connect token. This is the only token allowed by spec.
 /// Has an access_token for bearer, and the id_token for authentication.
@@ -10,6 +12,81 @@ pub struct Token<C: CompactJson + Claims = StandardClaims> {
     pub id_token: Option<IdToken<C>>,
 }
 
+/// Tokens of a redirect in the implicit or hybrid flow, as returned by
+/// [`Client::parse_fragment_response`](crate::Client::parse_fragment_response).
+pub enum FragmentResponse<C: CompactJson + Claims = StandardClaims> {
+    /// The redirect carried an access_token, e.g. for `response_type=id_token token`. Its id_token
+    /// is decoded and bound to the access_token by its at_hash.
+    Token(Token<C>),
+    /// The redirect carried only the decoded id_token, e.g. for `response_type=id_token`.
+    IdToken(IdToken<C>),
+}
+
+impl<C: CompactJson + Claims> Token<C> {
+    /// Deserializes the claims of the decoded id_token into a user-specified type.
+    ///
+    /// `T` can pick any claims `C` carries, e.g. only the profile, or with a [`CustomClaims`]
+    /// type as `C` non-standard ones like `roles` or `groups`. Returns `None` if there is no
+    /// id_token. The id_token must be decoded (and so verified) first, otherwise a Jose error is
+    /// returned.
+    ///
+    /// [`CustomClaims`]: crate::CustomClaims
+    pub fn claims<T: DeserializeOwned>(&self) -> Result<Option<T>, Error> {
+        match self.id_token {
+            Some(ref id_token) => {
+                let payload = serde_json::to_value(id_token.payload()?)?;
+                Ok(Some(serde_json::from_value(payload)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The id_token in its original compact form, e.g. to persist it in a session or send it as
+    /// `id_token_hint`. Unlike [`Jws::encoded`] it is also available once the id_token is
+    /// decoded, as the bearer keeps it. None if there is no id_token.
+    pub fn encoded_id_token(&self) -> Option<&str> {
+        self.bearer.id_token.as_deref()
+    }
+
+    /// Time until the decoded id_token expires according to its `exp` claim, e.g. to schedule a
+    /// re-authentication. Negative if it is already expired. None if there is no id_token, it
+    /// isn't decoded or has no `exp`.
+    pub fn id_token_expires_in(&self) -> Option<Duration> {
+        self.id_token_expires_in_at(Utc::now())
+    }
+
+    /// Like [`Token::id_token_expires_in`], relative to the given point in time.
+    pub fn id_token_expires_in_at(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let claims = self.id_token.as_ref()?.payload().ok()?;
+        Some(Duration::seconds(claims.exp()? - now.timestamp()))
+    }
+
+    /// Time since the user authenticated according to the `auth_time` claim of the decoded
+    /// id_token, e.g. to show when the user logged in or to require a step-up authentication.
+    /// None if there is no id_token, it isn't decoded or has no `auth_time`.
+    pub fn auth_age(&self) -> Option<Duration> {
+        self.auth_age_at(Utc::now())
+    }
+
+    /// Like [`Token::auth_age`], relative to the given point in time.
+    pub fn auth_age_at(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let claims = self.id_token.as_ref()?.payload().ok()?;
+        Some(Duration::seconds(now.timestamp() - claims.auth_time()?))
+    }
+
+    /// A `login_hint` to prefill the username when the user re-authenticates, e.g. after the
+    /// session expired: the `preferred_username` of the decoded id_token, or its `email`. None if
+    /// there is no id_token, it isn't decoded or has neither claim. Set it with
+    /// [`Options::login_hint_from`](crate::Options::login_hint_from).
+    pub fn login_hint(&self) -> Option<&str> {
+        let userinfo = self.id_token.as_ref()?.payload().ok()?.userinfo();
+        userinfo
+            .preferred_username
+            .as_deref()
+            .or(userinfo.email.as_deref())
+    }
+}
+
 impl<C: CompactJson + Claims> From<Bearer> for Token<C> {
     fn from(bearer: Bearer) -> Self {
         let id_token = bearer
@@ -19,3 +96,189 @@ impl<C: CompactJson + Claims> From<Bearer> for Token<C> {
         Self { bearer, id_token }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use biscuit::{jwa::SignatureAlgorithm, jws::Secret};
+    use chrono::Timelike;
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+
+    #[derive(Deserialize)]
+    struct RoleClaims {
+        sub: String,
+        roles: Vec<String>,
+    }
+
+    /// Claims keeping the non-standard `roles`.
+    #[derive(Deserialize, Serialize)]
+    struct RoleIdClaims {
+        roles: Vec<String>,
+        #[serde(flatten)]
+        standard_claims: StandardClaims,
+    }
+
+    impl crate::CustomClaims for RoleIdClaims {
+        fn standard_claims(&self) -> &StandardClaims {
+            &self.standard_claims
+        }
+    }
+
+    impl CompactJson for RoleIdClaims {}
+
+    fn token() -> Token {
+        token_expiring_at(4102444800)
+    }
+
+    fn token_expiring_at<C: CompactJson + Claims>(exp: i64) -> Token<C> {
+        token_with_claims(json!({
+            "exp": exp,
+            "roles": ["admin", "user"],
+        }))
+    }
+
+    /// Token with the [`testing::claims`], decoded with [`testing::SECRET`].
+    fn token_with_claims<C: CompactJson + Claims>(extra: Value) -> Token<C> {
+        let bearer: Bearer = serde_json::from_value(json!({
+            "access_token": "aaaaaaaa",
+            "id_token": testing::sign(testing::claims(extra), None),
+        }))
+        .unwrap();
+
+        let mut token: Token<C> = bearer.into();
+        let id_token = token.id_token.as_mut().unwrap();
+        let secret = Secret::bytes_from_str(testing::SECRET);
+        *id_token = id_token.decode(&secret, SignatureAlgorithm::HS256).unwrap();
+        token
+    }
+
+    #[test]
+    fn claims_from_decoded_token() {
+        let roles: Token<RoleIdClaims> = token_expiring_at(4102444800);
+        let claims: RoleClaims = roles.claims().unwrap().unwrap();
+        assert_eq!("subject", claims.sub);
+        assert_eq!(vec!["admin", "user"], claims.roles);
+
+        // Standard claims don't carry the roles
+        assert!(token().claims::<RoleClaims>().is_err());
+        let claims: StandardClaims = token().claims().unwrap().unwrap();
+        assert_eq!("subject", claims.sub);
+    }
+
+    #[test]
+    fn claims_require_decoded_token() {
+        let mut token = token();
+        let encoded = token.bearer.id_token.as_deref().unwrap();
+        token.id_token = Some(Jws::new_encoded(encoded));
+        assert!(token.claims::<StandardClaims>().is_err());
+    }
+
+    #[test]
+    fn claims_without_encoded_id_token() {
+        let mut token = token();
+        token.bearer.id_token = None;
+        let claims: StandardClaims = token.claims().unwrap().unwrap();
+        assert_eq!("subject", claims.sub);
+    }
+
+    #[test]
+    fn encoded_id_token_after_decode() {
+        let token = token();
+        assert!(token.id_token.as_ref().unwrap().encoded().is_err());
+
+        let encoded = token.encoded_id_token().unwrap();
+        let mut id_token: IdToken<StandardClaims> = Jws::new_encoded(encoded);
+        id_token = id_token
+            .decode(
+                &Secret::bytes_from_str(testing::SECRET),
+                SignatureAlgorithm::HS256,
+            )
+            .unwrap();
+        assert_eq!("subject", id_token.payload().unwrap().sub);
+    }
+
+    #[test]
+    fn claims_without_id_token() {
+        let token: Token = Bearer {
+            access_token: "aaaaaaaa".to_string(),
+            token_type: None,
+            scope: None,
+            refresh_token: None,
+            expires: None,
+            id_token: None,
+        }
+        .into();
+        assert!(token.claims::<RoleClaims>().unwrap().is_none());
+    }
+
+    #[test]
+    fn id_token_expires_in() {
+        let now = Utc::now();
+        let token: Token = token_expiring_at((now + Duration::minutes(5)).timestamp());
+        assert_eq!(
+            Some(Duration::minutes(5)),
+            token.id_token_expires_in_at(now.with_nanosecond(0).unwrap())
+        );
+        let expires_in = token.id_token_expires_in().unwrap();
+        assert!(expires_in > Duration::minutes(4) && expires_in <= Duration::minutes(5));
+    }
+
+    #[test]
+    fn id_token_expires_in_requires_decoded_token() {
+        let mut token = token();
+        let encoded = token.bearer.id_token.as_deref().unwrap();
+        token.id_token = Some(Jws::new_encoded(encoded));
+        assert_eq!(None, token.id_token_expires_in());
+    }
+
+    #[test]
+    fn auth_age() {
+        let now = Utc::now().with_nanosecond(0).unwrap();
+        let token: Token = token_with_claims(json!({
+            "auth_time": (now - Duration::minutes(3)).timestamp(),
+        }));
+        assert_eq!(Some(Duration::minutes(3)), token.auth_age_at(now));
+        let age = token.auth_age().unwrap();
+        assert!(age >= Duration::minutes(3) && age < Duration::minutes(4));
+    }
+
+    #[test]
+    fn auth_age_without_auth_time() {
+        assert_eq!(None, token().auth_age());
+    }
+
+    #[test]
+    fn login_hint_from_preferred_username() {
+        let token: Token = token_with_claims(json!({
+            "preferred_username": "jdoe",
+            "email": "jdoe@example.com",
+        }));
+        assert_eq!(Some("jdoe"), token.login_hint());
+
+        let mut options = crate::Options::default();
+        options.login_hint_from(&token);
+        assert_eq!(Some("jdoe"), options.login_hint.as_deref());
+    }
+
+    #[test]
+    fn login_hint_from_email() {
+        let token: Token = token_with_claims(json!({
+            "email": "jdoe@example.com",
+        }));
+        assert_eq!(Some("jdoe@example.com"), token.login_hint());
+    }
+
+    #[test]
+    fn login_hint_without_claims() {
+        assert_eq!(None, token().login_hint());
+
+        let mut options = crate::Options {
+            login_hint: Some("kept".to_string()),
+            ..Default::default()
+        };
+        options.login_hint_from(&token());
+        assert_eq!(Some("kept"), options.login_hint.as_deref());
+    }
+}
@@ -0,0 +1,188 @@
+//! Spans and events of the `tracing` feature, and notifications of the [`ClientObserver`].
+//! Without the feature the helpers only run the wrapped operation and notify the observer.
+use crate::{ClientObserver, Operation};
+use std::{fmt::Display, future::Future, time::Instant};
+use url::Url;
+
+/// Runs an outbound HTTP request in an `openid_request` span with the operation and endpoint,
+/// logging its outcome. The observer, if any, is notified of the outcome and duration.
+pub(crate) async fn request<F, T, E>(
+    operation: Operation,
+    endpoint: &Url,
+    observer: Option<&dyn ClientObserver>,
+    future: F,
+) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Display,
+{
+    let start = Instant::now();
+
+    #[cfg(feature = "tracing")]
+    let result = {
+        use tracing::Instrument;
+        let operation = operation.as_str();
+        let span = tracing::debug_span!("openid_request", operation, endpoint = %endpoint);
+        let result = future.instrument(span.clone()).await;
+        span.in_scope(|| outcome(&result));
+        result
+    };
+    #[cfg(not(feature = "tracing"))]
+    let result = {
+        let _ = endpoint;
+        future.await
+    };
+
+    if let Some(observer) = observer {
+        observer.on_operation(operation, start.elapsed(), result.is_ok());
+    }
+    result
+}
+
+/// Like [`request`], for the requests of the blocking client.
+#[cfg(feature = "blocking")]
+pub(crate) fn request_blocking<T, E: Display>(
+    operation: Operation,
+    endpoint: &Url,
+    observer: Option<&dyn ClientObserver>,
+    f: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    let start = Instant::now();
+
+    #[cfg(feature = "tracing")]
+    let result = {
+        let span = tracing::debug_span!(
+            "openid_request",
+            operation = operation.as_str(),
+            endpoint = %endpoint
+        );
+        span.in_scope(|| {
+            let result = f();
+            outcome(&result);
+            result
+        })
+    };
+    #[cfg(not(feature = "tracing"))]
+    let result = {
+        let _ = endpoint;
+        f()
+    };
+
+    if let Some(observer) = observer {
+        observer.on_operation(operation, start.elapsed(), result.is_ok());
+    }
+    result
+}
+
+/// Runs a token validation in an `openid_validation` span, logging its outcome.
+pub(crate) fn validation<T, E: Display>(f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::debug_span!("openid_validation").in_scope(|| {
+            let result = f();
+            outcome(&result);
+            result
+        })
+    }
+    #[cfg(not(feature = "tracing"))]
+    f()
+}
+
+#[cfg(feature = "tracing")]
+fn outcome<T, E: Display>(result: &Result<T, E>) {
+    match result {
+        Ok(_) => tracing::debug!("succeeded"),
+        Err(error) => tracing::debug!(%error, "failed"),
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use crate::{
+        testing::{config_json, MockServer, Response},
+        Config, DiscoveredClient,
+    };
+    use serde_json::json;
+    use std::{
+        fmt,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Metadata, Subscriber,
+    };
+
+    /// Records the spans as `name operation=... endpoint=...` and the event messages.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        next_id: Arc<AtomicU64>,
+        spans: Arc<Mutex<Vec<String>>>,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Fields(span.metadata().name().to_string());
+            span.record(&mut fields);
+            self.spans.lock().unwrap().push(fields.0);
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields(String::new());
+            event.record(&mut fields);
+            self.events.lock().unwrap().push(fields.0);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[tokio::test]
+    async fn token_request_emits_span() {
+        let server = MockServer::start(|_| Response::json(json!({"access_token": "fresh"})));
+        let config: Config = serde_json::from_value(config_json(&server.url("/"))).unwrap();
+        let client = DiscoveredClient::from_config(
+            config,
+            None,
+            String::from("client"),
+            String::from("secret"),
+            None,
+            reqwest::Client::new(),
+        )
+        .unwrap();
+        let recorder = Recorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        client.request_token("code").await.unwrap();
+
+        let spans = recorder.spans.lock().unwrap();
+        let endpoint = server.url("/token");
+        let expected = format!("openid_request operation=\"token\" endpoint={}", endpoint);
+        assert!(spans.contains(&expected), "{:?}", spans);
+        assert!(spans.iter().all(|span| !span.contains("secret")));
+        let events = recorder.events.lock().unwrap();
+        assert!(events.iter().any(|event| event.contains("succeeded")));
+    }
+}
@@ -56,21 +56,19 @@ impl<C: CompactJson + Claims> Client<DiscoveredUma2, C> {
         redirect: Option<String>,
         issuer: Url,
     ) -> Result<Self, Error> {
-        let http_client = reqwest::Client::new();
+        let http_client = crate::client::default_http_client(crate::client::USER_AGENT)?;
         let uma2_config = discover_uma2(&http_client, &issuer).await?;
-        let jwks =
-            crate::discovered::jwks(&http_client, uma2_config.config.jwks_uri.clone()).await?;
+        let jwks = match uma2_config.config.jwks_uri {
+            Some(ref jwks_uri) => {
+                Some(crate::discovered::jwks(&http_client, jwks_uri.clone()).await?)
+            }
+            None => None,
+        };
+        crate::client::check_jwks(jwks.as_ref())?;
 
         let provider = uma2_config.into();
 
-        Ok(Self::new(
-            provider,
-            id,
-            secret,
-            redirect,
-            http_client,
-            Some(jwks),
-        ))
+        Ok(Self::new(provider, id, secret, redirect, http_client, jwks))
     }
 }
 
@@ -121,8 +121,9 @@ where
             .json(&permission)
             .send()
             .await?
-            .json::<Value>()
+            .bytes()
             .await?;
+        let json: Value = serde_json::from_slice(&json)?;
 
         let error: Result<OAuth2Error, _> = serde_json::from_value(json.clone());
 
@@ -211,8 +212,9 @@ where
             .json(&permission)
             .send()
             .await?
-            .json::<Value>()
+            .bytes()
             .await?;
+        let json: Value = serde_json::from_slice(&json)?;
 
         let error: Result<OAuth2Error, _> = serde_json::from_value(json.clone());
 
@@ -259,8 +261,9 @@ where
             .header(AUTHORIZATION, format!("Bearer {:}", token))
             .send()
             .await?
-            .json::<Value>()
+            .bytes()
             .await?;
+        let json: Value = serde_json::from_slice(&json)?;
 
         let error: Result<OAuth2Error, _> = serde_json::from_value(json);
 
@@ -329,8 +332,9 @@ where
             .header(AUTHORIZATION, format!("Bearer {:}", token))
             .send()
             .await?
-            .json::<Value>()
+            .bytes()
             .await?;
+        let json: Value = serde_json::from_slice(&json)?;
 
         let error: Result<OAuth2Error, _> = serde_json::from_value(json.clone());
 
@@ -105,8 +105,9 @@ where
             .json(&body)
             .send()
             .await?
-            .json::<Value>()
+            .bytes()
             .await?;
+        let json: Value = serde_json::from_slice(&json)?;
 
         let error: Result<OAuth2Error, _> = serde_json::from_value(json.clone());
 
@@ -183,8 +184,9 @@ where
             .json(&body)
             .send()
             .await?
-            .json::<Value>()
+            .bytes()
             .await?;
+        let json: Value = serde_json::from_slice(&json)?;
 
         let error: Result<OAuth2Error, _> = serde_json::from_value(json.clone());
 
@@ -226,8 +228,9 @@ where
             .header(AUTHORIZATION, format!("Bearer {:}", pat_token))
             .send()
             .await?
-            .json::<Value>()
+            .bytes()
             .await?;
+        let json: Value = serde_json::from_slice(&json)?;
 
         let error: Result<OAuth2Error, _> = serde_json::from_value(json);
 
@@ -269,8 +272,9 @@ where
             .header(AUTHORIZATION, format!("Bearer {:}", pat_token))
             .send()
             .await?
-            .json::<Value>()
+            .bytes()
             .await?;
+        let json: Value = serde_json::from_slice(&json)?;
 
         let error: Result<OAuth2Error, _> = serde_json::from_value(json.clone());
 
@@ -338,8 +342,9 @@ where
             .header(ACCEPT, "application/json")
             .send()
             .await?
-            .json::<Value>()
+            .bytes()
             .await?;
+        let json: Value = serde_json::from_slice(&json)?;
 
         let error: Result<OAuth2Error, _> = serde_json::from_value(json.clone());
 
@@ -137,8 +137,9 @@ where
             .body(body)
             .send()
             .await?
-            .json::<Value>()
+            .bytes()
             .await?;
+        let json: Value = serde_json::from_slice(&json)?;
 
         let error: Result<OAuth2Error, _> = serde_json::from_value(json.clone());
 
@@ -185,8 +186,9 @@ where
             .json(&requests)
             .send()
             .await?
-            .json::<Value>()
+            .bytes()
             .await?;
+        let json: Value = serde_json::from_slice(&json)?;
 
         let error: Result<OAuth2Error, _> = serde_json::from_value(json.clone());
 
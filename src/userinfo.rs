@@ -1,5 +1,7 @@
-use crate::deserializers::bool_from_str_or_bool;
-use crate::Address;
+use crate::deserializers::{
+    bool_from_str_or_bool, option_bool_from_str_or_bool, option_from_json_str_or_object,
+};
+use crate::{Address, Gender, Locale, StandardClaims};
 use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use url::Url;
@@ -9,7 +11,8 @@ use validator::Validate;
 // TODO is there a way to use claims_supported in config to simplify this struct?
 #[derive(Debug, Deserialize, Serialize, Validate, Clone, Eq, PartialEq)]
 pub struct Userinfo {
-    #[serde(default)]
+    // Skipped if None, as flattened into the claims it would overwrite their sub
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     /// Subject - Identifier for the End-User at the Issuer.
     pub sub: Option<String>,
     #[serde(default)]
@@ -43,9 +46,11 @@ pub struct Userinfo {
     #[validate(email)]
     /// End-User's preferred e-mail address. Its value MUST conform to the RFC 5322 [RFC5322] addr-spec syntax. The RP MUST NOT rely upon this value being unique, as discussed in Section 5.7.
     pub email: Option<String>,
-    #[serde(default, deserialize_with = "bool_from_str_or_bool")]
+    #[serde(default, deserialize_with = "option_bool_from_str_or_bool")]
     /// True if the End-User's e-mail address has been verified; otherwise false. When this Claim Value is true, this means that the OP took affirmative steps to ensure that this e-mail address was controlled by the End-User at the time the verification was performed. The means by which an e-mail address is verified is context-specific, and dependent upon the trust framework or contractual agreements within which the parties are operating.
-    pub email_verified: bool,
+    ///
+    /// None if the claim is absent, see [`Userinfo::email_verified`] for the spec default.
+    pub email_verified: Option<bool>,
     // Isn't required to be just male or female
     #[serde(default)]
     /// End-User's gender. Values defined by this specification are female and male. Other values MAY be used when neither of the defined values are applicable.
@@ -69,10 +74,320 @@ pub struct Userinfo {
     #[serde(default, deserialize_with = "bool_from_str_or_bool")]
     /// True if the End-User's phone number has been verified; otherwise false. When this Claim Value is true, this means that the OP took affirmative steps to ensure that this phone number was controlled by the End-User at the time the verification was performed. The means by which a phone number is verified is context-specific, and dependent upon the trust framework or contractual agreements within which the parties are operating. When true, the phone_number Claim MUST be in E.164 format and any extensions MUST be represented in RFC 3966 format.
     pub phone_number_verified: bool,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "option_from_json_str_or_object")]
     /// End-User's preferred postal address. The value of the address member is a JSON [RFC4627] structure containing some or all of the members defined in Section 5.1.1.
     pub address: Option<Address>,
     #[serde(default)]
     /// Time the End-User's information was last updated. Its value is a JSON number representing the number of seconds from 1970-01-01T0:0:0Z as measured in UTC until the date/time.
     pub updated_at: Option<i64>,
 }
+
+impl Userinfo {
+    /// Whether the e-mail address has been verified, false if the claim is absent.
+    pub fn email_verified(&self) -> bool {
+        self.email_verified.unwrap_or_default()
+    }
+
+    /// Typed End-User's gender.
+    pub fn gender(&self) -> Option<Gender> {
+        self.gender.as_deref().map(Gender::from)
+    }
+
+    /// End-User's locale split into language and region. None if absent or malformed.
+    pub fn locale(&self) -> Option<Locale> {
+        self.locale.as_deref().and_then(Locale::parse)
+    }
+
+    /// End-User's time zone if it looks like a zoneinfo name, e.g. Europe/Paris. The name is only
+    /// format-checked, not looked up in the time zone database.
+    pub fn zoneinfo(&self) -> Option<&str> {
+        let zoneinfo = self.zoneinfo.as_deref()?;
+        let valid = zoneinfo.split('/').all(|part| {
+            part.starts_with(|c: char| c.is_ascii_alphabetic())
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-+".contains(c))
+        });
+        if valid {
+            Some(zoneinfo)
+        } else {
+            None
+        }
+    }
+
+    /// A unified profile of this userinfo and another source, e.g. the claims of the id_token
+    /// converted with `Userinfo::from`. Fields present in both are taken from this
+    /// userinfo, missing ones from the other. The email and phone number are verified if their
+    /// source says so, or if both carry the same one and either says so.
+    pub fn merge(self, other: &Userinfo) -> Userinfo {
+        let (email, email_verified) = merge_verified(
+            (self.email, self.email_verified),
+            (&other.email, other.email_verified),
+        );
+        let (phone_number, phone_number_verified) = merge_verified(
+            (self.phone_number, Some(self.phone_number_verified)),
+            (&other.phone_number, Some(other.phone_number_verified)),
+        );
+        Userinfo {
+            sub: self.sub.or_else(|| other.sub.clone()),
+            name: self.name.or_else(|| other.name.clone()),
+            given_name: self.given_name.or_else(|| other.given_name.clone()),
+            family_name: self.family_name.or_else(|| other.family_name.clone()),
+            middle_name: self.middle_name.or_else(|| other.middle_name.clone()),
+            nickname: self.nickname.or_else(|| other.nickname.clone()),
+            preferred_username: self
+                .preferred_username
+                .or_else(|| other.preferred_username.clone()),
+            profile: self.profile.or_else(|| other.profile.clone()),
+            picture: self.picture.or_else(|| other.picture.clone()),
+            website: self.website.or_else(|| other.website.clone()),
+            email,
+            email_verified,
+            gender: self.gender.or_else(|| other.gender.clone()),
+            birthdate: self.birthdate.or(other.birthdate),
+            zoneinfo: self.zoneinfo.or_else(|| other.zoneinfo.clone()),
+            locale: self.locale.or_else(|| other.locale.clone()),
+            phone_number,
+            phone_number_verified: phone_number_verified.unwrap_or_default(),
+            address: self.address.or_else(|| other.address.clone()),
+            updated_at: self.updated_at.or(other.updated_at),
+        }
+    }
+}
+
+/// Merges a value with its verified flag, taking both from the same source, so a flag never
+/// describes the value of the other source. The flags are combined if both carry the same value.
+fn merge_verified<T: Clone + PartialEq>(
+    (value, verified): (Option<T>, Option<bool>),
+    (other, other_verified): (&Option<T>, Option<bool>),
+) -> (Option<T>, Option<bool>) {
+    match value {
+        Some(value) if other.as_ref() == Some(&value) => {
+            let verified = match (verified, other_verified) {
+                (Some(verified), Some(other_verified)) => Some(verified || other_verified),
+                (verified, other_verified) => verified.or(other_verified),
+            };
+            (Some(value), verified)
+        }
+        Some(value) => (Some(value), verified),
+        None if other.is_some() => (other.clone(), other_verified),
+        None => (None, verified),
+    }
+}
+
+/// The userinfo claims of an id_token, with its subject.
+impl From<&StandardClaims> for Userinfo {
+    fn from(claims: &StandardClaims) -> Self {
+        Userinfo {
+            sub: Some(claims.sub.clone()),
+            ..claims.userinfo.clone()
+        }
+    }
+}
+
+/// Userinfo of [`crate::Client::request_userinfo_cached`] with its ETag, to request it again
+/// only if it changed, e.g. kept per session.
+#[derive(Debug, Clone, Default)]
+pub struct UserinfoCache {
+    pub(crate) etag: Option<String>,
+    pub(crate) userinfo: Option<Userinfo>,
+}
+
+impl UserinfoCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The ETag of the cached userinfo, if the provider sent one.
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    /// The userinfo of the last successful request.
+    pub fn userinfo(&self) -> Option<&Userinfo> {
+        self.userinfo.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn userinfo(gender: &str, locale: &str, zoneinfo: &str) -> Userinfo {
+        serde_json::from_value(json!({
+            "gender": gender,
+            "locale": locale,
+            "zoneinfo": zoneinfo,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let userinfo = userinfo("female", "en_US", "America/Los_Angeles");
+        assert_eq!(Some(Gender::Female), userinfo.gender());
+        let locale = userinfo.locale().unwrap();
+        assert_eq!("en", locale.language);
+        assert_eq!(Some("US"), locale.region.as_deref());
+        assert_eq!(Some("America/Los_Angeles"), userinfo.zoneinfo());
+        // Raw fields stay untouched
+        assert_eq!(Some("en_US"), userinfo.locale.as_deref());
+    }
+
+    #[test]
+    fn email_verified() {
+        let userinfo: Userinfo = serde_json::from_value(json!({"email_verified": true})).unwrap();
+        assert_eq!(Some(true), userinfo.email_verified);
+        assert!(userinfo.email_verified());
+
+        let userinfo: Userinfo =
+            serde_json::from_value(json!({"email_verified": "false"})).unwrap();
+        assert_eq!(Some(false), userinfo.email_verified);
+        assert!(!userinfo.email_verified());
+
+        for absent in [json!({}), json!({"email_verified": null})] {
+            let userinfo: Userinfo = serde_json::from_value(absent).unwrap();
+            assert_eq!(None, userinfo.email_verified);
+            assert!(!userinfo.email_verified());
+        }
+        assert!(serde_json::from_value::<Userinfo>(json!({"email_verified": "yes"})).is_err());
+    }
+
+    #[test]
+    fn compare_documents() {
+        let document = json!({
+            "sub": "subject",
+            "email": "user@example.com",
+            "email_verified": true,
+            "address": {"locality": "Berlin", "country": "Germany"},
+        });
+        let first: Userinfo = serde_json::from_value(document.clone()).unwrap();
+        let second: Userinfo = serde_json::from_value(document).unwrap();
+        assert_eq!(first, second);
+
+        let mut changed = first.clone();
+        assert_eq!(first, changed);
+        changed.address.as_mut().unwrap().locality = Some("Hamburg".into());
+        assert_ne!(first, changed);
+    }
+
+    #[test]
+    fn merge_with_id_token_claims() {
+        let claims: StandardClaims = serde_json::from_value(json!({
+            "iss": "https://example.com",
+            "sub": "subject",
+            "aud": "client",
+            "exp": 0,
+            "iat": 0,
+            "name": "Token Name",
+            "email": "token@example.com",
+            "email_verified": true,
+            "preferred_username": "jdoe",
+        }))
+        .unwrap();
+        let from_token = Userinfo::from(&claims);
+        assert_eq!(Some("subject"), from_token.sub.as_deref());
+        assert_eq!(Some("Token Name"), from_token.name.as_deref());
+
+        let userinfo: Userinfo = serde_json::from_value(json!({
+            "sub": "subject",
+            "name": "Userinfo Name",
+            "email": "userinfo@example.com",
+            "address": {"locality": "Berlin"},
+        }))
+        .unwrap();
+        let merged = userinfo.merge(&from_token);
+
+        assert_eq!(Some("subject"), merged.sub.as_deref());
+        // Present in both, taken from the userinfo
+        assert_eq!(Some("Userinfo Name"), merged.name.as_deref());
+        assert_eq!(Some("userinfo@example.com"), merged.email.as_deref());
+        // Only in the id_token
+        assert_eq!(Some("jdoe"), merged.preferred_username.as_deref());
+        // Only in the userinfo
+        assert_eq!(Some("Berlin"), merged.address.unwrap().locality.as_deref());
+        assert_eq!(None, merged.phone_number);
+    }
+
+    #[test]
+    fn merge_verified_with_its_value() {
+        let from_token: Userinfo = serde_json::from_value(json!({
+            "email": "token@example.com",
+            "email_verified": true,
+            "phone_number": "+49 30 1234",
+            "phone_number_verified": true,
+        }))
+        .unwrap();
+
+        // The id_token verified other ones than those of the userinfo
+        let userinfo: Userinfo = serde_json::from_value(json!({
+            "email": "userinfo@example.com",
+            "phone_number": "+49 40 5678",
+        }))
+        .unwrap();
+        let merged = userinfo.merge(&from_token);
+        assert_eq!(Some("userinfo@example.com"), merged.email.as_deref());
+        assert_eq!(None, merged.email_verified);
+        assert_eq!(Some("+49 40 5678"), merged.phone_number.as_deref());
+        assert!(!merged.phone_number_verified);
+
+        // The same ones
+        let userinfo: Userinfo = serde_json::from_value(json!({
+            "email": "token@example.com",
+            "email_verified": false,
+            "phone_number": "+49 30 1234",
+        }))
+        .unwrap();
+        let merged = userinfo.merge(&from_token);
+        assert_eq!(Some(true), merged.email_verified);
+        assert!(merged.phone_number_verified);
+
+        // Only in the id_token, with its flags
+        let userinfo: Userinfo = serde_json::from_value(json!({})).unwrap();
+        let merged = userinfo.merge(&from_token);
+        assert_eq!(Some("token@example.com"), merged.email.as_deref());
+        assert_eq!(Some(true), merged.email_verified);
+        assert!(merged.phone_number_verified);
+    }
+
+    #[test]
+    fn address_object() {
+        let userinfo: Userinfo = serde_json::from_value(json!({
+            "address": {"locality": "Berlin", "country": "Germany"}
+        }))
+        .unwrap();
+        let address = userinfo.address.unwrap();
+        assert_eq!(Some("Berlin"), address.locality.as_deref());
+        assert_eq!(Some("Germany"), address.country.as_deref());
+    }
+
+    #[test]
+    fn address_string() {
+        let userinfo: Userinfo = serde_json::from_value(json!({
+            "address": r#"{"locality": "Berlin", "country": "Germany"}"#
+        }))
+        .unwrap();
+        let address = userinfo.address.unwrap();
+        assert_eq!(Some("Berlin"), address.locality.as_deref());
+        assert_eq!(Some("Germany"), address.country.as_deref());
+    }
+
+    #[test]
+    fn address_absent_or_invalid() {
+        let userinfo: Userinfo = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(None, userinfo.address);
+        let userinfo: Userinfo = serde_json::from_value(json!({"address": null})).unwrap();
+        assert_eq!(None, userinfo.address);
+        assert!(serde_json::from_value::<Userinfo>(json!({"address": "Berlin"})).is_err());
+    }
+
+    #[test]
+    fn typed_accessors_other_and_invalid() {
+        let userinfo = userinfo("non-binary", "not a locale", "Europe Paris");
+        assert_eq!(Some(Gender::Other("non-binary".into())), userinfo.gender());
+        assert_eq!(None, userinfo.locale());
+        assert_eq!(None, userinfo.zoneinfo());
+        assert_eq!(Some("not a locale"), userinfo.locale.as_deref());
+    }
+}
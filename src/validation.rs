@@ -2,11 +2,96 @@ use crate::{
     error::{Error, Expiry, Mismatch, Missing, Validation},
     Claims, Config,
 };
-use biscuit::SingleOrMultiple;
+use biscuit::{jwa::SignatureAlgorithm, SingleOrMultiple};
 use chrono::{Duration, Utc};
+use ring::digest;
+use serde_json::Value;
+use std::{collections::BTreeSet, fmt};
+use url::Url;
+
+/// A non-fatal finding of [`lint_token`] about an id_token that passes validation, e.g. to
+/// diagnose provider quirks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TokenWarning {
+    /// No `auth_time`, so a later max_age check or step-up authentication can't rely on it.
+    MissingAuthTime,
+    /// The `azp` is redundant as it equals the only audience.
+    RedundantAuthorizedParty,
+    /// The `iat` is later than now, hinting at clock skew between client and provider.
+    IssuedInFuture(Duration),
+    /// The `acr` is not listed in the `acr_values_supported` of the provider.
+    UnsupportedAcr(String),
+}
+
+impl fmt::Display for TokenWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenWarning::MissingAuthTime => write!(f, "Token missing Auth Time"),
+            TokenWarning::RedundantAuthorizedParty => {
+                write!(f, "Token AZP is redundant with its single Audience")
+            }
+            TokenWarning::IssuedInFuture(ahead) => {
+                write!(f, "Token issued {}s in the future", ahead.num_seconds())
+            }
+            TokenWarning::UnsupportedAcr(acr) => {
+                write!(f, "Token acr is not supported by the provider: {}", acr)
+            }
+        }
+    }
+}
+
+/// Lints the claims of an id_token for findings not failing its validation. Without max_age,
+/// the missing `auth_time` is reported, as validation only requires it with max_age.
+pub fn lint_token<C: Claims>(
+    claims: &C,
+    config: &Config,
+    max_age: Option<&Duration>,
+) -> Vec<TokenWarning> {
+    let mut warnings = Vec::new();
+
+    if max_age.is_none() && claims.auth_time().is_none() {
+        warnings.push(TokenWarning::MissingAuthTime);
+    }
+
+    if let Some(azp) = claims.azp() {
+        let single = match claims.aud() {
+            SingleOrMultiple::Single(aud) => Some(aud),
+            SingleOrMultiple::Multiple(auds) => {
+                auds.iter().all(|aud| aud == &auds[0]).then(|| &auds[0])
+            }
+        };
+        if single == Some(azp) {
+            warnings.push(TokenWarning::RedundantAuthorizedParty);
+        }
+    }
+
+    let ahead = claims.iat() - Utc::now().timestamp();
+    if ahead > 0 {
+        warnings.push(TokenWarning::IssuedInFuture(Duration::seconds(ahead)));
+    }
+
+    if let Some(acr) = claims.acr() {
+        if !config.supports_acr_value(acr) {
+            warnings.push(TokenWarning::UnsupportedAcr(acr.clone()));
+        }
+    }
+
+    warnings
+}
 
 pub fn validate_token_issuer<C: Claims>(claims: &C, config: &Config) -> Result<(), Error> {
-    if claims.iss() != &config.issuer {
+    validate_token_issuer_in(claims, config, &[])
+}
+
+/// Like [`validate_token_issuer`], also accepting tokens of the additional issuers, e.g. of the
+/// old provider while migrating to a new one.
+pub fn validate_token_issuer_in<C: Claims>(
+    claims: &C,
+    config: &Config,
+    additional: &[Url],
+) -> Result<(), Error> {
+    if claims.iss() != &config.issuer && !additional.contains(claims.iss()) {
         let expected = config.issuer.as_str().to_string();
         let actual = claims.iss().as_str().to_string();
         return Err(Validation::Mismatch(Mismatch::Issuer { expected, actual }).into());
@@ -37,13 +122,93 @@ pub fn validate_token_nonce<C: Claims>(claims: &C, nonce: Option<&str>) -> Resul
     Ok(())
 }
 
+/// The `sub` is required in id_tokens. It can't be absent from [`crate::StandardClaims`], but
+/// may still be empty.
+pub fn validate_token_subject<C: Claims>(claims: &C) -> Result<(), Error> {
+    if claims.sub().is_empty() {
+        return Err(Validation::Missing(Missing::Subject).into());
+    }
+    Ok(())
+}
+
+/// Checks an id_token of a refresh response against the previous id_token. Its `iss`, `sub`,
+/// `aud` and `azp` must be the same, the audiences in any order, and if it has an `auth_time`,
+/// it must be the one of the original authentication. It may only carry the nonce of the
+/// previous one, as providers leave the nonce out of refreshed id_tokens.
+///
+/// See [OpenID Connect Core, section 12.2](https://openid.net/specs/openid-connect-core-1_0.html#RefreshTokenResponse).
+pub fn validate_refreshed_token<C: Claims>(previous: &C, refreshed: &C) -> Result<(), Error> {
+    let claims = [
+        (
+            "iss",
+            previous.iss().as_str().to_string(),
+            refreshed.iss().as_str().to_string(),
+        ),
+        (
+            "sub",
+            previous.sub().to_string(),
+            refreshed.sub().to_string(),
+        ),
+        ("aud", audiences(previous), audiences(refreshed)),
+        (
+            "azp",
+            previous.azp().cloned().unwrap_or_default(),
+            refreshed.azp().cloned().unwrap_or_default(),
+        ),
+        (
+            "auth_time",
+            auth_time(previous),
+            // Refreshed tokens may leave out the auth_time
+            match refreshed.auth_time() {
+                Some(_) => auth_time(refreshed),
+                None => auth_time(previous),
+            },
+        ),
+    ];
+    for (claim, expected, actual) in claims {
+        if expected != actual {
+            let mismatch = Mismatch::RefreshedToken {
+                claim,
+                expected,
+                actual,
+            };
+            return Err(Validation::Mismatch(mismatch).into());
+        }
+    }
+
+    if let Some(actual) = refreshed.nonce() {
+        if previous.nonce() != Some(actual) {
+            let expected = previous.nonce().cloned().unwrap_or_default();
+            let actual = actual.clone();
+            return Err(Validation::Mismatch(Mismatch::Nonce { expected, actual }).into());
+        }
+    }
+    Ok(())
+}
+
+/// The audiences of the claims sorted and space separated, so their order and a single audience
+/// sent as array don't matter.
+fn audiences<C: Claims>(claims: &C) -> String {
+    let audiences: BTreeSet<&str> = claims.aud().iter().map(String::as_str).collect();
+    audiences.into_iter().collect::<Vec<_>>().join(" ")
+}
+
+fn auth_time<C: Claims>(claims: &C) -> String {
+    claims
+        .auth_time()
+        .map(|t| t.to_string())
+        .unwrap_or_default()
+}
+
 pub fn validate_token_aud<C: Claims>(claims: &C, client_id: &str) -> Result<(), Error> {
     if !claims.aud().contains(client_id) {
         return Err(Validation::Missing(Missing::Audience).into());
     }
     // By spec, if there are multiple auds, we must have an azp
-    if let SingleOrMultiple::Multiple(_) = claims.aud() {
-        if claims.azp().is_none() {
+    if let SingleOrMultiple::Multiple(auds) = claims.aud() {
+        // An array with a single (possibly repeated) audience is not multiple audiences
+        let multiple = auds.iter().any(|aud| aud != &auds[0]);
+        if multiple && claims.azp().is_none() {
             return Err(Validation::Missing(Missing::AuthorizedParty).into());
         }
     }
@@ -62,14 +227,27 @@ pub fn validate_token_aud<C: Claims>(claims: &C, client_id: &str) -> Result<(),
 }
 
 pub fn validate_token_exp<C: Claims>(claims: &C, max_age: Option<&Duration>) -> Result<(), Error> {
+    validate_token_exp_with_leeway(claims, max_age, &Duration::zero())
+}
+
+/// Like [`validate_token_exp`], accepting tokens expired no longer than `leeway` ago to allow for
+/// clock skew between client and provider. The leeway also extends the max_age.
+pub fn validate_token_exp_with_leeway<C: Claims>(
+    claims: &C,
+    max_age: Option<&Duration>,
+    leeway: &Duration,
+) -> Result<(), Error> {
     let now = Utc::now();
     // Now should never be less than the time this code was written!
     if now.timestamp() < 1504758600 {
         panic!("chrono::Utc::now() can never be before this was written!")
     }
-    if claims.exp() <= now.timestamp() {
+    let exp = claims.exp().ok_or(Validation::Missing(Missing::Expiry))?;
+    if exp + leeway.num_seconds() <= now.timestamp() {
         return Err(Validation::Expired(Expiry::Expires(
-            chrono::naive::NaiveDateTime::from_timestamp(claims.exp(), 0),
+            chrono::DateTime::from_timestamp(exp, 0)
+                .unwrap_or_default()
+                .naive_utc(),
         ))
         .into());
     }
@@ -78,7 +256,7 @@ pub fn validate_token_exp<C: Claims>(claims: &C, max_age: Option<&Duration>) ->
         match claims.auth_time() {
             Some(time) => {
                 let age = chrono::Duration::seconds(now.timestamp() - time);
-                if age >= *max {
+                if age >= *max + *leeway {
                     return Err(Validation::Expired(Expiry::MaxAge(age)).into());
                 }
             }
@@ -88,3 +266,278 @@ pub fn validate_token_exp<C: Claims>(claims: &C, max_age: Option<&Duration>) ->
 
     Ok(())
 }
+
+/// Checks the `at_hash` claim against the access token, hashed with the hash algorithm of the
+/// token signature algorithm.
+///
+/// See [spec 3.2.2.9](https://openid.net/specs/openid-connect-core-1_0.html#ImplicitTokenValidation)
+pub fn validate_token_at_hash<C: Claims>(
+    claims: &C,
+    alg: SignatureAlgorithm,
+    access_token: &str,
+) -> Result<(), Error> {
+    use SignatureAlgorithm::*;
+    let expected = claims
+        .at_hash_to_vec()
+        .ok_or(Validation::Missing(Missing::AccessTokenHash))?;
+    let algorithm = match alg {
+        HS256 | RS256 | ES256 | PS256 => &digest::SHA256,
+        HS384 | RS384 | ES384 | PS384 => &digest::SHA384,
+        HS512 | RS512 | ES512 | PS512 => &digest::SHA512,
+        None => return Err(Validation::Mismatch(Mismatch::AccessTokenHash).into()),
+    };
+    let hash = digest::digest(algorithm, access_token.as_bytes());
+    let actual = &hash.as_ref()[..hash.as_ref().len() / 2];
+    if expected != actual {
+        return Err(Validation::Mismatch(Mismatch::AccessTokenHash).into());
+    }
+
+    Ok(())
+}
+
+/// Checks the state of a response against the state sent with the authorization request.
+pub fn validate_state(expected: &str, actual: Option<&str>) -> Result<(), Error> {
+    match actual {
+        Some(actual) if actual == expected => Ok(()),
+        Some(actual) => {
+            let expected = expected.to_string();
+            let actual = actual.to_string();
+            Err(Validation::Mismatch(Mismatch::State { expected, actual }).into())
+        }
+        None => Err(Validation::Missing(Missing::State).into()),
+    }
+}
+
+/// Checks the `cnf.x5t#S256` claim of a certificate-bound access token against the thumbprint
+/// of the client certificate presented to the resource server.
+///
+/// See [RFC 8705, section 3](https://www.rfc-editor.org/rfc/rfc8705#section-3).
+pub fn validate_token_certificate_binding(claims: &Value, thumbprint: &str) -> Result<(), Error> {
+    let actual = claims
+        .get("cnf")
+        .and_then(|cnf| cnf.get("x5t#S256"))
+        .and_then(Value::as_str)
+        .ok_or(Validation::Missing(Missing::CertificateThumbprint))?;
+    if actual != thumbprint {
+        let expected = thumbprint.to_string();
+        let actual = actual.to_string();
+        return Err(
+            Validation::Mismatch(Mismatch::CertificateThumbprint { expected, actual }).into(),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::standard_claims;
+    use serde_json::json;
+
+    #[test]
+    fn lint_sparse_token() {
+        let config: Config = serde_json::from_value(json!({
+            "issuer": "https://example.com",
+            "authorization_endpoint": "https://example.com/auth",
+            "token_endpoint": "https://example.com/token",
+            "jwks_uri": "https://example.com/jwks",
+            "response_types_supported": ["code"],
+            "acr_values_supported": ["mfa"],
+        }))
+        .unwrap();
+        let mut claims = standard_claims(json!({"aud": ["client"], "azp": "client"}));
+        claims.acr = Some(String::from("pwd"));
+
+        assert_eq!(
+            vec![
+                TokenWarning::MissingAuthTime,
+                TokenWarning::RedundantAuthorizedParty,
+                TokenWarning::UnsupportedAcr(String::from("pwd")),
+            ],
+            lint_token(&claims, &config, None)
+        );
+        // With max_age validation already requires the auth_time
+        assert!(!lint_token(&claims, &config, Some(&Duration::minutes(5)))
+            .contains(&TokenWarning::MissingAuthTime));
+
+        let claims = standard_claims(json!({
+            "aud": ["client", "other"],
+            "azp": "client",
+            "auth_time": 0,
+        }));
+        assert!(lint_token(&claims, &config, None).is_empty());
+    }
+
+    #[test]
+    fn aud_single_element_array_without_azp() {
+        let claims = standard_claims(json!({"aud": ["client"]}));
+        assert!(validate_token_aud(&claims, "client").is_ok());
+    }
+
+    #[test]
+    fn aud_multiple_without_azp() {
+        let claims = standard_claims(json!({"aud": ["client", "other"]}));
+        assert!(matches!(
+            validate_token_aud(&claims, "client"),
+            Err(Error::Validation(Validation::Missing(
+                Missing::AuthorizedParty
+            )))
+        ));
+    }
+
+    #[test]
+    fn aud_multiple_with_azp() {
+        let claims = standard_claims(json!({"aud": ["client", "other"], "azp": "client"}));
+        assert!(validate_token_aud(&claims, "client").is_ok());
+    }
+
+    #[test]
+    fn certificate_binding_matches() {
+        let claims = json!({"cnf": {"x5t#S256": "bwcK0esc3ACC3DB2Y5_lESsXE8o9ltc05O89jdN-dg2"}});
+        assert!(validate_token_certificate_binding(
+            &claims,
+            "bwcK0esc3ACC3DB2Y5_lESsXE8o9ltc05O89jdN-dg2"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn certificate_binding_mismatch() {
+        let claims = json!({"cnf": {"x5t#S256": "bwcK0esc3ACC3DB2Y5_lESsXE8o9ltc05O89jdN-dg2"}});
+        assert!(matches!(
+            validate_token_certificate_binding(&claims, "other"),
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::CertificateThumbprint { .. }
+            )))
+        ));
+    }
+
+    #[test]
+    fn certificate_binding_missing() {
+        let claims = json!({"sub": "subject"});
+        assert!(matches!(
+            validate_token_certificate_binding(&claims, "other"),
+            Err(Error::Validation(Validation::Missing(
+                Missing::CertificateThumbprint
+            )))
+        ));
+    }
+
+    #[test]
+    fn at_hash_matches() {
+        // Example from OpenID Connect Core, appendix A.3
+        let claims = standard_claims(json!({"at_hash": "77QmUPtjPfzWtF2AnpK9RQ"}));
+        let access_token = "jHkWEdUXMU1BwAsC4vtUsZwnNvTIxEl0z9K3vx5KF0Y";
+        assert!(validate_token_at_hash(&claims, SignatureAlgorithm::RS256, access_token).is_ok());
+    }
+
+    #[test]
+    fn at_hash_mismatch() {
+        let claims = standard_claims(json!({"at_hash": "77QmUPtjPfzWtF2AnpK9RQ"}));
+        assert!(matches!(
+            validate_token_at_hash(&claims, SignatureAlgorithm::RS256, "other"),
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::AccessTokenHash
+            )))
+        ));
+    }
+
+    #[test]
+    fn state_mismatch() {
+        assert!(validate_state("state", Some("state")).is_ok());
+        assert!(matches!(
+            validate_state("state", Some("other")),
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::State { .. }
+            )))
+        ));
+        assert!(matches!(
+            validate_state("state", None),
+            Err(Error::Validation(Validation::Missing(Missing::State)))
+        ));
+    }
+
+    #[test]
+    fn exp_within_leeway() {
+        let claims = standard_claims(json!({"exp": Utc::now().timestamp() - 10}));
+        assert!(validate_token_exp(&claims, None).is_err());
+        assert!(validate_token_exp_with_leeway(&claims, None, &Duration::seconds(30)).is_ok());
+    }
+
+    #[test]
+    fn exp_missing() {
+        let claims = standard_claims(json!({"exp": null}));
+        assert_eq!(None, claims.exp());
+        assert!(matches!(
+            validate_token_exp(&claims, None),
+            Err(Error::Validation(Validation::Missing(Missing::Expiry)))
+        ));
+    }
+
+    #[test]
+    fn exp_beyond_leeway() {
+        let claims = standard_claims(json!({"exp": Utc::now().timestamp() - 60}));
+        assert!(matches!(
+            validate_token_exp_with_leeway(&claims, None, &Duration::seconds(30)),
+            Err(Error::Validation(Validation::Expired(Expiry::Expires(_))))
+        ));
+    }
+
+    #[test]
+    fn max_age_within_leeway() {
+        let max_age = Duration::minutes(5);
+        let claims = standard_claims(json!({"auth_time": Utc::now().timestamp() - 302}));
+        assert!(validate_token_exp(&claims, Some(&max_age)).is_err());
+        let leeway = Duration::seconds(30);
+        assert!(validate_token_exp_with_leeway(&claims, Some(&max_age), &leeway).is_ok());
+    }
+
+    #[test]
+    fn max_age_beyond_leeway() {
+        let max_age = Duration::minutes(5);
+        let claims = standard_claims(json!({"auth_time": Utc::now().timestamp() - 340}));
+        let leeway = Duration::seconds(30);
+        assert!(matches!(
+            validate_token_exp_with_leeway(&claims, Some(&max_age), &leeway),
+            Err(Error::Validation(Validation::Expired(Expiry::MaxAge(_))))
+        ));
+    }
+
+    #[test]
+    fn refreshed_token_with_reordered_audiences() {
+        let previous = standard_claims(json!({"aud": ["client", "other"], "azp": "client"}));
+        let refreshed = standard_claims(json!({"aud": ["other", "client"], "azp": "client"}));
+        validate_refreshed_token(&previous, &refreshed).unwrap();
+
+        let previous = standard_claims(json!({}));
+        let refreshed = standard_claims(json!({"aud": ["client"]}));
+        validate_refreshed_token(&previous, &refreshed).unwrap();
+
+        let refreshed = standard_claims(json!({"aud": ["client", "other"]}));
+        assert!(matches!(
+            validate_refreshed_token(&previous, &refreshed),
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::RefreshedToken { claim: "aud", .. }
+            )))
+        ));
+    }
+
+    #[test]
+    fn refreshed_token_keeps_auth_time() {
+        let previous = standard_claims(json!({"auth_time": 100}));
+        validate_refreshed_token(&previous, &standard_claims(json!({"auth_time": 100}))).unwrap();
+        // Refreshed tokens may leave it out
+        validate_refreshed_token(&previous, &standard_claims(json!({}))).unwrap();
+
+        assert!(matches!(
+            validate_refreshed_token(&previous, &standard_claims(json!({"auth_time": 200}))),
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::RefreshedToken {
+                    claim: "auth_time",
+                    ..
+                }
+            )))
+        ));
+    }
+}
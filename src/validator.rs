@@ -0,0 +1,183 @@
+use crate::{error::Error, Claims, Client, Configurable, IdToken, Provider};
+use biscuit::CompactJson;
+use chrono::Duration;
+
+/// Decodes and validates many tokens against the key set of a client, e.g. in a gateway.
+///
+/// The secrets of the current keys are prepared when the validator is created and shared with
+/// the client, so they are not built again for every token. Like [`Client::decode_token`] it
+/// uses the key set fetched by [`Client::decode_token_refreshing`], also if fetched after the
+/// validator was created. Create it with [`Client::validator`].
+pub struct Validator<'a, P, C: CompactJson + Claims> {
+    client: &'a Client<P, C>,
+}
+
+impl<C: CompactJson + Claims, P: Provider + Configurable> Client<P, C> {
+    /// A validator borrowing the client, see [`Validator`].
+    pub fn validator(&self) -> Validator<'_, P, C> {
+        self.prepare_key_secrets();
+        Validator { client: self }
+    }
+}
+
+impl<C: CompactJson + Claims, P: Provider + Configurable> Validator<'_, P, C> {
+    /// Like [`Client::decode_token`], with the errors of it.
+    pub fn decode(&self, token: &mut IdToken<C>) -> Result<(), Error> {
+        self.client.decode_token(token)
+    }
+
+    /// Decodes the token with [`Validator::decode`] and validates it with
+    /// [`Client::validate_token`].
+    pub fn decode_and_validate(
+        &self,
+        token: &mut IdToken<C>,
+        nonce: Option<&str>,
+        max_age: Option<&Duration>,
+    ) -> Result<(), Error> {
+        self.decode(token)?;
+        self.client.validate_token(token, nonce, max_age)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::{Decode, Jose, Mismatch, Validation},
+        pem, testing, DiscoveredClient,
+    };
+    use biscuit::errors::ValidationError;
+    use serde_json::json;
+    use url::Url;
+
+    /// Client with an HMAC key `key` and an RSA key `rsa`.
+    fn client() -> DiscoveredClient {
+        let mut jwks = testing::jwks("key");
+        let pem = format!(
+            "{}/src/testing/rsa_public_key.pem",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        jwks.keys
+            .extend(pem::jwks_from_pem(pem, Some("rsa")).unwrap().keys);
+
+        let base = Url::parse("https://example.com/").unwrap();
+        let config: crate::Config = serde_json::from_value(testing::config_json(&base)).unwrap();
+        DiscoveredClient::from_config(
+            config,
+            Some(jwks),
+            String::from("client"),
+            String::from("secret"),
+            None,
+            reqwest::Client::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn validates_many_tokens() {
+        let client = client();
+        let validator = client.validator();
+        let tokens: Vec<_> = (0..1000)
+            .map(|i| {
+                let mut claims = testing::claims_json("https://example.com", "client");
+                claims["nonce"] = json!(i.to_string());
+                if i % 10 == 0 {
+                    testing::sign_rs256(claims, Some("rsa"))
+                } else {
+                    testing::sign(claims, Some("key"))
+                }
+            })
+            .collect();
+
+        for (i, encoded) in tokens.iter().enumerate() {
+            let mut token: IdToken<crate::StandardClaims> = IdToken::new_encoded(encoded);
+            validator
+                .decode_and_validate(&mut token, Some(&i.to_string()), None)
+                .unwrap();
+            assert_eq!(Some(i.to_string()), token.payload().unwrap().nonce);
+
+            // The same as decoding with the client
+            let mut token = IdToken::new_encoded(encoded);
+            client.decode_token(&mut token).unwrap();
+            client
+                .validate_token(&token, Some(&i.to_string()), None)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_tokens() {
+        let client = client();
+        let validator = client.validator();
+        let mut claims = testing::claims_json("https://example.com", "client");
+        claims["nonce"] = json!("nonce");
+
+        let mut token: IdToken<crate::StandardClaims> =
+            IdToken::new_encoded(&testing::sign(claims.clone(), Some("key")));
+        assert!(matches!(
+            validator.decode_and_validate(&mut token, Some("other"), None),
+            Err(Error::Validation(Validation::Mismatch(
+                Mismatch::Nonce { .. }
+            )))
+        ));
+
+        let mut tampered = testing::sign(claims.clone(), Some("key"));
+        tampered.push('A');
+        let mut token: IdToken<crate::StandardClaims> = IdToken::new_encoded(&tampered);
+        assert!(matches!(
+            validator.decode(&mut token),
+            Err(Error::Jose(Jose::ValidationError(
+                ValidationError::InvalidSignature
+            )))
+        ));
+
+        let mut token: IdToken<crate::StandardClaims> =
+            IdToken::new_encoded(&testing::sign(claims.clone(), Some("unknown")));
+        assert!(matches!(
+            validator.decode(&mut token),
+            Err(Error::Decode(Decode::MissingKey(_)))
+        ));
+
+        // An HMAC token can't be verified with the RSA key
+        let mut token: IdToken<crate::StandardClaims> =
+            IdToken::new_encoded(&testing::sign(claims, Some("rsa")));
+        assert!(matches!(
+            validator.decode(&mut token),
+            Err(Error::Jose(Jose::WrongKeyType { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn uses_refreshed_key_set() {
+        let server = testing::MockServer::start(|request| match request.path.as_str() {
+            "/jwks" => testing::Response::json(serde_json::to_value(testing::jwks("new")).unwrap()),
+            _ => testing::Response::with_status(404, json!({})),
+        });
+        let config = testing::config_json(&server.url("/"));
+        let config: crate::Config = serde_json::from_value(config).unwrap();
+        let issuer = config.issuer.to_string();
+        let client = DiscoveredClient::from_config(
+            config,
+            Some(testing::jwks("old")),
+            String::from("client"),
+            String::from("secret"),
+            None,
+            reqwest::Client::new(),
+        )
+        .unwrap();
+        let validator = client.validator();
+        let encoded = testing::sign(testing::claims_json(&issuer, "client"), Some("new"));
+
+        let mut token: IdToken<crate::StandardClaims> = IdToken::new_encoded(&encoded);
+        assert!(matches!(
+            validator.decode(&mut token),
+            Err(Error::Decode(Decode::MissingKey(_)))
+        ));
+        client.decode_token_refreshing(&mut token).await.unwrap();
+
+        let mut token: IdToken<crate::StandardClaims> = IdToken::new_encoded(&encoded);
+        validator
+            .decode_and_validate(&mut token, None, None)
+            .unwrap();
+    }
+}